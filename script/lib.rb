@@ -1,6 +1,7 @@
 OPERAND_TYPE = {
   '$i8' => 'i8',
   '$u8' => 'u8',
+  '$u16' => 'u16',
   '$i32' => 'i32',
   '$u32' => 'u32',
   '$u64' => 'u64',
@@ -17,16 +18,15 @@ def load_instructions
 end
 
 def identifier(instruction)
-  segments = instruction.split.map do |part|
-    part = part.chomp(',')
-
+  # Memory operands may contain spaces, as in [rel $label]
+  segments = instruction.scan(/\[[^\]]*\]?|[^\s,]+/).map do |part|
     if part == '$label'
       nil
     elsif part[0] == '$'
       part[(1...part.length)]
     elsif part[0] == '['
       assert(part[-1] == ']', "missing ] in #{instruction}")
-      'ptr_' + part[(1...-1)].gsub('+', '_plus_')
+      'ptr_' + part[(1...-1)].sub(/\s*\$label/, '').delete('$').gsub('+', '_plus_').gsub('*', '_times_')
     else
       part.downcase
     end
@@ -35,7 +35,9 @@ def identifier(instruction)
   segments.compact.join('_')
 end
 
-def operand_type(instruction)
-  match = /\$([a-z0-9]+)/.match(instruction)
-  match && OPERAND_TYPE.fetch(match[0])
+# Each operand in order of appearance; operands within brackets are memory displacements
+def operands(instruction)
+  instruction.scan(/(\[[^\]]*)?(\$[a-z0-9]+)/).map do |bracket, variable|
+    { variable: variable, type: OPERAND_TYPE.fetch(variable), displacement: !bracket.nil? }
+  end
 end
@@ -1,71 +1,282 @@
+use std::fmt;
+use std::fmt::{Display, Formatter};
 use std::io;
 
+use crate::stream::Span;
+
 pub trait Assembler<'a> {
     type Address: 'a + Copy;
     type Label: 'a + Copy;
 
-    fn allocate_memory(&mut self, size: u64) -> Self::Address;
+    fn allocate_memory(&mut self, size: u64) -> Result<Self::Address, AllocationError>;
     fn allocate_label(&mut self) -> Self::Label;
 
     fn label(&mut self, label: Self::Label);
+    // Embed raw bytes (e.g. a message to print) in the code; execution must never reach them
+    fn data(&mut self, bytes: &[u8]);
+    // Note that the code which follows was generated from the command(s) at the given span of the source, for
+    // assemblers which record debugging information or annotate their output
+    fn source_span(&mut self, _span: Span) {}
+    // Name a label, or a region of allocated memory, for assemblers which produce a symbol table; a label's symbol
+    // is only emitted if the label is placed
+    fn name_label(&mut self, _label: Self::Label, _name: &str) {}
+    fn name_memory(&mut self, _address: Self::Address, _size: u64, _name: &str) {}
     fn assemble<W: io::Write>(self, output: &mut W) -> Result<(), io::Error>;
 
+    fn add_byte_ptr_rbx_plus_r8_plus_i32_u8(&mut self, displacement: i32, operand: u8);
+    fn add_byte_ptr_rbx_plus_r8_plus_i32_al(&mut self, displacement: i32);
+    fn add_byte_ptr_rbx_plus_r8_plus_i32_cl(&mut self, displacement: i32);
     fn add_byte_ptr_rbx_plus_r8_u8(&mut self, operand: u8);
+    fn add_byte_ptr_rbx_plus_r8_al(&mut self);
+    fn add_byte_ptr_rbx_plus_r8_cl(&mut self);
+    fn add_dl_u8(&mut self, operand: u8);
+    fn add_dword_ptr_rbx_plus_r8_times_4_plus_i32_u32(&mut self, displacement: i32, operand: u32);
+    fn add_dword_ptr_rbx_plus_r8_times_4_plus_i32_eax(&mut self, displacement: i32);
+    fn add_dword_ptr_rbx_plus_r8_times_4_plus_i32_ecx(&mut self, displacement: i32);
+    fn add_dword_ptr_rbx_plus_r8_times_4_u32(&mut self, operand: u32);
+    fn add_dword_ptr_rbx_plus_r8_times_4_eax(&mut self);
+    fn add_dword_ptr_rbx_plus_r8_times_4_ecx(&mut self);
+    fn add_qword_ptr_rbx_plus_r8_times_8_i32(&mut self, operand: i32);
+    fn add_qword_ptr_rbx_plus_r8_times_8_plus_i32_i32(&mut self, displacement: i32, operand: i32);
+    fn add_qword_ptr_rbx_plus_r8_times_8_plus_i32_r15(&mut self, displacement: i32);
+    fn add_qword_ptr_rbx_plus_r8_times_8_plus_i32_rax(&mut self, displacement: i32);
+    fn add_qword_ptr_rbx_plus_r8_times_8_plus_i32_rcx(&mut self, displacement: i32);
+    fn add_qword_ptr_rbx_plus_r8_times_8_r15(&mut self);
+    fn add_qword_ptr_rbx_plus_r8_times_8_rax(&mut self);
+    fn add_qword_ptr_rbx_plus_r8_times_8_rcx(&mut self);
+    fn add_r11_i8(&mut self, operand: i8);
+    fn add_r15_i32(&mut self, operand: i32);
+    fn add_r15_i8(&mut self, operand: i8);
     fn add_r15_rax(&mut self);
     fn add_r8_i32(&mut self, operand: i32);
     fn add_r8_i8(&mut self, operand: i8);
+    fn add_r8_r15(&mut self);
     fn add_r8_r9(&mut self);
+    fn add_r8_rax(&mut self);
+    fn add_rdx_r9(&mut self);
+    fn add_rsi_i8(&mut self, operand: i8);
     fn add_rsi_r15(&mut self);
+    fn add_word_ptr_rbx_plus_r8_times_2_plus_i32_u16(&mut self, displacement: i32, operand: u16);
+    fn add_word_ptr_rbx_plus_r8_times_2_plus_i32_ax(&mut self, displacement: i32);
+    fn add_word_ptr_rbx_plus_r8_times_2_plus_i32_cx(&mut self, displacement: i32);
+    fn add_word_ptr_rbx_plus_r8_times_2_u16(&mut self, operand: u16);
+    fn add_word_ptr_rbx_plus_r8_times_2_ax(&mut self);
+    fn add_word_ptr_rbx_plus_r8_times_2_cx(&mut self);
+    fn and_eax_u32(&mut self, operand: u32);
+    fn bsf_eax_eax(&mut self);
+    fn bsr_eax_eax(&mut self);
     fn cmovae_r8_r15(&mut self);
     fn cmp_byte_ptr_rbx_plus_r8_u8(&mut self, operand: u8);
+    fn cmp_dword_ptr_rbx_plus_r8_times_4_u32(&mut self, operand: u32);
+    fn cmp_qword_ptr_rbx_plus_r8_times_8_i32(&mut self, operand: i32);
     fn cmp_r10_r11(&mut self);
     fn cmp_r10_r12(&mut self);
+    fn cmp_r11_r9(&mut self);
     fn cmp_r13_u32(&mut self, operand: u32);
     fn cmp_r13_rbp(&mut self);
     fn cmp_r15b_u8(&mut self, operand: u8);
     fn cmp_r15_r13(&mut self);
+    fn cmp_r15_r9(&mut self);
+    fn cmp_r8_i32(&mut self, operand: i32);
+    fn cmp_r8_i8(&mut self, operand: i8);
+    fn cmp_r8_r9(&mut self);
     fn cmp_rax_u32(&mut self, operand: u32);
+    fn cmp_rbp_r11(&mut self);
+    fn cmp_rbp_r8(&mut self);
+    fn cmp_word_ptr_rbx_plus_r8_times_2_u16(&mut self, operand: u16);
     fn dec_byte_ptr_rbx_plus_r8(&mut self);
+    fn dec_byte_ptr_rbx_plus_r8_plus_i32(&mut self, displacement: i32);
+    fn dec_dword_ptr_rbx_plus_r8_times_4(&mut self);
+    fn dec_dword_ptr_rbx_plus_r8_times_4_plus_i32(&mut self, displacement: i32);
+    fn dec_qword_ptr_rbx_plus_r8_times_8(&mut self);
+    fn dec_qword_ptr_rbx_plus_r8_times_8_plus_i32(&mut self, displacement: i32);
+    fn dec_r11(&mut self);
+    fn dec_rsi(&mut self);
+    fn dec_word_ptr_rbx_plus_r8_times_2(&mut self);
+    fn dec_word_ptr_rbx_plus_r8_times_2_plus_i32(&mut self, displacement: i32);
+    fn div_rcx(&mut self);
+    fn imul_ecx_eax_i32(&mut self, operand: i32);
+    fn imul_rcx_rax(&mut self);
     fn inc_byte_ptr_rbx_plus_r8(&mut self);
+    fn inc_byte_ptr_rbx_plus_r8_plus_i32(&mut self, displacement: i32);
+    fn inc_dword_ptr_rbx_plus_r8_times_4(&mut self);
+    fn inc_dword_ptr_rbx_plus_r8_times_4_plus_i32(&mut self, displacement: i32);
+    fn inc_qword_ptr_rbx_plus_r8_times_8(&mut self);
+    fn inc_qword_ptr_rbx_plus_r8_times_8_plus_i32(&mut self, displacement: i32);
     fn inc_r10(&mut self);
     fn inc_r13(&mut self);
+    fn inc_r15(&mut self);
+    fn inc_rbp(&mut self);
+    fn inc_rdi(&mut self);
+    fn inc_word_ptr_rbx_plus_r8_times_2(&mut self);
+    fn inc_word_ptr_rbx_plus_r8_times_2_plus_i32(&mut self, displacement: i32);
+    fn jae(&mut self, label: Self::Label);
+    fn jb(&mut self, label: Self::Label);
     fn je(&mut self, label: Self::Label);
     fn jg(&mut self, label: Self::Label);
     fn jge(&mut self, label: Self::Label);
     fn jmp(&mut self, label: Self::Label);
+    fn jmp_r15(&mut self);
     fn jne(&mut self, label: Self::Label);
     fn jns(&mut self, label: Self::Label);
+    fn js(&mut self, label: Self::Label);
+    fn lea_r15_ptr_rel(&mut self, label: Self::Label);
+    fn lea_rbp_ptr_rel(&mut self, label: Self::Label);
+    fn lea_rsi_ptr_rbp_plus_i8(&mut self, displacement: i8);
+    fn mov_byte_ptr_rbx_plus_r8_plus_i32_u8(&mut self, displacement: i32, operand: u8);
+    fn mov_byte_ptr_rbx_plus_r8_u8(&mut self, operand: u8);
     fn mov_byte_ptr_rbx_plus_r8_r15b(&mut self);
+    fn mov_byte_ptr_rdi_u8(&mut self, operand: u8);
+    fn mov_byte_ptr_rsi_dl(&mut self);
     fn mov_byte_ptr_rsp_plus_r13_r15b(&mut self);
+    fn movdqu_xmm1_ptr_rbx_plus_r15(&mut self);
+    fn movdqu_xmm1_ptr_rbx_plus_r8(&mut self);
+    fn mov_dword_ptr_rbx_plus_r8_times_4_plus_i32_u32(&mut self, displacement: i32, operand: u32);
+    fn mov_dword_ptr_rbx_plus_r8_times_4_u32(&mut self, operand: u32);
+    fn mov_dword_ptr_rbx_plus_r8_times_4_r15d(&mut self);
+    fn mov_eax_dword_ptr_rbx_plus_r8_times_4(&mut self);
+    fn mov_eax_dword_ptr_rbx_plus_r8_times_4_plus_i32(&mut self, displacement: i32);
+    fn mov_eax_dword_ptr_rbx_plus_rbp_times_4(&mut self);
+    fn mov_qword_ptr_r15_rsp(&mut self);
+    fn mov_qword_ptr_rax_plus_i8_rdx(&mut self, displacement: i8);
+    fn mov_qword_ptr_rax_r15(&mut self);
+    fn mov_qword_ptr_rax_rbx(&mut self);
+    fn mov_qword_ptr_rbx_plus_r8_times_8_i32(&mut self, operand: i32);
+    fn mov_qword_ptr_rbx_plus_r8_times_8_plus_i32_i32(&mut self, displacement: i32, operand: i32);
+    fn mov_qword_ptr_rbx_plus_r8_times_8_r15(&mut self);
+    fn mov_r10_u32(&mut self, operand: u32);
+    fn mov_r10_rbp(&mut self);
+    fn mov_r11_r8(&mut self);
+    fn mov_r11_r9(&mut self);
     fn mov_r11_rax(&mut self);
     fn mov_r12_u64(&mut self, operand: u64);
     fn mov_r12_rax(&mut self);
     fn mov_r13_u32(&mut self, operand: u32);
     fn mov_r14_addr(&mut self, addr: Self::Address);
+    fn mov_r15_addr(&mut self, addr: Self::Address);
+    fn mov_r15_u64(&mut self, operand: u64);
     fn mov_r15b_byte_ptr_r14_plus_r10(&mut self);
     fn mov_r15b_byte_ptr_rbx_plus_r8(&mut self);
+    fn mov_r15b_byte_ptr_rbx_plus_r8_times_2(&mut self);
+    fn mov_r15b_byte_ptr_rbx_plus_r8_times_4(&mut self);
+    fn mov_r15b_byte_ptr_rbx_plus_r8_times_8(&mut self);
+    fn mov_r15_qword_ptr_rax(&mut self);
     fn mov_r15_r8(&mut self);
+    fn mov_r8_u64(&mut self, operand: u64);
+    fn mov_r8_r15(&mut self);
     fn mov_r9_u64(&mut self, operand: u64);
+    fn mov_r9_rax(&mut self);
+    fn mov_r9_rdx(&mut self);
+    fn mov_rax_addr(&mut self, addr: Self::Address);
     fn mov_rax_u32(&mut self, operand: u32);
+    fn mov_rax_qword_ptr_rbx_plus_r8_times_8(&mut self);
+    fn mov_rax_qword_ptr_rbx_plus_r8_times_8_plus_i32(&mut self, displacement: i32);
+    fn mov_rax_qword_ptr_rbx_plus_rbp_times_8(&mut self);
+    fn mov_rax_r11(&mut self);
+    fn mov_rax_r12(&mut self);
+    fn mov_rax_r8(&mut self);
+    fn mov_rax_r9(&mut self);
+    fn mov_rax_rbp(&mut self);
+    fn mov_rax_rdi(&mut self);
     fn mov_rbp_u64(&mut self, operand: u64);
+    fn mov_rbp_r10(&mut self);
+    fn mov_rbp_r8(&mut self);
     fn mov_rbx_addr(&mut self, addr: Self::Address);
+    fn mov_rbx_rax(&mut self);
+    fn mov_rcx_u32(&mut self, operand: u32);
+    fn mov_rcx_u64(&mut self, operand: u64);
+    fn mov_rcx_qword_ptr_rbp(&mut self);
+    fn mov_rcx_r15(&mut self);
+    fn mov_rdi_addr(&mut self, addr: Self::Address);
     fn mov_rdi_u32(&mut self, operand: u32);
+    fn mov_rdi_qword_ptr_rax(&mut self);
+    fn mov_rdi_rbx(&mut self);
     fn mov_rdx_u32(&mut self, operand: u32);
     fn mov_rdx_r12(&mut self);
     fn mov_rdx_r13(&mut self);
+    fn mov_rdx_r8(&mut self);
+    fn mov_rdx_rdi(&mut self);
+    fn mov_rdx_rsi(&mut self);
+    fn mov_rsi_addr(&mut self, addr: Self::Address);
+    fn mov_rsi_u64(&mut self, operand: u64);
+    fn mov_rsi_qword_ptr_rax_plus_i8(&mut self, displacement: i8);
     fn mov_rsi_r14(&mut self);
+    fn mov_rsi_r9(&mut self);
+    fn mov_rsi_rbp(&mut self);
     fn mov_rsi_rsp(&mut self);
     fn mov_rsp_addr(&mut self, addr: Self::Address);
+    fn mov_rsp_qword_ptr_r15(&mut self);
+    fn mov_word_ptr_rbx_plus_r8_times_2_plus_i32_u16(&mut self, displacement: i32, operand: u16);
+    fn mov_word_ptr_rbx_plus_r8_times_2_u16(&mut self, operand: u16);
+    fn mov_word_ptr_rbx_plus_r8_times_2_r15w(&mut self);
+    fn movzx_eax_byte_ptr_rbx_plus_r8(&mut self);
+    fn movzx_eax_byte_ptr_rbx_plus_r8_plus_i32(&mut self, displacement: i32);
+    fn movzx_eax_byte_ptr_rbx_plus_rbp(&mut self);
+    fn movzx_eax_word_ptr_rbx_plus_r8_times_2(&mut self);
+    fn movzx_eax_word_ptr_rbx_plus_r8_times_2_plus_i32(&mut self, displacement: i32);
+    fn movzx_eax_word_ptr_rbx_plus_rbp_times_2(&mut self);
+    fn movzx_r15d_byte_ptr_r14_plus_r10(&mut self);
+    fn pcmpeqb_xmm1_xmm0(&mut self);
+    fn pmovmskb_eax_xmm1(&mut self);
+    fn pop_r12(&mut self);
+    fn pop_r13(&mut self);
+    fn pop_r14(&mut self);
+    fn pop_r15(&mut self);
+    fn pop_rbp(&mut self);
+    fn pop_rbx(&mut self);
+    fn push_r12(&mut self);
+    fn push_r13(&mut self);
+    fn push_r14(&mut self);
+    fn push_r15(&mut self);
+    fn push_rbp(&mut self);
+    fn push_rbx(&mut self);
+    fn pxor_xmm0_xmm0(&mut self);
+    fn rep_movsb(&mut self);
+    fn ret(&mut self);
+    fn shl_rdx_u8(&mut self, operand: u8);
+    fn shl_rsi_u8(&mut self, operand: u8);
+    fn shr_rdx_u8(&mut self, operand: u8);
+    fn sub_byte_ptr_rbx_plus_r8_plus_i32_al(&mut self, displacement: i32);
+    fn sub_byte_ptr_rbx_plus_r8_al(&mut self);
+    fn sub_dword_ptr_rbx_plus_r8_times_4_plus_i32_eax(&mut self, displacement: i32);
+    fn sub_dword_ptr_rbx_plus_r8_times_4_eax(&mut self);
+    fn sub_qword_ptr_rbx_plus_r8_times_8_plus_i32_rax(&mut self, displacement: i32);
+    fn sub_qword_ptr_rbx_plus_r8_times_8_rax(&mut self);
     fn sub_r15_r9(&mut self);
     fn sub_r8_r9(&mut self);
+    fn sub_rbp_i8(&mut self, operand: i8);
     fn sub_rdx_r15(&mut self);
+    fn sub_rdx_rsi(&mut self);
+    fn sub_word_ptr_rbx_plus_r8_times_2_plus_i32_ax(&mut self, displacement: i32);
+    fn sub_word_ptr_rbx_plus_r8_times_2_ax(&mut self);
     fn syscall(&mut self);
+    fn test_rax_rax(&mut self);
     fn xor_r10_r10(&mut self);
     fn xor_r11_r11(&mut self);
     fn xor_r12_r12(&mut self);
     fn xor_r13_r13(&mut self);
     fn xor_r15_r15(&mut self);
     fn xor_r8_r8(&mut self);
+    fn xor_r9_r9(&mut self);
     fn xor_rax_rax(&mut self);
+    fn xor_rbp_rbp(&mut self);
     fn xor_rdi_rdi(&mut self);
+    fn xor_rdx_rdx(&mut self);
+}
+
+// Returned by allocate_memory when there isn't enough address space left for the requested allocation
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct AllocationError {
+    pub size: u64,
+    pub available: u64,
+}
+
+impl Display for AllocationError {
+    fn fmt(&self, formatter: &mut Formatter) -> fmt::Result {
+        write!(
+            formatter,
+            "cannot allocate {} bytes of memory; only {} bytes are available",
+            self.size, self.available
+        )
+    }
 }
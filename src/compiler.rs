@@ -1,10 +1,17 @@
+use std::fmt;
+use std::fmt::{Debug, Display, Formatter};
 use std::io;
 
-use crate::assembler::Assembler;
+use crate::assembler::{AllocationError, Assembler};
 use crate::elf_assembler::ElfAssembler;
-use crate::parser::Token::*;
-use crate::parser::{parse, ParseError};
-use crate::stream::Stream;
+use crate::ir;
+use crate::ir::{Node, Op, DEBUG_RADIUS};
+use crate::jit_assembler::{JitAssembler, JitProgram};
+use crate::listing_assembler::ListingAssembler;
+use crate::optimizer;
+use crate::options::{Boundary, CellWidth, Eof, Options, OptionsError};
+use crate::parser::ParseError;
+use crate::stream::{Position, Stream};
 
 /*
     We allocate registers as follows:
@@ -17,214 +24,1094 @@ use crate::stream::Stream;
     - r12: Total number of bytes in the input buffer
     - r13: Current position within the output buffer
     - r15: Scratch space
+
+    With function linkage, the callee-saved registers are pushed onto the caller's stack
+    and the caller's rsp is saved in memory, from which it's restored on exit.
+
+    When the program stops because of an error, rbp and r12 are repurposed to hold a
+    pointer to and the length of the message to be printed.
+
+    The shared routines take their return address in r15, since rsp isn't a stack
+    pointer. The routine flushing the output buffer only clobbers registers we don't
+    allocate. The routine growing the tape preserves rax for its caller; rbp holds r10
+    while it's needed as a system call argument, and r9 holds rax. The routine dumping
+    the tape for `#` takes a pointer to its message in rbp, and otherwise only clobbers
+    registers we don't allocate.
 */
 
-const TAPE_LENGTH: u64 = 30000;
-const INPUT_BUFFER_SIZE: u64 = 16;
-const OUTPUT_BUFFER_SIZE: u64 = 16;
+// Exit codes of the compiled program, which the interpreter's callers use too
+pub const EXIT_WRITE_ERROR: u32 = 1;
+pub const EXIT_READ_ERROR: u32 = 2;
+pub const EXIT_OUT_OF_BOUNDS: u32 = 3;
+pub const EXIT_OUT_OF_MEMORY: u32 = 4;
+
+// The boundary logic assumes that adding a shift to r8 can't overflow, which holds for any tape shorter than this
+// (we compare it against the size in bytes, which is at least the length in cells); it also means that the tape
+// length and any shift modulo it fit in an i64
+const MAX_TAPE_LENGTH: u64 = 1 << 62;
+
+// The size of the buffer in which a tape dump is formatted; a dump holds at most 2 * DEBUG_RADIUS + 1 cells, and
+// five other numbers, each at most 20 digits long
+const DUMP_BUFFER_SIZE: u64 = 1024;
+
+// The most digits a 64-bit number can have
+const MAX_DIGITS: u64 = 20;
+
+pub fn compile<W: io::Write, R: io::Read>(
+    output: &mut W,
+    mut stream: Stream<R>,
+    options: &Options,
+) -> Result<(), CompileError> {
+    options.validate()?;
+    let program = optimizer::optimize(ir::build(&mut stream, options.debug_command)?, &options.passes);
+
+    let mut asm = match &options.debug_info {
+        Some(source) => ElfAssembler::new().with_debug_info(source),
+        None => ElfAssembler::new(),
+    };
+
+    generate(&mut asm, &program, options, Linkage::Executable)?;
+    asm.check_code_size()?;
+    asm.assemble(output).map_err(CompileError::Write)?;
+
+    Ok(())
+}
+
+// Write an assembly listing of the code an executable compiled from the source would contain
+pub fn listing<W: io::Write>(output: &mut W, source: &[u8], options: &Options) -> Result<(), CompileError> {
+    options.validate()?;
+    let mut stream = Stream::with_tab_width(source, options.tab_width);
+    let program = optimizer::optimize(ir::build(&mut stream, options.debug_command)?, &options.passes);
+
+    let mut asm = ListingAssembler::new(source);
+    generate(&mut asm, &program, options, Linkage::Executable)?;
+    asm.assemble(output).map_err(CompileError::Write)?;
+
+    Ok(())
+}
+
+// Compile a program into memory, to be run within the current process
+pub fn jit<R: io::Read>(mut stream: Stream<R>, options: &Options) -> Result<JitProgram, CompileError> {
+    options.validate()?;
+    let program = optimizer::optimize(ir::build(&mut stream, options.debug_command)?, &options.passes);
+
+    let mut asm = JitAssembler::new();
+    generate(&mut asm, &program, options, Linkage::Function)?;
+    let program = asm.finish().map_err(CompileError::Map)?;
+
+    Ok(program)
+}
+
+// How the generated code is entered and left
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Linkage {
+    // As the entry point of a process, which exits via sys_exit
+    Executable,
+    // As a function following the System V calling convention, taking no arguments and returning the exit code
+    Function,
+}
+
+// State shared by the functions generating code for a single program
+struct Context<'o, L> {
+    options: &'o Options,
+    linkage: Linkage,
+    // Stubs reporting that the tape pointer moved off the tape, with the message each prints; these are emitted
+    // after the rest of the program
+    boundary_errors: Vec<(L, String)>,
+    // The messages identifying each `#`, which are likewise emitted after the rest of the program
+    debug_messages: Vec<(L, String)>,
+    // Shared routines, which are likewise emitted after the rest of the program, and only if they're needed
+    flush: L,
+    out_of_bounds: L,
+    out_of_memory: L,
+    grow_tape: L,
+    dump_tape: L,
+    // With function linkage, the routine returning to the caller
+    exit: L,
+}
+
+impl<'o, L: Copy> Context<'o, L> {
+    // Allocate a stub reporting that the move at the given position went past the given end of the tape
+    fn boundary_error<'a, A: Assembler<'a, Label = L>>(&mut self, asm: &mut A, position: Position, end: &str) -> L {
+        let label = asm.allocate_label();
+        let message = format!(
+            "error: tape pointer moved past the {} end of the tape at line {}, column {}\n",
+            end, position.line, position.column
+        );
+
+        asm.name_label(label, &format!("out_of_bounds_L{}_C{}", position.line, position.column));
+        self.boundary_errors.push((label, message));
+        label
+    }
+}
+
+// Generate the code for a program, whose options must be valid
+pub fn generate<'a, A: Assembler<'a>>(
+    asm: &mut A,
+    program: &[Node],
+    options: &Options,
+    linkage: Linkage,
+) -> Result<(), AllocationError> {
+    // With function linkage, the caller's stack pointer is saved here while the program uses rsp
+    let saved_rsp = match linkage {
+        Linkage::Executable => None,
+        Linkage::Function => Some(allocate_named(asm, 8, "saved_rsp")?),
+    };
+
+    // A growable tape is mapped by the program itself, so with function linkage its address and size in bytes are
+    // kept here, to unmap it before returning
+    let tape_mapping = match (linkage, options.boundary) {
+        (Linkage::Function, Boundary::Grow) => Some(allocate_named(asm, 16, "tape_mapping")?),
+        _ => None,
+    };
+
+    // The flush routine's return address is saved here, since it uses r15 as a counter
+    let flush_return_address = allocate_named(asm, 8, "flush_return_address")?;
 
-pub fn compile<W: io::Write, R: io::Read>(output: &mut W, mut stream: Stream<R>) -> Result<(), ParseError> {
-    let mut asm = ElfAssembler::new();
+    // With any `#` commands, memory for the tape dump routine: a slot for its return address, since it has to flush
+    // the output buffer (which clobbers r15), a buffer to format a number in, and one to format the whole dump in
+    let dump_memory = if contains_debug(program) {
+        Some((
+            allocate_named(asm, 8, "dump_return_address")?,
+            allocate_named(asm, MAX_DIGITS, "dump_digits")?,
+            allocate_named(asm, DUMP_BUFFER_SIZE, "dump_buffer")?,
+        ))
+    } else {
+        None
+    };
+
+    // The tape is allocated last, so that if it's too long the error reports its size rather than a buffer's. A
+    // growable tape is mapped at runtime instead, so that it can be remapped as it grows
+    let input_buffer = allocate_named(asm, options.input_buffer_size, "input_buffer")?;
+    let output_buffer = allocate_named(asm, options.output_buffer_size, "output_buffer")?;
+
+    // Tapes too long to address are rejected below, so it doesn't matter if the size saturates
+    let tape_size = options.tape_length.saturating_mul(options.cell_width.bytes());
+
+    let tape = match options.boundary {
+        Boundary::Wrap | Boundary::Abort => Some(allocate_named(asm, tape_size, "tape")?),
+        Boundary::Grow => None,
+    };
+
+    if tape_size >= MAX_TAPE_LENGTH {
+        return Err(AllocationError {
+            size: tape_size,
+            available: MAX_TAPE_LENGTH - 1,
+        });
+    }
 
-    let tape = asm.allocate_memory(TAPE_LENGTH);
-    let input_buffer = asm.allocate_memory(INPUT_BUFFER_SIZE);
-    let output_buffer = asm.allocate_memory(OUTPUT_BUFFER_SIZE);
+    let mut context = Context {
+        options,
+        linkage,
+        boundary_errors: vec![],
+        debug_messages: vec![],
+        flush: asm.allocate_label(),
+        out_of_bounds: asm.allocate_label(),
+        out_of_memory: asm.allocate_label(),
+        grow_tape: asm.allocate_label(),
+        dump_tape: asm.allocate_label(),
+        exit: asm.allocate_label(),
+    };
+
+    asm.name_label(context.flush, "flush");
+    asm.name_label(context.out_of_bounds, "out_of_bounds");
+    asm.name_label(context.out_of_memory, "out_of_memory");
+    asm.name_label(context.grow_tape, "grow_tape");
+    asm.name_label(context.dump_tape, "dump_tape");
+    asm.name_label(context.exit, "return");
+
+    // Preserve the callee-saved registers, which the program is free to clobber
+    if let Some(saved_rsp) = saved_rsp {
+        asm.push_rbx();
+        asm.push_rbp();
+        asm.push_r12();
+        asm.push_r13();
+        asm.push_r14();
+        asm.push_r15();
+        asm.mov_r15_addr(saved_rsp);
+        asm.mov_qword_ptr_r15_rsp();
+    }
 
-    asm.mov_rbx_addr(tape);
     asm.mov_r14_addr(input_buffer);
     asm.mov_rsp_addr(output_buffer);
-    asm.xor_r8_r8();
-    asm.mov_r9_u64(TAPE_LENGTH);
-    asm.xor_r10_r10();
     asm.xor_r12_r12();
     asm.xor_r13_r13();
 
-    let mut loop_stack = vec![];
+    match tape {
+        Some(tape) => asm.mov_rbx_addr(tape),
+        None => emit_map_tape(asm, &context, tape_size, tape_mapping),
+    }
+
+    asm.xor_r8_r8();
+    asm.mov_r9_u64(options.tape_length);
+    asm.xor_r10_r10();
 
-    loop {
-        let (token, line, column) = parse(&mut stream)?;
+    emit_nodes(asm, &mut context, program);
 
-        match token {
-            Move(shift) => {
-                // FIXME: clean up casting mess
-                let wrapped_shift = shift % (TAPE_LENGTH as i64);
-                assert!(-(TAPE_LENGTH as i64) < wrapped_shift && wrapped_shift < (TAPE_LENGTH as i64));
+    // The program's end is only labelled for the symbol table, so that the final flush and exit can be told apart
+    // from the last command
+    let exit = asm.allocate_label();
+    asm.name_label(exit, "exit");
+    asm.label(exit);
 
-                // If the shift would bring us back to the same cell, it's a no-op
-                if wrapped_shift == 0 {
-                    continue;
-                }
+    // Flush any remaining output
+    {
+        let skip_flush = asm.allocate_label();
+        asm.cmp_r13_u32(0);
+        asm.je(skip_flush);
+        emit_flush(asm, &context);
+        asm.label(skip_flush);
+    }
+
+    emit_exit(asm, &context, 0);
+
+    emit_flush_routine(asm, &context, flush_return_address);
+    emit_error_routines(asm, &context, tape_mapping);
+
+    if let Some((return_address, digits, buffer)) = dump_memory {
+        emit_dump_routine(asm, &context, return_address, digits, buffer);
+    }
+
+    if let Some(saved_rsp) = saved_rsp {
+        // The exit code is in rdi
+        asm.label(context.exit);
+        asm.mov_rax_rdi();
+
+        // r12 is restored below, so it can hold the exit code while the tape is unmapped
+        if let Some(tape_mapping) = tape_mapping {
+            asm.mov_r12_rax();
+            asm.mov_rax_addr(tape_mapping);
+            asm.mov_rdi_qword_ptr_rax();
+            asm.mov_rsi_qword_ptr_rax_plus_i8(8);
+            asm.mov_rax_u32(0x0b); // sys_munmap
+            asm.syscall();
+            asm.mov_rax_r12();
+        }
+
+        asm.mov_r15_addr(saved_rsp);
+        asm.mov_rsp_qword_ptr_r15();
+        asm.pop_r15();
+        asm.pop_r14();
+        asm.pop_r13();
+        asm.pop_r12();
+        asm.pop_rbp();
+        asm.pop_rbx();
+        asm.ret();
+    }
+
+    Ok(())
+}
+
+pub enum CompileError {
+    Parse(ParseError),
+    Allocation(AllocationError),
+    Options(OptionsError),
+    // The executable or listing couldn't be written
+    Write(io::Error),
+    // The compiled code couldn't be mapped into memory to be run
+    Map(io::Error),
+}
+
+impl Display for CompileError {
+    fn fmt(&self, formatter: &mut Formatter) -> fmt::Result {
+        match self {
+            CompileError::Parse(error) => Display::fmt(error, formatter),
+            CompileError::Allocation(error) => Display::fmt(error, formatter),
+            CompileError::Options(error) => Display::fmt(error, formatter),
+            CompileError::Write(error) => write!(formatter, "error writing output: {}", error),
+            CompileError::Map(error) => write!(formatter, "cannot map program into memory: {}", error),
+        }
+    }
+}
+
+impl Debug for CompileError {
+    fn fmt(&self, formatter: &mut Formatter) -> fmt::Result {
+        match self {
+            CompileError::Parse(error) => Debug::fmt(error, formatter),
+            CompileError::Allocation(error) => Debug::fmt(error, formatter),
+            CompileError::Options(error) => Debug::fmt(error, formatter),
+            CompileError::Write(error) => Debug::fmt(error, formatter),
+            CompileError::Map(error) => Debug::fmt(error, formatter),
+        }
+    }
+}
+
+impl From<ParseError> for CompileError {
+    fn from(error: ParseError) -> Self {
+        CompileError::Parse(error)
+    }
+}
+
+impl From<AllocationError> for CompileError {
+    fn from(error: AllocationError) -> Self {
+        CompileError::Allocation(error)
+    }
+}
+
+impl From<OptionsError> for CompileError {
+    fn from(error: OptionsError) -> Self {
+        CompileError::Options(error)
+    }
+}
+
+// Allocate memory, giving it a name for the symbol table
+fn allocate_named<'a, A: Assembler<'a>>(asm: &mut A, size: u64, name: &str) -> Result<A::Address, AllocationError> {
+    let address = asm.allocate_memory(size)?;
+    asm.name_memory(address, size, name);
+    Ok(address)
+}
+
+fn emit_nodes<'a, A: Assembler<'a>>(asm: &mut A, context: &mut Context<'_, A::Label>, nodes: &[Node]) {
+    for node in nodes {
+        emit_node(asm, context, node);
+    }
+}
+
+fn emit_node<'a, A: Assembler<'a>>(asm: &mut A, context: &mut Context<'_, A::Label>, node: &Node) {
+    let position = node.span.start;
+    let width = context.options.cell_width;
+
+    // A loop's code is split between its `[` and its `]`
+    match &node.op {
+        Op::Loop(_) => asm.source_span(node.span.first_character()),
+        _ => asm.source_span(node.span),
+    }
+
+    match &node.op {
+        Op::Move(shift) => emit_move(asm, context, *shift, position),
+        Op::Add(value) => emit_add(asm, width, 0, *value),
+        Op::Read => emit_read(asm, context),
+        Op::Write => emit_write(asm, context),
+        Op::Clear => emit_set(asm, width, 0, 0),
+        Op::MultiplyAdd(terms) => emit_multiply_add(asm, context, terms, position),
+        Op::Scan(stride) => emit_scan(asm, context, *stride, position),
+        Op::Block(ops, shift) => emit_block(asm, context, ops, *shift),
+        Op::Debug => emit_debug(asm, context, position),
+        Op::Loop(body) => {
+            let header_label = asm.allocate_label();
+            let start_label = asm.allocate_label();
+            let end_label = asm.allocate_label();
+
+            asm.name_label(header_label, &format!("loop_L{}_C{}", position.line, position.column));
+            asm.label(header_label);
+
+            emit_test_cell(asm, width);
+            asm.je(end_label);
+            asm.label(start_label);
+
+            emit_nodes(asm, context, body);
+
+            asm.source_span(node.span.last_character());
+
+            emit_test_cell(asm, width);
+            asm.jne(start_label);
+            asm.label(end_label);
+        }
+    }
+}
+
+// Move the tape pointer, handling the ends of the tape according to the boundary mode; the position is that of the
+// command responsible for the move, which is reported if the move is an error
+fn emit_move<'a, A: Assembler<'a>>(asm: &mut A, context: &mut Context<'_, A::Label>, shift: i64, position: Position) {
+    match context.options.boundary {
+        Boundary::Wrap => emit_wrapping_move(asm, context.options.tape_length, shift),
+        Boundary::Abort | Boundary::Grow => emit_checked_move(asm, context, shift, position),
+    }
+}
+
+// Implement a shift as a sign-extended addition to r8 with an 8- or 32-bit immediate, or failing that via r15; we
+// can't use inc/dec here because the boundary logic depends on the flags being updated
+fn emit_add_r8<'a, A: Assembler<'a>>(asm: &mut A, shift: i64) {
+    if i64::from(i8::MIN) <= shift && shift <= i64::from(i8::MAX) {
+        asm.add_r8_i8(shift as i8);
+    } else if i64::from(i32::MIN) <= shift && shift <= i64::from(i32::MAX) {
+        asm.add_r8_i32(shift as i32);
+    } else {
+        asm.mov_r15_u64(shift as u64);
+        asm.add_r8_r15();
+    }
+}
+
+fn emit_wrapping_move<'a, A: Assembler<'a>>(asm: &mut A, tape_length: u64, shift: i64) {
+    let wrapped_shift = shift % (tape_length as i64);
+
+    // If the shift would bring us back to the same cell, it's a no-op
+    if wrapped_shift == 0 {
+        return;
+    }
+
+    emit_add_r8(asm, wrapped_shift);
+
+    if wrapped_shift > 0 {
+        // The addition can't have overflowed r8 (this would only be possible for
+        // tapes of 2**63 cells or more), so we know that the shift exceeded the right boundary
+        // of the tape if and only if r8 is greater than or equal to r9 (unsigned). In
+        // this case we can recover the correctly-wrapped value of the tape pointer by
+        // simply subtracting r9 from r8
+
+        // Using r15 as scratch, compute r8 - r9, and copy the result back to r8 if
+        // in fact r8 >= r9 (unsigned)
+        asm.mov_r15_r8();
+        asm.sub_r15_r9();
+        asm.cmovae_r8_r15();
+    } else {
+        // Again because the tape isn't huge, we exceeded the left boundary of the
+        // tape if and only if the previous addition resulted in a negative integer. Moreover,
+        // in this case we can recover the correctly-wrapped value of the tape pointer
+        // by simply adding r9 to r8 (because r8 contains a signed negative integer
+        // indicating the magnitude of the underflow)
+
+        let done = asm.allocate_label();
+        asm.jns(done);
+        asm.add_r8_r9();
+        asm.label(done);
+    }
+}
+
+/*
+    Without wraparound the shift isn't reduced modulo the tape length, but the same
+    reasoning applies: r8 is less than 2**62 and the shift is less than 2**63 in magnitude,
+    so after a shift to the right r8 is past the right end of the tape if and only if it's
+    greater than or equal to r9 (unsigned), and after a shift to the left r8 is past the
+    left end if and only if it's negative.
+
+    Going past the left end is always an error. Going past the right end is an error when
+    aborting, but when growing the tape instead jumps to a routine which grows it to cover
+    r8; rsp isn't a stack pointer, so r15 holds the address for the routine to return to.
+*/
+
+fn emit_checked_move<'a, A: Assembler<'a>>(
+    asm: &mut A,
+    context: &mut Context<'_, A::Label>,
+    shift: i64,
+    position: Position,
+) {
+    if shift == 0 {
+        return;
+    }
+
+    emit_add_r8(asm, shift);
+
+    if shift > 0 {
+        asm.cmp_r8_r9();
+
+        if context.options.boundary == Boundary::Grow {
+            let done = asm.allocate_label();
+            asm.jb(done);
+            asm.lea_r15_ptr_rel(done);
+            asm.jmp(context.grow_tape);
+            asm.label(done);
+        } else {
+            let error = context.boundary_error(asm, position, "right");
+            asm.jae(error);
+        }
+    } else {
+        let error = context.boundary_error(asm, position, "left");
+        asm.js(error);
+    }
+}
+
+/*
+    Cells are addressed as rbx + r8 * (cell size) + displacement, where the displacement
+    is the offset of the cell from the tape pointer scaled by the cell size. The functions
+    below dispatch on the cell width; the caller guarantees that the cell lies within the
+    tape and that the displacement fits in 32 bits.
+*/
+
+fn displacement(width: CellWidth, offset: i32) -> i32 {
+    offset * width.bytes() as i32
+}
 
-                // Implement the shift as a sign-extended addition to r8 with an 8- or 32-bit immediate;
-                // we can't use inc/dec here because the wraparound logic depends on the flags being updated
-                if i64::from(i8::min_value()) <= wrapped_shift && wrapped_shift <= i64::from(i8::max_value()) {
-                    asm.add_r8_i8(wrapped_shift as i8);
-                } else if i64::from(i32::min_value()) <= wrapped_shift && wrapped_shift <= i64::from(i32::max_value()) {
-                    asm.add_r8_i32(wrapped_shift as i32);
+// Add to the cell at the given offset from the tape pointer
+fn emit_add<'a, A: Assembler<'a>>(asm: &mut A, width: CellWidth, offset: i32, value: i64) {
+    let wrapped_value = width.wrap(value);
+
+    if wrapped_value == 0 {
+        return;
+    }
+
+    if wrapped_value == 1 {
+        emit_increment(asm, width, offset);
+        return;
+    }
+
+    if wrapped_value == width.wrap(-1) {
+        emit_decrement(asm, width, offset);
+        return;
+    }
+
+    let d = displacement(width, offset);
+
+    match (width, offset) {
+        (CellWidth::Bits8, 0) => asm.add_byte_ptr_rbx_plus_r8_u8(wrapped_value as u8),
+        (CellWidth::Bits8, _) => asm.add_byte_ptr_rbx_plus_r8_plus_i32_u8(d, wrapped_value as u8),
+        (CellWidth::Bits16, 0) => asm.add_word_ptr_rbx_plus_r8_times_2_u16(wrapped_value as u16),
+        (CellWidth::Bits16, _) => asm.add_word_ptr_rbx_plus_r8_times_2_plus_i32_u16(d, wrapped_value as u16),
+        (CellWidth::Bits32, 0) => asm.add_dword_ptr_rbx_plus_r8_times_4_u32(wrapped_value as u32),
+        (CellWidth::Bits32, _) => asm.add_dword_ptr_rbx_plus_r8_times_4_plus_i32_u32(d, wrapped_value as u32),
+        (CellWidth::Bits64, _) => {
+            // 64-bit additions only take sign-extended 32-bit immediates
+            let value = wrapped_value as i64;
+
+            if i64::from(i32::MIN) <= value && value <= i64::from(i32::MAX) {
+                if offset == 0 {
+                    asm.add_qword_ptr_rbx_plus_r8_times_8_i32(value as i32);
                 } else {
-                    panic!("shift too big (FIXME)")
+                    asm.add_qword_ptr_rbx_plus_r8_times_8_plus_i32_i32(d, value as i32);
                 }
+            } else {
+                asm.mov_r15_u64(wrapped_value);
 
-                if wrapped_shift > 0 {
-                    // Assume that the addition didn't overflow r8 (this would only be possible for TAPE_LENGTH >= 2**63)
-                    // FIXME: surface this assertion in a saner way
-                    assert!(TAPE_LENGTH < (1u64 << 63));
-
-                    // Given the previous assumption, we know that the shift exceeded the right
-                    // boundary of the tape if and only if r8 is greater than or equal to r9
-                    // (unsigned). In this case we can recover the correctly-wrapped value of the
-                    // tape pointer by simply subtracting r9 from r8
-
-                    // Using r15 as scratch, compute r8 - r9, and copy the result back to r8 if
-                    // in fact r8 >= r9 (unsigned)
-                    asm.mov_r15_r8();
-                    asm.sub_r15_r9();
-                    asm.cmovae_r8_r15();
+                if offset == 0 {
+                    asm.add_qword_ptr_rbx_plus_r8_times_8_r15();
                 } else {
-                    // Assume that TAPE_LENGTH isn't huge, again (FIXME)
-                    assert!(TAPE_LENGTH < (1u64 << 63));
-
-                    // Given the previous assertion, we exceeded the left boundary of the tape if
-                    // and only if the previous addition resulted in a negative integer. Moreover,
-                    // in this case we can recover the correctly-wrapped value of the tape pointer
-                    // by simply adding r9 to r8 (because r8 contains a signed negative integer
-                    // indicating the magnitude of the underflow)
-
-                    let done = asm.allocate_label();
-                    asm.jns(done);
-                    asm.add_r8_r9();
-                    asm.label(done);
+                    asm.add_qword_ptr_rbx_plus_r8_times_8_plus_i32_r15(d);
                 }
             }
-            Add(value) => {
-                let wrapped_value = ((value % 256 + 256) % 256) as u8;
-
-                match wrapped_value {
-                    0 => (),
-                    1 => asm.inc_byte_ptr_rbx_plus_r8(),
-                    255 => asm.dec_byte_ptr_rbx_plus_r8(),
-                    _ => asm.add_byte_ptr_rbx_plus_r8_u8(wrapped_value),
-                }
-            }
-            ReadChar => {
-                // FIXME: allow unbuffered input
-                assert!(INPUT_BUFFER_SIZE > 0);
-
-                let data_in_buffer = asm.allocate_label();
-
-                asm.cmp_r10_r12();
-                asm.jne(data_in_buffer);
-
-                // Flush any buffered output
-                {
-                    let skip_flush = asm.allocate_label();
-                    asm.cmp_r13_u32(0);
-                    asm.je(skip_flush);
-                    emit_flush(&mut asm);
-                    asm.label(skip_flush);
-                }
+        }
+    }
+}
 
-                // Read into the input buffer
-                {
-                    asm.xor_rax_rax(); // sys_read
-                    asm.xor_rdi_rdi(); // Standard input
-                    asm.mov_rsi_r14(); // Input buffer
-                    asm.mov_rdx_u32(INPUT_BUFFER_SIZE as u32); // Input buffer size
-                    asm.syscall();
-
-                    // FIXME: distinguish errors from EOF
-                    let okay = asm.allocate_label();
-                    asm.cmp_rax_u32(0);
-                    asm.jg(okay);
-                    emit_exit(&mut asm, 2);
-                    asm.label(okay);
-
-                    // Record the number of bytes in the input buffer
-                    asm.mov_r12_rax();
-
-                    // Rest input buffer cursor to zero
-                    asm.xor_r10_r10();
-                }
+fn emit_increment<'a, A: Assembler<'a>>(asm: &mut A, width: CellWidth, offset: i32) {
+    let d = displacement(width, offset);
 
-                asm.label(data_in_buffer);
+    match (width, offset) {
+        (CellWidth::Bits8, 0) => asm.inc_byte_ptr_rbx_plus_r8(),
+        (CellWidth::Bits8, _) => asm.inc_byte_ptr_rbx_plus_r8_plus_i32(d),
+        (CellWidth::Bits16, 0) => asm.inc_word_ptr_rbx_plus_r8_times_2(),
+        (CellWidth::Bits16, _) => asm.inc_word_ptr_rbx_plus_r8_times_2_plus_i32(d),
+        (CellWidth::Bits32, 0) => asm.inc_dword_ptr_rbx_plus_r8_times_4(),
+        (CellWidth::Bits32, _) => asm.inc_dword_ptr_rbx_plus_r8_times_4_plus_i32(d),
+        (CellWidth::Bits64, 0) => asm.inc_qword_ptr_rbx_plus_r8_times_8(),
+        (CellWidth::Bits64, _) => asm.inc_qword_ptr_rbx_plus_r8_times_8_plus_i32(d),
+    }
+}
 
-                // Copy a byte from the input buffer to the tape
-                asm.mov_r15b_byte_ptr_r14_plus_r10();
-                asm.mov_byte_ptr_rbx_plus_r8_r15b();
+fn emit_decrement<'a, A: Assembler<'a>>(asm: &mut A, width: CellWidth, offset: i32) {
+    let d = displacement(width, offset);
 
-                // Increment input buffer index
-                asm.inc_r10();
-            }
-            WriteChar => {
-                // FIXME: allow unbuffered output
-                assert!(OUTPUT_BUFFER_SIZE > 0);
+    match (width, offset) {
+        (CellWidth::Bits8, 0) => asm.dec_byte_ptr_rbx_plus_r8(),
+        (CellWidth::Bits8, _) => asm.dec_byte_ptr_rbx_plus_r8_plus_i32(d),
+        (CellWidth::Bits16, 0) => asm.dec_word_ptr_rbx_plus_r8_times_2(),
+        (CellWidth::Bits16, _) => asm.dec_word_ptr_rbx_plus_r8_times_2_plus_i32(d),
+        (CellWidth::Bits32, 0) => asm.dec_dword_ptr_rbx_plus_r8_times_4(),
+        (CellWidth::Bits32, _) => asm.dec_dword_ptr_rbx_plus_r8_times_4_plus_i32(d),
+        (CellWidth::Bits64, 0) => asm.dec_qword_ptr_rbx_plus_r8_times_8(),
+        (CellWidth::Bits64, _) => asm.dec_qword_ptr_rbx_plus_r8_times_8_plus_i32(d),
+    }
+}
+
+// Store a value in the cell at the given offset; 64-bit stores only take sign-extended 32-bit immediates, so the
+// value must fit in an i32
+fn emit_set<'a, A: Assembler<'a>>(asm: &mut A, width: CellWidth, offset: i32, value: i64) {
+    let wrapped_value = width.wrap(value);
+    let d = displacement(width, offset);
+
+    match (width, offset) {
+        (CellWidth::Bits8, 0) => asm.mov_byte_ptr_rbx_plus_r8_u8(wrapped_value as u8),
+        (CellWidth::Bits8, _) => asm.mov_byte_ptr_rbx_plus_r8_plus_i32_u8(d, wrapped_value as u8),
+        (CellWidth::Bits16, 0) => asm.mov_word_ptr_rbx_plus_r8_times_2_u16(wrapped_value as u16),
+        (CellWidth::Bits16, _) => asm.mov_word_ptr_rbx_plus_r8_times_2_plus_i32_u16(d, wrapped_value as u16),
+        (CellWidth::Bits32, 0) => asm.mov_dword_ptr_rbx_plus_r8_times_4_u32(wrapped_value as u32),
+        (CellWidth::Bits32, _) => asm.mov_dword_ptr_rbx_plus_r8_times_4_plus_i32_u32(d, wrapped_value as u32),
+        (CellWidth::Bits64, 0) => asm.mov_qword_ptr_rbx_plus_r8_times_8_i32(value as i32),
+        (CellWidth::Bits64, _) => asm.mov_qword_ptr_rbx_plus_r8_times_8_plus_i32_i32(d, value as i32),
+    }
+}
+
+// Compare the current cell against zero
+fn emit_test_cell<'a, A: Assembler<'a>>(asm: &mut A, width: CellWidth) {
+    match width {
+        CellWidth::Bits8 => asm.cmp_byte_ptr_rbx_plus_r8_u8(0),
+        CellWidth::Bits16 => asm.cmp_word_ptr_rbx_plus_r8_times_2_u16(0),
+        CellWidth::Bits32 => asm.cmp_dword_ptr_rbx_plus_r8_times_4_u32(0),
+        CellWidth::Bits64 => asm.cmp_qword_ptr_rbx_plus_r8_times_8_i32(0),
+    }
+}
+
+// Load the cell at the given offset into rax, zero-extended
+fn emit_load_rax<'a, A: Assembler<'a>>(asm: &mut A, width: CellWidth, offset: i32) {
+    let d = displacement(width, offset);
+
+    match (width, offset) {
+        (CellWidth::Bits8, 0) => asm.movzx_eax_byte_ptr_rbx_plus_r8(),
+        (CellWidth::Bits8, _) => asm.movzx_eax_byte_ptr_rbx_plus_r8_plus_i32(d),
+        (CellWidth::Bits16, 0) => asm.movzx_eax_word_ptr_rbx_plus_r8_times_2(),
+        (CellWidth::Bits16, _) => asm.movzx_eax_word_ptr_rbx_plus_r8_times_2_plus_i32(d),
+        (CellWidth::Bits32, 0) => asm.mov_eax_dword_ptr_rbx_plus_r8_times_4(),
+        (CellWidth::Bits32, _) => asm.mov_eax_dword_ptr_rbx_plus_r8_times_4_plus_i32(d),
+        (CellWidth::Bits64, 0) => asm.mov_rax_qword_ptr_rbx_plus_r8_times_8(),
+        (CellWidth::Bits64, _) => asm.mov_rax_qword_ptr_rbx_plus_r8_times_8_plus_i32(d),
+    }
+}
+
+// Add factor times rax (as loaded by emit_load_rax) to the cell at the given offset; clobbers rcx
+fn emit_multiply_add_rax<'a, A: Assembler<'a>>(asm: &mut A, width: CellWidth, offset: i32, factor: i64) {
+    let wrapped_factor = width.wrap(factor);
+    let d = displacement(width, offset);
+
+    if wrapped_factor == 0 {
+        return;
+    }
+
+    if wrapped_factor == width.wrap(-1) {
+        match (width, offset) {
+            (CellWidth::Bits8, 0) => asm.sub_byte_ptr_rbx_plus_r8_al(),
+            (CellWidth::Bits8, _) => asm.sub_byte_ptr_rbx_plus_r8_plus_i32_al(d),
+            (CellWidth::Bits16, 0) => asm.sub_word_ptr_rbx_plus_r8_times_2_ax(),
+            (CellWidth::Bits16, _) => asm.sub_word_ptr_rbx_plus_r8_times_2_plus_i32_ax(d),
+            (CellWidth::Bits32, 0) => asm.sub_dword_ptr_rbx_plus_r8_times_4_eax(),
+            (CellWidth::Bits32, _) => asm.sub_dword_ptr_rbx_plus_r8_times_4_plus_i32_eax(d),
+            (CellWidth::Bits64, 0) => asm.sub_qword_ptr_rbx_plus_r8_times_8_rax(),
+            (CellWidth::Bits64, _) => asm.sub_qword_ptr_rbx_plus_r8_times_8_plus_i32_rax(d),
+        }
+
+        return;
+    }
+
+    if wrapped_factor == 1 {
+        match (width, offset) {
+            (CellWidth::Bits8, 0) => asm.add_byte_ptr_rbx_plus_r8_al(),
+            (CellWidth::Bits8, _) => asm.add_byte_ptr_rbx_plus_r8_plus_i32_al(d),
+            (CellWidth::Bits16, 0) => asm.add_word_ptr_rbx_plus_r8_times_2_ax(),
+            (CellWidth::Bits16, _) => asm.add_word_ptr_rbx_plus_r8_times_2_plus_i32_ax(d),
+            (CellWidth::Bits32, 0) => asm.add_dword_ptr_rbx_plus_r8_times_4_eax(),
+            (CellWidth::Bits32, _) => asm.add_dword_ptr_rbx_plus_r8_times_4_plus_i32_eax(d),
+            (CellWidth::Bits64, 0) => asm.add_qword_ptr_rbx_plus_r8_times_8_rax(),
+            (CellWidth::Bits64, _) => asm.add_qword_ptr_rbx_plus_r8_times_8_plus_i32_rax(d),
+        }
+
+        return;
+    }
+
+    // Only the low bits of the product matter, so a 32-bit multiplication suffices unless the cells are wider
+    if width == CellWidth::Bits64 {
+        asm.mov_rcx_u64(wrapped_factor);
+        asm.imul_rcx_rax();
+    } else {
+        asm.imul_ecx_eax_i32(wrapped_factor as u32 as i32);
+    }
+
+    match (width, offset) {
+        (CellWidth::Bits8, 0) => asm.add_byte_ptr_rbx_plus_r8_cl(),
+        (CellWidth::Bits8, _) => asm.add_byte_ptr_rbx_plus_r8_plus_i32_cl(d),
+        (CellWidth::Bits16, 0) => asm.add_word_ptr_rbx_plus_r8_times_2_cx(),
+        (CellWidth::Bits16, _) => asm.add_word_ptr_rbx_plus_r8_times_2_plus_i32_cx(d),
+        (CellWidth::Bits32, 0) => asm.add_dword_ptr_rbx_plus_r8_times_4_ecx(),
+        (CellWidth::Bits32, _) => asm.add_dword_ptr_rbx_plus_r8_times_4_plus_i32_ecx(d),
+        (CellWidth::Bits64, 0) => asm.add_qword_ptr_rbx_plus_r8_times_8_rcx(),
+        (CellWidth::Bits64, _) => asm.add_qword_ptr_rbx_plus_r8_times_8_plus_i32_rcx(d),
+    }
+}
+
+fn emit_multiply_add<'a, A: Assembler<'a>>(
+    asm: &mut A,
+    context: &mut Context<'_, A::Label>,
+    terms: &[(i64, i64)],
+    position: Position,
+) {
+    let width = context.options.cell_width;
+
+    // The loop this replaces doesn't visit its target cells if the current cell is zero, so unless the tape wraps
+    // (in which case visiting them is harmless) we mustn't either
+    let skip = asm.allocate_label();
+
+    if context.options.boundary != Boundary::Wrap {
+        emit_test_cell(asm, width);
+        asm.je(skip);
+    }
+
+    // Keep the initial value of the current cell in rax; emit_move clobbers r15, and when it grows the tape, rcx,
+    // rdx, rsi, rdi and r11, but not rax
+    emit_load_rax(asm, width, 0);
+
+    // Visit each target cell in turn, moving the tape pointer so that the usual boundary logic applies. Cells
+    // which are left unchanged only need visiting to catch moves off the tape, which can't happen if it wraps
+    let mut current = 0;
+
+    let wraps = context.options.boundary == Boundary::Wrap;
+    let terms = terms.iter().filter(|&&(_, factor)| !wraps || width.wrap(factor) != 0);
+
+    for &(offset, factor) in terms {
+        emit_move(asm, context, offset - current, position);
+        current = offset;
+        emit_multiply_add_rax(asm, width, 0, factor);
+    }
+
+    emit_move(asm, context, -current, position);
+    emit_set(asm, width, 0, 0);
+    asm.label(skip);
+}
+
+// Like emit_multiply_add, but for a loop at the given offset whose targets are all known to lie within the tape
+fn emit_offset_multiply_add<'a, A: Assembler<'a>>(asm: &mut A, width: CellWidth, offset: i32, terms: &[(i32, i64)]) {
+    emit_load_rax(asm, width, offset);
+
+    for &(target, factor) in terms {
+        emit_multiply_add_rax(asm, width, target, factor);
+    }
 
-                // Copy a byte from the tape to the output buffer
-                asm.mov_r15b_byte_ptr_rbx_plus_r8();
-                asm.mov_byte_ptr_rsp_plus_r13_r15b();
+    emit_set(asm, width, offset, 0);
+}
 
-                // Increment output buffer index
-                asm.inc_r13();
+/*
+    A block addresses each cell it touches as a displacement from the tape pointer, and
+    then moves the pointer once at the end. Displacements don't wrap around the tape, so
+    this fast path is only taken if every cell touched lies between the tape pointer and
+    the end of the tape on that side. Otherwise we fall back to executing the block's
+    commands one by one, with the usual boundary logic.
+*/
 
-                let flush = asm.allocate_label();
-                let done = asm.allocate_label();
+fn emit_block<'a, A: Assembler<'a>>(asm: &mut A, context: &mut Context<'_, A::Label>, ops: &[(i64, Node)], shift: i64) {
+    let width = context.options.cell_width;
+    let slow = asm.allocate_label();
+    let done = asm.allocate_label();
 
-                // Flush output buffer if character was a newline
-                asm.cmp_r15b_u8(b'\n');
-                asm.je(flush);
+    // A growable tape may later be long enough for the fast path, even if it isn't yet
+    let limit = match context.options.boundary {
+        Boundary::Wrap | Boundary::Abort => context.options.tape_length,
+        Boundary::Grow => MAX_TAPE_LENGTH,
+    };
 
-                // Skip flush if the character was not a newline and the buffer isn't full
-                asm.cmp_r13_u32(OUTPUT_BUFFER_SIZE as u32);
-                asm.jne(done);
+    if let Some((low, high)) = block_bounds(ops, width, limit) {
+        if low < 0 {
+            // Take the slow path if r8 < -low, i.e. the lowest cell would be before the tape
+            if -low <= i32::from(i8::MAX) {
+                asm.cmp_r8_i8(-low as i8);
+            } else {
+                asm.cmp_r8_i32(-low);
+            }
 
-                asm.label(flush);
+            asm.jb(slow);
+        }
 
-                emit_flush(&mut asm);
+        if high > 0 {
+            // Take the slow path if r8 + high >= r9, i.e. the highest cell would be past the tape
+            asm.mov_r15_r8();
 
-                // Flush is complete, or no flush was necessary
-                asm.label(done);
+            if high <= i32::from(i8::MAX) {
+                asm.add_r15_i8(high as i8);
+            } else {
+                asm.add_r15_i32(high);
             }
-            LoopStart => {
-                let start_label = asm.allocate_label();
-                let end_label = asm.allocate_label();
-                loop_stack.push((start_label, end_label));
-                asm.cmp_byte_ptr_rbx_plus_r8_u8(0);
-                asm.je(end_label);
-                asm.label(start_label);
+
+            asm.cmp_r15_r9();
+            asm.jae(slow);
+        }
+
+        for (offset, node) in ops {
+            // block_bounds has checked that every offset fits in a 32-bit displacement
+            let offset = *offset as i32;
+
+            asm.source_span(node.span);
+
+            match &node.op {
+                Op::Move(_) => (),
+                Op::Add(value) => emit_add(asm, width, offset, *value),
+                Op::Clear => emit_set(asm, width, offset, 0),
+                Op::MultiplyAdd(terms) => {
+                    let terms = terms
+                        .iter()
+                        .map(|&(target, factor)| (offset + target as i32, factor))
+                        .collect::<Vec<_>>();
+                    emit_offset_multiply_add(asm, width, offset, &terms);
+                }
+                _ => unreachable!(),
             }
-            LoopEnd => {
-                // FIXME: error handling
-                let (start_label, end_label) = loop_stack.pop().unwrap();
-                asm.cmp_byte_ptr_rbx_plus_r8_u8(0);
-                asm.jne(start_label);
-                asm.label(end_label);
+        }
+
+        // Every cell visited before the last move was touched, so only the last move can leave the tape
+        let last_move = ops.iter().rev().find(|(_, node)| matches!(node.op, Op::Move(_)));
+
+        if let Some((_, node)) = last_move {
+            asm.source_span(node.span);
+            emit_move(asm, context, shift, node.span.start);
+        }
+
+        asm.jmp(done);
+    }
+
+    asm.label(slow);
+
+    for (_, node) in ops {
+        emit_node(asm, context, node);
+    }
+
+    asm.label(done);
+}
+
+// The lowest and highest offsets touched by a block, counting the current cell; None if the fast path can't be
+// used, because some offset doesn't fit in a 32-bit displacement once scaled by the cell size, or the cells touched
+// don't fit on a tape of the given length
+fn block_bounds(ops: &[(i64, Node)], width: CellWidth, tape_length: u64) -> Option<(i32, i32)> {
+    let mut low = 0i64;
+    let mut high = 0i64;
+
+    for (offset, node) in ops {
+        let mut offsets = vec![*offset];
+
+        if let Op::MultiplyAdd(terms) = &node.op {
+            for (target, _) in terms {
+                offsets.push(offset.checked_add(*target)?);
             }
-            EndOfFile => break,
+        }
+
+        for offset in offsets {
+            low = low.min(offset);
+            high = high.max(offset);
         }
     }
 
-    // FIXME: error handling
-    assert!(loop_stack.is_empty());
+    let bytes = width.bytes() as i64;
 
-    // Flush any remaining output
+    // The lowest offset is negated when compared against r8, so i32::MIN is out of range too
+    if low <= i64::from(i32::MIN) / bytes || high > i64::from(i32::MAX) / bytes || high - low >= tape_length as i64 {
+        return None;
+    }
+
+    Some((low as i32, high as i32))
+}
+
+/*
+    Scans whose stride divides 16 are vectorized: we compare 16 cells at a time against
+    zero using SSE2, mask off the cells which the stride skips over, and use bsf/bsr to
+    find the first zero cell in the direction of travel. Because the stride divides 16,
+    advancing by a whole chunk preserves the alignment of the mask.
+
+    Vector loads must stay within the tape, so when fewer than 16 cells remain before the
+    end of the tape in the direction of travel we fall back to stepping one stride at a
+    time with the usual boundary logic; once the pointer has wrapped (or the tape has
+    grown), vectorized scanning resumes. Other strides, and scans over cells wider than a
+    byte, always take the scalar path.
+*/
+
+fn emit_scan<'a, A: Assembler<'a>>(asm: &mut A, context: &mut Context<'_, A::Label>, stride: i64, position: Position) {
+    const CHUNK: i64 = 16;
+
+    // The stride is only reduced modulo the tape length if the tape wraps
+    let wrapped_stride = match context.options.boundary {
+        Boundary::Wrap => stride % (context.options.tape_length as i64),
+        Boundary::Abort | Boundary::Grow => stride,
+    };
+
+    let vectorized = context.options.passes.vectorize_scans
+        && context.options.cell_width == CellWidth::Bits8
+        && wrapped_stride != 0
+        && CHUNK % wrapped_stride == 0;
+
+    let top = asm.allocate_label();
+    let scalar = asm.allocate_label();
+    let done = asm.allocate_label();
+
+    if vectorized {
+        let found = asm.allocate_label();
+
+        // Bit i of the mask is set if the cell i bytes into the chunk lies on the stride; backward scans
+        // load the chunk ending at the current cell, so their mask is anchored at bit 15
+        let magnitude = wrapped_stride.unsigned_abs();
+        let mut mask = 0u32;
+
+        for index in (0..CHUNK as u64).step_by(magnitude as usize) {
+            mask |= if wrapped_stride > 0 {
+                1 << index
+            } else {
+                1 << (CHUNK as u64 - 1 - index)
+            };
+        }
+
+        asm.pxor_xmm0_xmm0();
+        asm.label(top);
+
+        if wrapped_stride > 0 {
+            // Fall back to the scalar path if r8 + 16 >= r9, i.e. the chunk would extend past the tape, or would
+            // end at its last cell and leave r8 pointing past it
+            asm.mov_r15_r8();
+            asm.add_r15_i8(CHUNK as i8);
+            asm.cmp_r15_r9();
+            asm.jae(scalar);
+
+            asm.movdqu_xmm1_ptr_rbx_plus_r8();
+        } else {
+            // Fall back to the scalar path if r8 < 16, i.e. the chunk would extend before the tape, or would
+            // start at its first cell and leave r8 pointing before it
+            asm.cmp_r8_i8(CHUNK as i8);
+            asm.jb(scalar);
+
+            asm.mov_r15_r8();
+            asm.add_r15_i8(-(CHUNK - 1) as i8);
+            asm.movdqu_xmm1_ptr_rbx_plus_r15();
+        }
+
+        asm.pcmpeqb_xmm1_xmm0();
+        asm.pmovmskb_eax_xmm1();
+        asm.and_eax_u32(mask);
+        asm.jne(found);
+
+        asm.add_r8_i8(if wrapped_stride > 0 { CHUNK as i8 } else { -CHUNK as i8 });
+        asm.jmp(top);
+
+        asm.label(found);
+
+        if wrapped_stride > 0 {
+            // The lowest set bit is the first zero cell after r8
+            asm.bsf_eax_eax();
+            asm.add_r8_rax();
+        } else {
+            // The highest set bit is the first zero cell before r8; r15 still points at the start of the chunk
+            asm.bsr_eax_eax();
+            asm.mov_r8_r15();
+            asm.add_r8_rax();
+        }
+
+        asm.jmp(done);
+    } else {
+        asm.label(top);
+    }
+
+    asm.label(scalar);
+    emit_test_cell(asm, context.options.cell_width);
+    asm.je(done);
+    emit_move(asm, context, stride, position);
+    asm.jmp(top);
+
+    asm.label(done);
+}
+
+fn emit_read<'a, A: Assembler<'a>>(asm: &mut A, context: &Context<'_, A::Label>) {
+    let options = context.options;
+    let width = options.cell_width;
+    let data_in_buffer = asm.allocate_label();
+    let done = asm.allocate_label();
+
+    asm.cmp_r10_r12();
+    asm.jne(data_in_buffer);
+
+    // Flush any buffered output
     {
         let skip_flush = asm.allocate_label();
         asm.cmp_r13_u32(0);
         asm.je(skip_flush);
-        emit_flush(&mut asm);
+        emit_flush(asm, context);
         asm.label(skip_flush);
     }
 
-    emit_exit(&mut asm, 0);
+    // Read into the input buffer
+    {
+        asm.xor_rax_rax(); // sys_read
+        asm.xor_rdi_rdi(); // Standard input
+        asm.mov_rsi_r14(); // Input buffer
+        asm.mov_rdx_u32(options.input_buffer_size as u32); // Input buffer size
+        asm.syscall();
 
-    asm.assemble(output)?;
+        // A positive result is the number of bytes read, zero means end of input, and a negative result is an
+        // error. Since nothing was read at end of input the buffer remains empty, so the next read tries again
+        let okay = asm.allocate_label();
+        let end_of_input = match options.eof {
+            Eof::Unchanged => done,
+            Eof::Zero | Eof::MinusOne => asm.allocate_label(),
+        };
 
-    Ok(())
+        asm.cmp_rax_u32(0);
+        asm.jg(okay);
+        asm.je(end_of_input);
+        emit_exit(asm, context, EXIT_READ_ERROR);
+
+        match options.eof {
+            Eof::Unchanged => (),
+            Eof::Zero | Eof::MinusOne => {
+                asm.label(end_of_input);
+                emit_set(asm, width, 0, if options.eof == Eof::Zero { 0 } else { -1 });
+                asm.jmp(done);
+            }
+        }
+
+        asm.label(okay);
+
+        // Record the number of bytes in the input buffer
+        asm.mov_r12_rax();
+
+        // Rest input buffer cursor to zero
+        asm.xor_r10_r10();
+    }
+
+    asm.label(data_in_buffer);
+
+    // Copy a byte from the input buffer to the tape, zero-extending it to the cell size
+    match width {
+        CellWidth::Bits8 => {
+            asm.mov_r15b_byte_ptr_r14_plus_r10();
+            asm.mov_byte_ptr_rbx_plus_r8_r15b();
+        }
+        CellWidth::Bits16 => {
+            asm.movzx_r15d_byte_ptr_r14_plus_r10();
+            asm.mov_word_ptr_rbx_plus_r8_times_2_r15w();
+        }
+        CellWidth::Bits32 => {
+            asm.movzx_r15d_byte_ptr_r14_plus_r10();
+            asm.mov_dword_ptr_rbx_plus_r8_times_4_r15d();
+        }
+        CellWidth::Bits64 => {
+            // Writing r15d zeroes the upper half of r15
+            asm.movzx_r15d_byte_ptr_r14_plus_r10();
+            asm.mov_qword_ptr_rbx_plus_r8_times_8_r15();
+        }
+    }
+
+    // Increment input buffer index
+    asm.inc_r10();
+
+    asm.label(done);
 }
 
-fn emit_exit(asm: &mut ElfAssembler, code: u32) {
+fn emit_write<'a, A: Assembler<'a>>(asm: &mut A, context: &Context<'_, A::Label>) {
+    let options = context.options;
+
+    // Copy the low byte of the current cell to the output buffer; x86 is little-endian, so it's the cell's first byte
+    match options.cell_width {
+        CellWidth::Bits8 => asm.mov_r15b_byte_ptr_rbx_plus_r8(),
+        CellWidth::Bits16 => asm.mov_r15b_byte_ptr_rbx_plus_r8_times_2(),
+        CellWidth::Bits32 => asm.mov_r15b_byte_ptr_rbx_plus_r8_times_4(),
+        CellWidth::Bits64 => asm.mov_r15b_byte_ptr_rbx_plus_r8_times_8(),
+    }
+
+    asm.mov_byte_ptr_rsp_plus_r13_r15b();
+
+    // Increment output buffer index
+    asm.inc_r13();
+
+    // Without a buffer, every character is flushed as soon as it's written
+    if options.output_buffer_size == 1 {
+        emit_flush(asm, context);
+        return;
+    }
+
+    let flush = asm.allocate_label();
+    let done = asm.allocate_label();
+
+    // Flush output buffer if character was a newline
+    if options.flush_on_newline {
+        asm.cmp_r15b_u8(b'\n');
+        asm.je(flush);
+    }
+
+    // Skip flush if the character was not a newline and the buffer isn't full
+    asm.cmp_r13_u32(options.output_buffer_size as u32);
+    asm.jne(done);
+
+    asm.label(flush);
+
+    emit_flush(asm, context);
+
+    // Flush is complete, or no flush was necessary
+    asm.label(done);
+}
+
+fn emit_exit<'a, A: Assembler<'a>>(asm: &mut A, context: &Context<'_, A::Label>, code: u32) {
     // sys_exit
-    asm.mov_rax_u32(0x3c);
+    if context.linkage == Linkage::Executable {
+        asm.mov_rax_u32(0x3c);
+    }
 
     // Exit code
     if code == 0 {
@@ -233,10 +1120,27 @@ fn emit_exit(asm: &mut ElfAssembler, code: u32) {
         asm.mov_rdi_u32(code);
     }
 
-    asm.syscall();
+    match context.linkage {
+        Linkage::Executable => asm.syscall(),
+        Linkage::Function => asm.jmp(context.exit),
+    }
+}
+
+// Write out the contents of the output buffer, by way of the routine below
+fn emit_flush<'a, A: Assembler<'a>>(asm: &mut A, context: &Context<'_, A::Label>) {
+    let done = asm.allocate_label();
+    asm.lea_r15_ptr_rel(done);
+    asm.jmp(context.flush);
+    asm.label(done);
 }
 
-fn emit_flush(asm: &mut ElfAssembler) {
+// The routine flushing the output buffer, which returns to the address in r15; it exits the program if writing
+// fails
+fn emit_flush_routine<'a, A: Assembler<'a>>(asm: &mut A, context: &Context<'_, A::Label>, return_address: A::Address) {
+    asm.label(context.flush);
+    asm.mov_rax_addr(return_address);
+    asm.mov_qword_ptr_rax_r15();
+
     // Let r15 represent the number of bytes written thus far
     asm.xor_r15_r15();
 
@@ -265,7 +1169,7 @@ fn emit_flush(asm: &mut ElfAssembler) {
     // Check for errors (rax <= 0, signed)
     asm.cmp_rax_u32(0);
     asm.jg(okay);
-    emit_exit(asm, 1);
+    emit_exit(asm, context, EXIT_WRITE_ERROR);
     asm.label(okay);
 
     // Count the number of bytes written; if there remain bytes to be written, jump
@@ -276,4 +1180,326 @@ fn emit_flush(asm: &mut ElfAssembler) {
 
     // Mark the buffer as empty
     asm.xor_r13_r13();
+
+    asm.mov_rax_addr(return_address);
+    asm.mov_r15_qword_ptr_rax();
+    asm.jmp_r15();
+}
+
+// Map the initial tape of the given size in bytes into memory, pointing rbx at it; clobbers r8, r9 and r10
+fn emit_map_tape<'a, A: Assembler<'a>>(
+    asm: &mut A,
+    context: &Context<'_, A::Label>,
+    tape_size: u64,
+    tape_mapping: Option<A::Address>,
+) {
+    asm.mov_rax_u32(0x09); // sys_mmap
+    asm.xor_rdi_rdi(); // Let the kernel choose the address
+    asm.mov_rsi_u64(tape_size); // Length in bytes
+    asm.mov_rdx_u32(0x03); // PROT_READ | PROT_WRITE
+    asm.mov_r10_u32(0x22); // MAP_PRIVATE | MAP_ANONYMOUS
+    asm.mov_r8_u64(u64::MAX); // No file descriptor
+    asm.xor_r9_r9(); // Offset
+    asm.syscall();
+
+    // Errors are returned as negative numbers; mapped addresses are in user space, so never negative
+    asm.test_rax_rax();
+    asm.js(context.out_of_memory);
+
+    asm.mov_rbx_rax();
+    asm.mov_rdx_rsi();
+    emit_record_tape(asm, tape_mapping);
+}
+
+// Record the address of the tape (in rbx) and its size in bytes (in rdx), if it's to be unmapped on return; clobbers
+// rax
+fn emit_record_tape<'a, A: Assembler<'a>>(asm: &mut A, tape_mapping: Option<A::Address>) {
+    if let Some(tape_mapping) = tape_mapping {
+        asm.mov_rax_addr(tape_mapping);
+        asm.mov_qword_ptr_rax_rbx();
+        asm.mov_qword_ptr_rax_plus_i8_rdx(8);
+    }
+}
+
+fn emit_error_routines<'a, A: Assembler<'a>>(
+    asm: &mut A,
+    context: &Context<'_, A::Label>,
+    tape_mapping: Option<A::Address>,
+) {
+    // Each stub points rbp at its message, which is stored directly after it, and jumps to the common routine
+    // which reports it
+    for (label, message) in &context.boundary_errors {
+        let text = asm.allocate_label();
+
+        asm.label(*label);
+        asm.lea_rbp_ptr_rel(text);
+        asm.mov_r12_u64(message.len() as u64);
+        asm.jmp(context.out_of_bounds);
+
+        asm.label(text);
+        asm.data(message.as_bytes());
+    }
+
+    if !context.boundary_errors.is_empty() {
+        asm.label(context.out_of_bounds);
+        emit_report(asm, context, EXIT_OUT_OF_BOUNDS);
+    }
+
+    if context.options.boundary == Boundary::Grow {
+        // Grow the tape to r8 + r9 cells, which covers r8 and at least doubles its length; the new cells are
+        // zeroed by the kernel. On success, return to the address in r15. The lengths passed to mremap are in
+        // bytes; they can't overflow, because the tape only ever grows to the size of memory
+        let scale = context.options.cell_width.bytes().trailing_zeros() as u8;
+
+        asm.label(context.grow_tape);
+        asm.mov_rbp_r10();
+        asm.mov_rsi_r9(); // Old length
+        asm.mov_rdx_r8(); // New length
+        asm.add_rdx_r9();
+
+        // The caller may be holding a value in rax, e.g. the multiplier of a multiply loop, so it's kept in r9
+        // until the tape has been remapped; the new length is recovered from rdx, which the system call preserves
+        asm.mov_r9_rax();
+        asm.mov_rax_u32(0x19); // sys_mremap
+        asm.mov_rdi_rbx(); // Old address
+
+        if scale != 0 {
+            asm.shl_rsi_u8(scale);
+            asm.shl_rdx_u8(scale);
+        }
+
+        asm.mov_r10_u32(0x01); // MREMAP_MAYMOVE
+        asm.syscall();
+        asm.mov_r10_rbp();
+
+        asm.test_rax_rax();
+        asm.js(context.out_of_memory);
+
+        asm.mov_rbx_rax();
+        emit_record_tape(asm, tape_mapping);
+        asm.mov_rax_r9();
+
+        if scale != 0 {
+            asm.shr_rdx_u8(scale);
+        }
+
+        asm.mov_r9_rdx();
+        asm.jmp_r15();
+
+        let message = "error: out of memory for the tape\n";
+        let text = asm.allocate_label();
+
+        asm.label(context.out_of_memory);
+        asm.lea_rbp_ptr_rel(text);
+        asm.mov_r12_u64(message.len() as u64);
+        emit_report(asm, context, EXIT_OUT_OF_MEMORY);
+
+        asm.label(text);
+        asm.data(message.as_bytes());
+    }
+}
+
+// Flush any buffered output, print the message pointed to by rbp (whose length is in r12) to standard error, and
+// exit with the given code
+fn emit_report<'a, A: Assembler<'a>>(asm: &mut A, context: &Context<'_, A::Label>, code: u32) {
+    {
+        let skip_flush = asm.allocate_label();
+        asm.cmp_r13_u32(0);
+        asm.je(skip_flush);
+        emit_flush(asm, context);
+        asm.label(skip_flush);
+    }
+
+    // Nothing more can be done if this fails, so its result is ignored
+    asm.mov_rax_u32(0x01); // sys_write
+    asm.mov_rdi_u32(0x02); // Standard error
+    asm.mov_rsi_rbp();
+    asm.mov_rdx_r12();
+    asm.syscall();
+
+    emit_exit(asm, context, code);
+}
+
+fn contains_debug(nodes: &[Node]) -> bool {
+    nodes.iter().any(|node| match &node.op {
+        Op::Debug => true,
+        Op::Loop(body) => contains_debug(body),
+        _ => false,
+    })
+}
+
+// Dump the tape around the pointer to standard error, using the shared routine; the dump starts with the position of
+// the `#` responsible for it
+fn emit_debug<'a, A: Assembler<'a>>(asm: &mut A, context: &mut Context<'_, A::Label>, position: Position) {
+    let message = asm.allocate_label();
+    let back = asm.allocate_label();
+
+    let text = format!("debug: line {}, column {}: pointer ", position.line, position.column);
+    context.debug_messages.push((message, text));
+
+    asm.lea_rbp_ptr_rel(message);
+    asm.lea_r15_ptr_rel(back);
+    asm.jmp(context.dump_tape);
+    asm.label(back);
+}
+
+/*
+    The tape dump routine writes a line like
+
+        debug: line 3, column 5: pointer 2, cells 0 to 10: 0 1 [2] 0 0 0 0 0 0 0 0
+
+    to standard error, showing up to DEBUG_RADIUS cells on either side of the pointer.
+    The message pointed to by rbp, which starts with its length as a u64, supplies the
+    text up to the pointer's value. Each message is stored after the routine.
+*/
+fn emit_dump_routine<'a, A: Assembler<'a>>(
+    asm: &mut A,
+    context: &Context<'_, A::Label>,
+    return_address: A::Address,
+    digits: A::Address,
+    buffer: A::Address,
+) {
+    asm.label(context.dump_tape);
+    asm.mov_rax_addr(return_address);
+    asm.mov_qword_ptr_rax_r15();
+
+    // Flush any buffered output, so that the dump comes after whatever the program wrote before the `#`
+    {
+        let skip_flush = asm.allocate_label();
+        asm.cmp_r13_u32(0);
+        asm.je(skip_flush);
+        emit_flush(asm, context);
+        asm.label(skip_flush);
+    }
+
+    // Copy the message to the start of the buffer; from here on, rdi points to the end of the text so far
+    asm.mov_rdi_addr(buffer);
+    asm.mov_rcx_qword_ptr_rbp();
+    asm.lea_rsi_ptr_rbp_plus_i8(8);
+    asm.rep_movsb();
+
+    asm.mov_rax_r8();
+    emit_append_decimal(asm, digits);
+    emit_append_text(asm, ", cells ");
+
+    // The first cell shown, in rbp, is DEBUG_RADIUS cells left of the pointer, or else the first cell of the tape
+    {
+        let clamped = asm.allocate_label();
+        asm.mov_rbp_r8();
+        asm.sub_rbp_i8(DEBUG_RADIUS as i8);
+        asm.jae(clamped);
+        asm.xor_rbp_rbp();
+        asm.label(clamped);
+    }
+
+    asm.mov_rax_rbp();
+    emit_append_decimal(asm, digits);
+    emit_append_text(asm, " to ");
+
+    // The last, in r11, is DEBUG_RADIUS cells right of the pointer, or else the last cell of the tape
+    {
+        let clamped = asm.allocate_label();
+        asm.mov_r11_r8();
+        asm.add_r11_i8(DEBUG_RADIUS as i8);
+        asm.cmp_r11_r9();
+        asm.jb(clamped);
+        asm.mov_r11_r9();
+        asm.dec_r11();
+        asm.label(clamped);
+    }
+
+    asm.mov_rax_r11();
+    emit_append_decimal(asm, digits);
+    emit_append_text(asm, ":");
+
+    // Each cell in turn, with the current cell in brackets
+    let top = asm.allocate_label();
+    let done = asm.allocate_label();
+
+    asm.label(top);
+    emit_append_text(asm, " ");
+
+    {
+        let skip_bracket = asm.allocate_label();
+        asm.cmp_rbp_r8();
+        asm.jne(skip_bracket);
+        emit_append_text(asm, "[");
+        asm.label(skip_bracket);
+    }
+
+    match context.options.cell_width {
+        CellWidth::Bits8 => asm.movzx_eax_byte_ptr_rbx_plus_rbp(),
+        CellWidth::Bits16 => asm.movzx_eax_word_ptr_rbx_plus_rbp_times_2(),
+        CellWidth::Bits32 => asm.mov_eax_dword_ptr_rbx_plus_rbp_times_4(),
+        CellWidth::Bits64 => asm.mov_rax_qword_ptr_rbx_plus_rbp_times_8(),
+    }
+
+    emit_append_decimal(asm, digits);
+
+    {
+        let skip_bracket = asm.allocate_label();
+        asm.cmp_rbp_r8();
+        asm.jne(skip_bracket);
+        emit_append_text(asm, "]");
+        asm.label(skip_bracket);
+    }
+
+    asm.cmp_rbp_r11();
+    asm.je(done);
+    asm.inc_rbp();
+    asm.jmp(top);
+
+    asm.label(done);
+    emit_append_text(asm, "\n");
+
+    // As when reporting an error, nothing can be done if this fails, so its result is ignored
+    asm.mov_rsi_addr(buffer);
+    asm.mov_rdx_rdi();
+    asm.sub_rdx_rsi();
+    asm.mov_rax_u32(0x01); // sys_write
+    asm.mov_rdi_u32(0x02); // Standard error
+    asm.syscall();
+
+    asm.mov_rax_addr(return_address);
+    asm.mov_r15_qword_ptr_rax();
+    asm.jmp_r15();
+
+    for (label, message) in &context.debug_messages {
+        asm.label(*label);
+        asm.data(&(message.len() as u64).to_le_bytes());
+        asm.data(message.as_bytes());
+    }
+}
+
+// Append the decimal representation of rax to the text ending at rdi, formatting it from right to left in the
+// digits buffer; clobbers rax, rcx, rdx, rsi and r15
+fn emit_append_decimal<'a, A: Assembler<'a>>(asm: &mut A, digits: A::Address) {
+    let top = asm.allocate_label();
+
+    asm.mov_rsi_addr(digits);
+    asm.add_rsi_i8(MAX_DIGITS as i8);
+    asm.mov_rcx_u32(10);
+    asm.xor_r15_r15();
+
+    // Divide by 10 until nothing is left, counting the digits in r15; there's always at least one
+    asm.label(top);
+    asm.xor_rdx_rdx();
+    asm.div_rcx();
+    asm.add_dl_u8(b'0');
+    asm.dec_rsi();
+    asm.mov_byte_ptr_rsi_dl();
+    asm.inc_r15();
+    asm.test_rax_rax();
+    asm.jne(top);
+
+    asm.mov_rcx_r15();
+    asm.rep_movsb();
+}
+
+// Append some fixed text to the text ending at rdi
+fn emit_append_text<'a, A: Assembler<'a>>(asm: &mut A, text: &str) {
+    for byte in text.bytes() {
+        asm.mov_byte_ptr_rdi_u8(byte);
+        asm.inc_rdi();
+    }
 }
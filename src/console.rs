@@ -0,0 +1,208 @@
+use std::fmt::Write as _;
+use std::io;
+use std::io::Write;
+
+/*
+    Pieces shared by the interactive modes, i.e. the REPL and the debugger. Both read
+    their commands from the stream the program takes its input from, and write to the
+    stream the program writes its output to, so their own output has to be fitted in
+    around the program's. Both show the tape in the same two ways: the cells around the
+    tape pointer,
+
+          0   1   2   3   4
+         72 101   0   0   0
+              ^
+
+    and a dump of a range of cells, a row at a time.
+*/
+
+// The number of cells shown on either side of the tape pointer
+const RADIUS: usize = 4;
+
+// The number of cells on each row of a dump
+const DUMP_WIDTH: usize = 16;
+
+// The program's output, which tracks whether the last byte written ended a line, so that the interactive mode's own
+// output can start on a fresh one
+pub struct Output<W> {
+    inner: W,
+    at_line_start: bool,
+}
+
+impl<W: Write> Output<W> {
+    pub fn new(inner: W) -> Self {
+        Self {
+            inner,
+            at_line_start: true,
+        }
+    }
+
+    // Write some text of the interactive mode's own, starting on a new line if the program left one unfinished
+    pub fn print(&mut self, text: &str) -> io::Result<()> {
+        if !self.at_line_start {
+            self.write_all(b"\n")?;
+        }
+
+        self.write_all(text.as_bytes())
+    }
+
+    // Print a prompt and read a line in response, which the terminal echoes, leaving the cursor at the start of the
+    // next line; the line is appended to the buffer, and the number of bytes read (0 at the end of input) returned
+    pub fn prompt<R: io::BufRead>(&mut self, prompt: &str, input: &mut R, line: &mut Vec<u8>) -> io::Result<usize> {
+        self.print(prompt)?;
+        self.flush()?;
+
+        let length = input.read_until(b'\n', line)?;
+        self.at_line_start = length > 0;
+
+        Ok(length)
+    }
+}
+
+impl<W: Write> Write for Output<W> {
+    fn write(&mut self, bytes: &[u8]) -> io::Result<usize> {
+        let written = self.inner.write(bytes)?;
+
+        if written > 0 {
+            self.at_line_start = bytes[written - 1] == b'\n';
+        }
+
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+// The cells around the tape pointer, with their indices above and a caret below the current cell
+pub fn neighborhood(tape: &[u64], pointer: usize) -> String {
+    let start = pointer.saturating_sub(RADIUS);
+    let end = pointer.saturating_add(RADIUS).min(tape.len() - 1);
+
+    let mut indices = String::new();
+    let mut values = String::new();
+    let mut marker = String::new();
+
+    for (index, cell) in tape.iter().enumerate().take(end + 1).skip(start) {
+        let value = cell.to_string();
+        let width = value.len().max(index.to_string().len());
+        let mark = if index == pointer { "^" } else { "" };
+
+        write!(indices, " {:>width$}", index, width = width).unwrap();
+        write!(values, " {:>width$}", value, width = width).unwrap();
+        write!(marker, " {:>width$}", mark, width = width).unwrap();
+    }
+
+    format!("{}\n{}\n{}\n", indices, values, marker.trim_end())
+}
+
+// A dump of the cells given by a command's arguments: from START to END, 16 cells from START, or with neither, the
+// whole tape up to its last nonzero cell. The command's name is used in error messages
+pub fn dump(tape: &[u64], pointer: usize, command: &str, arguments: &[&str]) -> Result<String, String> {
+    let last = tape.len() - 1;
+
+    let (start, end) = match *arguments {
+        [] => (0, tape.iter().rposition(|&cell| cell != 0).unwrap_or(0)),
+        [start] => {
+            let start = parse_cell(start)?;
+            (start, start.saturating_add(DUMP_WIDTH - 1))
+        }
+        [start, end] => (parse_cell(start)?, parse_cell(end)?),
+        _ => return Err(format!("'{}' takes at most two arguments", command)),
+    };
+
+    if start > last {
+        return Err(format!("cell {} is past the end of the tape", start));
+    }
+
+    if start > end {
+        return Err(format!("cell {} comes after cell {}", start, end));
+    }
+
+    let end = end.min(last);
+    let cells = &tape[start..=end];
+    let index_width = end.to_string().len();
+    let value_width = cells.iter().map(|cell| cell.to_string().len()).max().unwrap();
+
+    let mut text = String::new();
+
+    for (row, chunk) in cells.chunks(DUMP_WIDTH).enumerate() {
+        write!(text, "{:>width$}:", start + row * DUMP_WIDTH, width = index_width).unwrap();
+
+        for cell in chunk {
+            write!(text, " {:>width$}", cell, width = value_width).unwrap();
+        }
+
+        text.push('\n');
+    }
+
+    writeln!(text, "pointer at cell {}", pointer).unwrap();
+    Ok(text)
+}
+
+// Split a command's arguments on whitespace, except within a quoted character, so that `' '` is a single argument
+pub fn split_arguments(text: &str) -> Vec<&str> {
+    let mut arguments = vec![];
+    let mut rest = text.trim_start();
+
+    while !rest.is_empty() {
+        let mut characters = rest.char_indices();
+
+        let end = match (characters.next(), characters.next(), characters.next()) {
+            (Some((_, '\'')), Some(_), Some((index, '\''))) => index + 1,
+            _ => rest.find(char::is_whitespace).unwrap_or(rest.len()),
+        };
+
+        arguments.push(&rest[..end]);
+        rest = rest[end..].trim_start();
+    }
+
+    arguments
+}
+
+pub fn parse_cell(text: &str) -> Result<usize, String> {
+    text.parse().map_err(|_| format!("invalid cell '{}'", text))
+}
+
+// Values are decimal, and may be negative or as large as the widest cell; a quoted character stands for its code
+pub fn parse_value(text: &str) -> Result<i64, String> {
+    let mut characters = text.chars();
+
+    let value = match (
+        characters.next(),
+        characters.next(),
+        characters.next(),
+        characters.next(),
+    ) {
+        (Some('\''), Some(character), Some('\''), None) => Some(i64::from(u32::from(character))),
+        _ => text
+            .parse::<i64>()
+            .ok()
+            .or_else(|| text.parse::<u64>().ok().map(|value| value as i64)),
+    };
+
+    value.ok_or_else(|| format!("invalid value '{}'", text))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn arguments() {
+        assert_eq!(split_arguments(""), Vec::<&str>::new());
+        assert_eq!(split_arguments("  set 3\t'a' 10 "), ["set", "3", "'a'", "10"]);
+        assert_eq!(split_arguments("set 0 ' ' '\t''''"), ["set", "0", "' '", "'\t'", "'''"]);
+        assert_eq!(split_arguments("'é' '"), ["'é'", "'"]);
+    }
+
+    #[test]
+    fn values() {
+        assert_eq!(parse_value("' '"), Ok(32));
+        assert_eq!(parse_value("'€'"), Ok(0x20ac));
+        assert_eq!(parse_value("-1"), Ok(-1));
+        assert_eq!(parse_value("18446744073709551615"), Ok(-1));
+        assert!(parse_value("'ab'").is_err());
+    }
+}
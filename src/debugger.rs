@@ -0,0 +1,468 @@
+use std::fmt::Write as _;
+use std::io;
+use std::io::{BufRead, Write};
+
+use crate::console;
+use crate::console::Output;
+use crate::diagnostic::Renderer;
+use crate::interpreter::{Interpreter, RunError};
+use crate::ir;
+use crate::ir::{Node, Op};
+use crate::options::Options;
+use crate::parser::ParseError;
+use crate::stream::{Position, Span, Stream};
+
+/*
+    The debugger runs a program on the interpreter a step at a time, where a step is a
+    single command: a run of moves or increments, `.`, `,` or `#`, or the test made by a
+    loop's `[` or `]`. Programs are debugged unoptimized, so that every step corresponds
+    to something in the source.
+
+    Rather than recursing into loops as the interpreter does, the debugger keeps a stack
+    of frames, one for the program and one for each loop it's inside; each frame records
+    the next node to run in its sequence. When a loop frame runs off the end of its body,
+    the next step is the loop's `]`.
+
+    While the program runs freely, it stops before any step with a breakpoint on it, at
+    each `#`, and after any step which changes a watched cell. Commands are read from the
+    stream the program takes its input from, as in the REPL.
+*/
+
+const PROMPT: &str = "(debug) ";
+
+// Every command and abbreviation, for telling a misused command from an unknown one
+const COMMANDS: [&str; 22] = [
+    "step", "s", "next", "n", "continue", "c", "break", "b", "delete", "d", "watch", "w", "unwatch", "info", "i",
+    "tape", "t", "where", "help", "h", "quit", "q",
+];
+
+const HELP: &str = "\
+Commands:
+  step [N], s [N]      Run N steps [default: 1]; a step is a single command,
+                       or the test made by a loop's [ or ]
+  next, n              Run a step, running a whole loop if the step enters one
+  continue, c          Run until a breakpoint, a '#', a watched cell changes,
+                       or the program finishes
+  break LINE[:COLUMN], b LINE[:COLUMN]
+                       Set a breakpoint on the first command at or after the
+                       given position
+  delete [N], d [N]    Delete breakpoint N, or every breakpoint
+  watch CELL, w CELL   Stop whenever the value of CELL changes
+  unwatch CELL         Stop watching CELL
+  info, i              List breakpoints and watched cells
+  tape [START [END]], t [START [END]]
+                       Show the cells around the tape pointer, or the cells
+                       from START to END (16 cells with only START)
+  where                Show the position of the next step
+  help, h              Print this message
+  quit, q              Exit, as does the end of input
+An empty line repeats the last step, next or continue.
+";
+
+// Parse a program for debugging: unoptimized, with `#` as a command
+pub fn parse<R: io::Read>(source: R, options: &Options) -> Result<Vec<Node>, ParseError> {
+    ir::build(&mut Stream::with_tab_width(source, options.tab_width), true)
+}
+
+pub struct Debugger<'a, R, W> {
+    interpreter: Interpreter<R, Output<W>>,
+    renderer: Renderer<'a>,
+    // Empty once the program has finished, or stopped with an error
+    frames: Vec<Frame<'a>>,
+    // The span of every step in the program, in source order
+    steps: Vec<Span>,
+    // Numbered from 1; deleted breakpoints leave a gap
+    breakpoints: Vec<Option<Position>>,
+    watchpoints: Vec<usize>,
+    repeated: Option<Motion>,
+}
+
+struct Frame<'a> {
+    nodes: &'a [Node],
+    index: usize,
+    // The position of the loop's `]`, or None for the program itself
+    end: Option<Position>,
+}
+
+// Ways of resuming the program
+#[derive(Clone, Copy)]
+enum Motion {
+    Step(u64),
+    Next,
+    Continue,
+}
+
+impl<'a, R: BufRead, W: Write> Debugger<'a, R, W> {
+    // The renderer quotes the program's source when showing where it's stopped
+    pub fn new(
+        program: &'a [Node],
+        renderer: Renderer<'a>,
+        options: &Options,
+        input: R,
+        output: W,
+    ) -> Result<Self, RunError> {
+        let mut steps = vec![];
+        collect_steps(program, &mut steps);
+
+        let mut debugger = Self {
+            interpreter: Interpreter::new(options, input, Output::new(output))?,
+            renderer,
+            frames: vec![Frame {
+                nodes: program,
+                index: 0,
+                end: None,
+            }],
+            steps,
+            breakpoints: vec![],
+            watchpoints: vec![],
+            repeated: None,
+        };
+
+        debugger.finish_frames();
+        Ok(debugger)
+    }
+
+    // Read and carry out commands until the end of input or quit. Errors in the program, like moving off the end of
+    // the tape, stop it without ending the session, so the only errors returned are failures to read or write
+    pub fn run(&mut self) -> Result<(), RunError> {
+        self.show_stop(None)?;
+
+        loop {
+            let mut line = vec![];
+            let (input, output) = self.interpreter.streams_mut();
+
+            if output.prompt(PROMPT, input, &mut line).map_err(RunError::Read)? == 0 {
+                return self.print("");
+            }
+
+            let line = String::from_utf8_lossy(&line);
+            let words: Vec<&str> = line.split_whitespace().collect();
+
+            let result = match *words.as_slice() {
+                [] => match self.repeated {
+                    Some(motion) => self.resume(motion).map(Ok),
+                    None => Ok(Ok(())),
+                },
+                ["quit"] | ["q"] => return Ok(()),
+                [command, ref arguments @ ..] => self.command(command, arguments),
+            };
+
+            if let Err(message) = result? {
+                self.print(&format!("error: {}\n", message))?;
+            }
+        }
+    }
+
+    // Commands return Ok(Err(message)) for mistakes in their arguments, which don't end the session
+    fn command(&mut self, command: &str, arguments: &[&str]) -> Result<Result<(), String>, RunError> {
+        let motion = match (command, arguments) {
+            ("step", []) | ("s", []) => Motion::Step(1),
+            ("step", [count]) | ("s", [count]) => match count.parse() {
+                Ok(count) if count > 0 => Motion::Step(count),
+                _ => return Ok(Err(format!("invalid step count '{}'", count))),
+            },
+            ("next", []) | ("n", []) => Motion::Next,
+            ("continue", []) | ("c", []) => Motion::Continue,
+            ("break", [position]) | ("b", [position]) => return self.add_breakpoint(position),
+            ("delete", []) | ("d", []) => {
+                self.breakpoints.clear();
+                return Ok(Ok(()));
+            }
+            ("delete", [number]) | ("d", [number]) => return Ok(self.delete_breakpoint(number)),
+            ("watch", [cell]) | ("w", [cell]) => return self.watch(cell),
+            ("unwatch", [cell]) => return Ok(self.unwatch(cell)),
+            ("info", []) | ("i", []) => return self.info().map(Ok),
+            ("tape", []) | ("t", []) => return self.show_tape().map(Ok),
+            ("tape", arguments) | ("t", arguments) => {
+                let tape = self.interpreter.tape();
+                let dump = console::dump(tape, self.interpreter.pointer(), "tape", arguments);
+                return dump.map_or_else(|message| Ok(Err(message)), |text| self.print(&text).map(Ok));
+            }
+            ("where", []) => return self.show_position().map(Ok),
+            ("help", []) | ("h", []) => return self.print(HELP).map(Ok),
+            _ if COMMANDS.contains(&command) => {
+                return Ok(Err(format!("wrong arguments for '{}' (try 'help')", command)))
+            }
+            _ => return Ok(Err(format!("unknown command '{}' (try 'help')", command))),
+        };
+
+        self.repeated = Some(motion);
+        self.resume(motion).map(Ok)
+    }
+
+    // Run the program until the motion is complete or something stops it, then show where it stopped
+    fn resume(&mut self, motion: Motion) -> Result<(), RunError> {
+        if self.frames.is_empty() {
+            return self.print("the program has ended\n");
+        }
+
+        let depth = self.frames.len();
+        let mut watched = self.watched_values();
+        let mut count = 0;
+
+        loop {
+            if let Err(error) = self.step() {
+                return match error {
+                    RunError::Read(_) | RunError::Write(_) => Err(error),
+                    error => {
+                        self.frames.clear();
+                        self.print(&format!("error: {}\n", error))?;
+                        self.show_tape()
+                    }
+                };
+            }
+
+            count += 1;
+
+            let changes = self.watched_values();
+            let changed = watched.iter().zip(&changes).find(|(before, after)| before.1 != after.1);
+
+            if let Some((&(cell, before), &(_, after))) = changed {
+                return self.show_stop(Some(format!("cell {} changed from {} to {}", cell, before, after)));
+            }
+
+            watched = changes;
+
+            if self.frames.is_empty() {
+                return self.show_stop(None);
+            }
+
+            let position = self.position().unwrap();
+
+            if let Some(number) = self
+                .breakpoints
+                .iter()
+                .position(|&breakpoint| breakpoint == Some(position))
+            {
+                return self.show_stop(Some(format!("breakpoint {}", number + 1)));
+            }
+
+            if self.at_debug_command() {
+                return self.show_stop(Some("stopped at '#'".to_string()));
+            }
+
+            let done = match motion {
+                Motion::Step(steps) => count >= steps,
+                Motion::Next => self.frames.len() <= depth,
+                Motion::Continue => false,
+            };
+
+            if done {
+                return self.show_stop(None);
+            }
+        }
+    }
+
+    // Run a single step
+    fn step(&mut self) -> Result<(), RunError> {
+        let cell = self.interpreter.cell();
+        let frame = self.frames.last_mut().unwrap();
+        let nodes = frame.nodes;
+
+        match nodes.get(frame.index) {
+            // At a loop's `]`, either go round again or leave the loop
+            None if cell != 0 => frame.index = 0,
+            None => {
+                self.frames.pop();
+                self.frames.last_mut().unwrap().index += 1;
+            }
+            Some(node) => match &node.op {
+                Op::Loop(body) if cell != 0 => {
+                    self.frames.push(Frame {
+                        nodes: body,
+                        index: 0,
+                        end: Some(node.span.last_character().start),
+                    });
+                }
+                Op::Loop(_) => frame.index += 1,
+                _ => {
+                    // Advance only once the command has run, so that a failed command is where the program stopped
+                    self.interpreter.execute(node)?;
+                    frame.index += 1;
+                }
+            },
+        }
+
+        self.finish_frames();
+        Ok(())
+    }
+
+    // Once the program has run off its end, it's finished
+    fn finish_frames(&mut self) {
+        if let [frame] = self.frames.as_slice() {
+            if frame.index == frame.nodes.len() {
+                self.frames.clear();
+            }
+        }
+    }
+
+    // The position of the next step, unless the program has finished
+    fn position(&self) -> Option<Position> {
+        let frame = self.frames.last()?;
+
+        match frame.nodes.get(frame.index) {
+            Some(node) => Some(node.span.start),
+            None => frame.end,
+        }
+    }
+
+    fn at_debug_command(&self) -> bool {
+        let frame = self.frames.last().unwrap();
+        matches!(frame.nodes.get(frame.index), Some(Node { op: Op::Debug, .. }))
+    }
+
+    fn watched_values(&self) -> Vec<(usize, u64)> {
+        let tape = self.interpreter.tape();
+
+        self.watchpoints
+            .iter()
+            .map(|&cell| (cell, tape.get(cell).copied().unwrap_or(0)))
+            .collect()
+    }
+
+    fn add_breakpoint(&mut self, text: &str) -> Result<Result<(), String>, RunError> {
+        let (line, column): (Result<usize, _>, Result<usize, _>) = match text.split_once(':') {
+            Some((line, column)) => (line.parse(), column.parse()),
+            None => (text.parse(), Ok(1)),
+        };
+
+        let target = match (line, column) {
+            (Ok(line), Ok(column)) if line > 0 && column > 0 => (line, column),
+            _ => {
+                return Ok(Err(format!(
+                    "invalid position '{}' (expected LINE or LINE:COLUMN)",
+                    text
+                )))
+            }
+        };
+
+        // The first step which ends after the target, i.e. the one containing it or else the next one
+        let step = self.steps.iter().find(|span| (span.end.line, span.end.column) > target);
+
+        let position = match step {
+            Some(span) => span.start,
+            None => {
+                return Ok(Err(format!(
+                    "no command at or after line {}, column {}",
+                    target.0, target.1
+                )))
+            }
+        };
+
+        self.breakpoints.push(Some(position));
+        let message = format!(
+            "breakpoint {} at line {}, column {}\n",
+            self.breakpoints.len(),
+            position.line,
+            position.column
+        );
+        self.print(&message).map(Ok)
+    }
+
+    fn delete_breakpoint(&mut self, text: &str) -> Result<(), String> {
+        let breakpoint = text
+            .parse::<usize>()
+            .ok()
+            .and_then(|number| self.breakpoints.get_mut(number.checked_sub(1)?))
+            .filter(|breakpoint| breakpoint.is_some());
+
+        match breakpoint {
+            Some(breakpoint) => {
+                *breakpoint = None;
+                Ok(())
+            }
+            None => Err(format!("no breakpoint '{}'", text)),
+        }
+    }
+
+    fn watch(&mut self, text: &str) -> Result<Result<(), String>, RunError> {
+        let cell = match console::parse_cell(text) {
+            Ok(cell) => cell,
+            Err(message) => return Ok(Err(message)),
+        };
+
+        if !self.watchpoints.contains(&cell) {
+            self.watchpoints.push(cell);
+        }
+
+        let value = self.interpreter.tape().get(cell).copied().unwrap_or(0);
+        self.print(&format!("watching cell {}, currently {}\n", cell, value))
+            .map(Ok)
+    }
+
+    fn unwatch(&mut self, text: &str) -> Result<(), String> {
+        let cell = console::parse_cell(text)?;
+
+        match self.watchpoints.iter().position(|&watched| watched == cell) {
+            Some(index) => {
+                self.watchpoints.remove(index);
+                Ok(())
+            }
+            None => Err(format!("cell {} isn't being watched", cell)),
+        }
+    }
+
+    fn info(&mut self) -> Result<(), RunError> {
+        let mut text = String::new();
+
+        for (number, breakpoint) in self.breakpoints.iter().enumerate() {
+            if let Some(position) = breakpoint {
+                let (line, column) = (position.line, position.column);
+                writeln!(text, "breakpoint {} at line {}, column {}", number + 1, line, column).unwrap();
+            }
+        }
+
+        for cell in &self.watchpoints {
+            writeln!(text, "watching cell {}", cell).unwrap();
+        }
+
+        if text.is_empty() {
+            text.push_str("no breakpoints or watched cells\n");
+        }
+
+        self.print(&text)
+    }
+
+    // Show why the program stopped, if there's anything to say beyond that a motion was completed, then where it
+    // stopped and the tape around the pointer
+    fn show_stop(&mut self, reason: Option<String>) -> Result<(), RunError> {
+        if let Some(reason) = reason {
+            self.print(&format!("{}\n", reason))?;
+        }
+
+        self.show_position()?;
+        self.show_tape()
+    }
+
+    fn show_position(&mut self) -> Result<(), RunError> {
+        match self.position() {
+            Some(position) => {
+                let snippet = self.renderer.render_position(position);
+                self.print(&snippet)
+            }
+            None => self.print("the program has ended\n"),
+        }
+    }
+
+    fn show_tape(&mut self) -> Result<(), RunError> {
+        let neighborhood = console::neighborhood(self.interpreter.tape(), self.interpreter.pointer());
+        self.print(&neighborhood)
+    }
+
+    fn print(&mut self, text: &str) -> Result<(), RunError> {
+        self.interpreter.output_mut().print(text).map_err(RunError::Write)
+    }
+}
+
+fn collect_steps(nodes: &[Node], steps: &mut Vec<Span>) {
+    for node in nodes {
+        match &node.op {
+            Op::Loop(body) => {
+                steps.push(node.span.first_character());
+                collect_steps(body, steps);
+                steps.push(node.span.last_character());
+            }
+            _ => steps.push(node.span),
+        }
+    }
+}
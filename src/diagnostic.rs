@@ -0,0 +1,363 @@
+use std::fmt::Write;
+use std::num::NonZeroUsize;
+use std::str;
+
+use crate::parser::{ParseError, SyntaxError};
+use crate::stream::{next_column, Position, DEFAULT_TAB_WIDTH};
+
+/*
+    Diagnostics are rendered in the style of rustc:
+
+        error: unclosed `[`
+         --> program.bf:1:1
+          |
+        1 | [[-]
+          | ^
+          ...
+        4 | >[<+
+          |  - loop opened here is also unclosed
+
+    The primary position is marked with carets, and any notes attached to the error
+    with dashes. Positions on the same line share a single copy of the source line.
+*/
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Style {
+    Plain,
+    Colored,
+}
+
+// ANSI escape sequences used by the colored style
+const RESET: &str = "\x1b[0m";
+const BOLD: &str = "\x1b[1m";
+const BOLD_RED: &str = "\x1b[1;31m";
+const BOLD_BLUE: &str = "\x1b[1;34m";
+
+pub struct Renderer<'a> {
+    file_name: &'a str,
+    source: &'a [u8],
+    tab_width: NonZeroUsize,
+    style: Style,
+}
+
+// A position to be marked in the rendered snippet
+struct Label<'a> {
+    position: Position,
+    message: Option<&'a str>,
+    primary: bool,
+}
+
+impl<'a> Renderer<'a> {
+    pub fn new(file_name: &'a str, source: &'a [u8], style: Style) -> Self {
+        Self {
+            file_name,
+            source,
+            tab_width: DEFAULT_TAB_WIDTH,
+            style,
+        }
+    }
+
+    // The tab width must match the one used while parsing, or carets won't line up with the source
+    pub fn with_tab_width(mut self, tab_width: NonZeroUsize) -> Self {
+        self.tab_width = tab_width;
+        self
+    }
+
+    pub fn render(&self, error: &ParseError) -> String {
+        match error {
+            ParseError::Io(error) => {
+                let mut output = self.headline(&error.to_string());
+                writeln!(output, "{}", self.location_line(None)).unwrap();
+                output
+            }
+            ParseError::Syntax(error) => self.render_syntax_error(error),
+        }
+    }
+
+    // A snippet pointing out a position in the source, without any message; used to show where a program is
+    pub fn render_position(&self, position: Position) -> String {
+        let gutter_width = position.line.to_string().len();
+        let blank_gutter = " ".repeat(gutter_width);
+        let gutter = self.paint(&format!("{} |", blank_gutter), BOLD_BLUE);
+
+        let mut output = String::new();
+        writeln!(output, "{}{}", blank_gutter, self.location_line(Some(position))).unwrap();
+        writeln!(output, "{}", gutter).unwrap();
+
+        let number = format!("{} |", position.line);
+        let text = self.line_text(position);
+        writeln!(output, "{} {}", self.paint(&number, BOLD_BLUE), text.trim_end()).unwrap();
+
+        let padding = " ".repeat(position.column - 1);
+        writeln!(output, "{} {}{}", gutter, padding, self.paint("^", BOLD_BLUE)).unwrap();
+
+        output
+    }
+
+    fn render_syntax_error(&self, error: &SyntaxError) -> String {
+        let mut labels = vec![Label {
+            position: error.position(),
+            message: None,
+            primary: true,
+        }];
+
+        labels.extend(error.notes().iter().map(|note| Label {
+            position: note.position,
+            message: Some(note.message),
+            primary: false,
+        }));
+
+        labels.sort_by_key(|label| (label.position.line, label.position.column));
+
+        let gutter_width = labels
+            .iter()
+            .map(|label| label.position.line)
+            .max()
+            .unwrap()
+            .to_string()
+            .len();
+        let blank_gutter = " ".repeat(gutter_width);
+
+        let mut output = self.headline(error.message());
+        writeln!(output, "{}{}", blank_gutter, self.location_line(Some(error.position()))).unwrap();
+        writeln!(output, "{} {}", blank_gutter, self.paint("|", BOLD_BLUE)).unwrap();
+
+        let mut previous_line = None;
+
+        for label in &labels {
+            let line = label.position.line;
+
+            if previous_line != Some(line) {
+                if previous_line.is_some_and(|previous| line > previous + 1) {
+                    writeln!(output, "{}", self.paint("...", BOLD_BLUE)).unwrap();
+                }
+
+                let text = self.line_text(label.position);
+                let number = format!("{:>width$} |", line, width = gutter_width);
+                writeln!(output, "{} {}", self.paint(&number, BOLD_BLUE), text.trim_end()).unwrap();
+                previous_line = Some(line);
+            }
+
+            // Labels on the same line are drawn one per row, in column order
+            let (marker, color) = if label.primary {
+                ("^", BOLD_RED)
+            } else {
+                ("-", BOLD_BLUE)
+            };
+            let mut annotation = marker.to_string();
+
+            if let Some(message) = label.message {
+                annotation.push(' ');
+                annotation.push_str(message);
+            }
+
+            let padding = " ".repeat(label.position.column - 1);
+            let gutter = format!("{} |", blank_gutter);
+            writeln!(
+                output,
+                "{} {}{}",
+                self.paint(&gutter, BOLD_BLUE),
+                padding,
+                self.paint(&annotation, color)
+            )
+            .unwrap();
+        }
+
+        writeln!(output, "{} {}", blank_gutter, self.paint("|", BOLD_BLUE)).unwrap();
+
+        output
+    }
+
+    fn headline(&self, message: &str) -> String {
+        format!(
+            "{}{}\n",
+            self.paint("error", BOLD_RED),
+            self.paint(&format!(": {}", message), BOLD)
+        )
+    }
+
+    fn location_line(&self, position: Option<Position>) -> String {
+        let location = match position {
+            Some(position) => format!("{}:{}:{}", self.file_name, position.line, position.column),
+            None => self.file_name.to_string(),
+        };
+
+        format!("{} {}", self.paint("-->", BOLD_BLUE), location)
+    }
+
+    // The source line containing the given position, with tabs expanded so that columns line up. Columns are counted
+    // as Stream counts them, with anything which isn't valid UTF-8 shown as a replacement character per column
+    fn line_text(&self, position: Position) -> String {
+        let is_break = |byte: &u8| *byte == b'\n' || *byte == b'\r';
+        let offset = position.offset.min(self.source.len());
+
+        let start = self.source[..offset]
+            .iter()
+            .rposition(is_break)
+            .map_or(0, |index| index + 1);
+        let end = self.source[offset..]
+            .iter()
+            .position(is_break)
+            .map_or(self.source.len(), |index| offset + index);
+
+        let line = &self.source[start..end];
+        let mut text = String::new();
+        let mut column = 1;
+        let mut index = 0;
+
+        while index < line.len() {
+            // A character is any byte but a continuation byte, together with the continuation bytes after it
+            let length = 1 + line[index + 1..]
+                .iter()
+                .take_while(|byte| (0x80..=0xbf).contains(*byte))
+                .count();
+            let bytes = &line[index..index + length];
+            let next = bytes
+                .iter()
+                .fold(column, |column, &byte| next_column(column, byte, self.tab_width));
+
+            match str::from_utf8(bytes) {
+                Ok("\t") => text.extend((column..next).map(|_| ' ')),
+                Ok(character) if next == column + 1 => text.push_str(character),
+                _ => text.extend((column..next).map(|_| char::REPLACEMENT_CHARACTER)),
+            }
+
+            column = next;
+            index += length;
+        }
+
+        text
+    }
+
+    fn paint(&self, text: &str, color: &str) -> String {
+        match self.style {
+            Style::Plain => text.to_string(),
+            Style::Colored => format!("{}{}{}", color, text, RESET),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ir;
+    use crate::stream::Stream;
+
+    fn render(source: &[u8]) -> String {
+        let error = ir::build(&mut Stream::new(source), false).unwrap_err();
+        Renderer::new("test.b", source, Style::Plain).render(&error)
+    }
+
+    #[test]
+    fn unmatched_loop_end() {
+        assert_eq!(
+            render(b"+]"),
+            "\
+error: unmatched `]`
+ --> test.b:1:2
+  |
+1 | +]
+  |  ^
+  |
+"
+        );
+    }
+
+    #[test]
+    fn notes_on_the_same_line() {
+        assert_eq!(
+            render(b"[+[-["),
+            "\
+error: unclosed `[`
+ --> test.b:1:1
+  |
+1 | [+[-[
+  | ^
+  |   - loop opened here is also unclosed
+  |     - loop opened here is also unclosed
+  |
+"
+        );
+    }
+
+    #[test]
+    fn notes_on_other_lines() {
+        // Lines which aren't adjacent are separated by an ellipsis
+        assert_eq!(
+            render(b"[\n+[-]\n  [>"),
+            "\
+error: unclosed `[`
+ --> test.b:1:1
+  |
+1 | [
+  | ^
+...
+3 |   [>
+  |   - loop opened here is also unclosed
+  |
+"
+        );
+        assert_eq!(
+            render(b"[\n[\n"),
+            "\
+error: unclosed `[`
+ --> test.b:1:1
+  |
+1 | [
+  | ^
+2 | [
+  | - loop opened here is also unclosed
+  |
+"
+        );
+    }
+
+    #[test]
+    fn tabs() {
+        assert_eq!(
+            render(b"\t+\t]"),
+            "\
+error: unmatched `]`
+ --> test.b:1:17
+  |
+1 |         +       ]
+  |                 ^
+  |
+"
+        );
+
+        let source = b"ab\t\t]";
+        let error = ir::build(
+            &mut Stream::with_tab_width(&source[..], NonZeroUsize::new(4).unwrap()),
+            false,
+        );
+        let renderer = Renderer::new("test.b", source, Style::Plain).with_tab_width(NonZeroUsize::new(4).unwrap());
+        assert_eq!(
+            renderer.render(&error.unwrap_err()),
+            "\
+error: unmatched `]`
+ --> test.b:1:9
+  |
+1 | ab      ]
+  |         ^
+  |
+"
+        );
+    }
+
+    #[test]
+    fn invalid_utf8() {
+        // A stray continuation byte takes no columns, while an invalid byte or truncated sequence takes one
+        assert_eq!(
+            render(b"\x80\xff\xe2\x82["),
+            "\
+error: unclosed `[`
+ --> test.b:1:3
+  |
+1 | \u{fffd}\u{fffd}[
+  |   ^
+  |
+"
+        );
+    }
+}
@@ -0,0 +1,443 @@
+use std::os::unix::ffi::OsStrExt;
+use std::path::Path;
+
+use crate::elf::Section;
+use crate::stream::Position;
+
+/*
+    Debugging information, in the DWARF 4 format, for a program compiled into a single
+    block of code. It takes three sections:
+    - .debug_abbrev, which declares the attributes each kind of entry in .debug_info has
+    - .debug_info, which holds a compilation unit naming the source file and the directory
+      it was compiled from, with a single subprogram covering all of the code
+    - .debug_line, a program for a state machine which generates the table mapping each
+      address in the code to the line and column of the command it was generated from
+
+    That's enough for a debugger to set breakpoints on lines of the source, and to show and
+    step through the command the program is stopped at. There are no variables to describe;
+    the tape can be examined through the registers pointing into it.
+
+    Each row of the line table is given as an offset into the code and the position of the
+    command whose code starts there, with the offsets in ascending order. The code at each
+    offset up to the next row's belongs to the same command.
+*/
+
+const VERSION: u16 = 4;
+
+// Abbreviation codes of the entries in .debug_info
+const ABBREVIATION_COMPILE_UNIT: u8 = 1;
+const ABBREVIATION_SUBPROGRAM: u8 = 2;
+
+// Tags
+const DW_TAG_COMPILE_UNIT: u8 = 0x11;
+const DW_TAG_SUBPROGRAM: u8 = 0x2e;
+
+const DW_CHILDREN_NO: u8 = 0x00;
+const DW_CHILDREN_YES: u8 = 0x01;
+
+// Attributes
+const DW_AT_NAME: u8 = 0x03;
+const DW_AT_STMT_LIST: u8 = 0x10;
+const DW_AT_LOW_PC: u8 = 0x11;
+const DW_AT_HIGH_PC: u8 = 0x12;
+const DW_AT_COMP_DIR: u8 = 0x1b;
+const DW_AT_PRODUCER: u8 = 0x25;
+const DW_AT_EXTERNAL: u8 = 0x3f;
+
+// Attribute forms
+const DW_FORM_ADDR: u8 = 0x01;
+const DW_FORM_DATA8: u8 = 0x07;
+const DW_FORM_STRING: u8 = 0x08;
+const DW_FORM_SEC_OFFSET: u8 = 0x17;
+const DW_FORM_FLAG_PRESENT: u8 = 0x19;
+
+// Line number program opcodes. We stick to the standard opcodes, which are simpler to generate than the special
+// ones, at the cost of a few bytes per row
+const DW_LNS_COPY: u8 = 0x01;
+const DW_LNS_ADVANCE_PC: u8 = 0x02;
+const DW_LNS_ADVANCE_LINE: u8 = 0x03;
+const DW_LNS_SET_COLUMN: u8 = 0x05;
+const DW_LNE_END_SEQUENCE: u8 = 0x01;
+const DW_LNE_SET_ADDRESS: u8 = 0x02;
+
+// Parameters of the line number program's special opcodes, which we never use, but which have to be declared
+const LINE_BASE: i8 = -5;
+const LINE_RANGE: u8 = 14;
+const OPCODE_BASE: u8 = 13;
+
+// The number of operands taken by each standard opcode, from DW_LNS_copy up to DW_LNS_set_isa
+const STANDARD_OPCODE_LENGTHS: [u8; 12] = [0, 1, 1, 1, 1, 0, 0, 0, 1, 0, 0, 1];
+
+// The name of the subprogram covering the code, which is the same as that of the entry point
+const SUBPROGRAM_NAME: &str = "_start";
+
+// The contents of .debug_abbrev, .debug_info and .debug_line, for code of the given size at the given address
+pub fn sections(source: &Path, directory: &Path, address: u64, size: u64, rows: &[(u64, Position)]) -> Vec<Section> {
+    vec![
+        Section::data(".debug_abbrev", abbreviations()),
+        Section::data(".debug_info", info(source, directory, address, size)),
+        Section::data(".debug_line", line_program(source, address, size, rows)),
+    ]
+}
+
+fn abbreviations() -> Vec<u8> {
+    vec![
+        ABBREVIATION_COMPILE_UNIT,
+        DW_TAG_COMPILE_UNIT,
+        DW_CHILDREN_YES,
+        DW_AT_PRODUCER,
+        DW_FORM_STRING,
+        DW_AT_NAME,
+        DW_FORM_STRING,
+        DW_AT_COMP_DIR,
+        DW_FORM_STRING,
+        DW_AT_STMT_LIST,
+        DW_FORM_SEC_OFFSET,
+        DW_AT_LOW_PC,
+        DW_FORM_ADDR,
+        DW_AT_HIGH_PC,
+        DW_FORM_DATA8,
+        0,
+        0,
+        ABBREVIATION_SUBPROGRAM,
+        DW_TAG_SUBPROGRAM,
+        DW_CHILDREN_NO,
+        DW_AT_NAME,
+        DW_FORM_STRING,
+        DW_AT_EXTERNAL,
+        DW_FORM_FLAG_PRESENT,
+        DW_AT_LOW_PC,
+        DW_FORM_ADDR,
+        DW_AT_HIGH_PC,
+        DW_FORM_DATA8,
+        0,
+        0,
+        // End of the abbreviations
+        0,
+    ]
+}
+
+fn info(source: &Path, directory: &Path, address: u64, size: u64) -> Vec<u8> {
+    let producer = format!("brainrust {}", env!("CARGO_PKG_VERSION"));
+
+    let mut unit = vec![];
+    unit.extend(&VERSION.to_le_bytes());
+    unit.extend(&0u32.to_le_bytes()); // Offset of the abbreviations
+    unit.push(8); // Size of an address
+
+    unit.push(ABBREVIATION_COMPILE_UNIT);
+    push_string(&mut unit, producer.as_bytes());
+    push_string(&mut unit, source.as_os_str().as_bytes());
+    push_string(&mut unit, directory.as_os_str().as_bytes());
+    unit.extend(&0u32.to_le_bytes()); // Offset of the line number program
+    unit.extend(&address.to_le_bytes());
+    unit.extend(&size.to_le_bytes()); // With DW_FORM_data8, the high PC is relative to the low PC
+
+    unit.push(ABBREVIATION_SUBPROGRAM);
+    push_string(&mut unit, SUBPROGRAM_NAME.as_bytes());
+    unit.extend(&address.to_le_bytes());
+    unit.extend(&size.to_le_bytes());
+
+    // End of the compilation unit's children
+    unit.push(0);
+
+    with_length(unit)
+}
+
+fn line_program(source: &Path, address: u64, size: u64, rows: &[(u64, Position)]) -> Vec<u8> {
+    let mut header = vec![
+        1, // Minimum instruction length
+        1, // Maximum operations per instruction
+        1, // Whether rows are statements by default
+        LINE_BASE as u8,
+        LINE_RANGE,
+        OPCODE_BASE,
+    ];
+
+    header.extend(&STANDARD_OPCODE_LENGTHS);
+
+    // No include directories beyond the compilation directory
+    header.push(0);
+
+    // A single file, in the compilation directory (unless its path is absolute), with unknown modification time and
+    // length
+    push_string(&mut header, source.as_os_str().as_bytes());
+    push_unsigned(&mut header, 0);
+    push_unsigned(&mut header, 0);
+    push_unsigned(&mut header, 0);
+    header.push(0);
+
+    let mut program = vec![0, 9, DW_LNE_SET_ADDRESS];
+    program.extend(&address.to_le_bytes());
+
+    // The state machine starts at line 1, column 0
+    let mut offset = 0;
+    let mut line = 1;
+
+    for (row_offset, position) in rows {
+        if *row_offset > offset {
+            program.push(DW_LNS_ADVANCE_PC);
+            push_unsigned(&mut program, row_offset - offset);
+            offset = *row_offset;
+        }
+
+        if position.line != line {
+            program.push(DW_LNS_ADVANCE_LINE);
+            push_signed(&mut program, position.line as i64 - line as i64);
+            line = position.line;
+        }
+
+        program.push(DW_LNS_SET_COLUMN);
+        push_unsigned(&mut program, position.column as u64);
+        program.push(DW_LNS_COPY);
+    }
+
+    // The sequence ends at the first address past the code
+    program.push(DW_LNS_ADVANCE_PC);
+    push_unsigned(&mut program, size - offset);
+    program.extend(&[0, 1, DW_LNE_END_SEQUENCE]);
+
+    let mut unit = vec![];
+    unit.extend(&VERSION.to_le_bytes());
+    unit.extend(&(header.len() as u32).to_le_bytes());
+    unit.extend(header);
+    unit.extend(program);
+
+    with_length(unit)
+}
+
+// Prefix a unit with its length, as a 32-bit DWARF unit
+fn with_length(unit: Vec<u8>) -> Vec<u8> {
+    let mut bytes = (unit.len() as u32).to_le_bytes().to_vec();
+    bytes.extend(unit);
+    bytes
+}
+
+fn push_string(bytes: &mut Vec<u8>, string: &[u8]) {
+    bytes.extend(string);
+    bytes.push(0);
+}
+
+// LEB128, a little-endian encoding of variable length, with seven bits to a byte and the high bit set on all but the
+// last byte
+fn push_unsigned(bytes: &mut Vec<u8>, mut value: u64) {
+    loop {
+        let byte = (value & 0x7f) as u8;
+        value >>= 7;
+
+        if value == 0 {
+            bytes.push(byte);
+            return;
+        }
+
+        bytes.push(byte | 0x80);
+    }
+}
+
+// Signed LEB128, which ends once the remaining bits are all copies of the sign bit of the last byte
+fn push_signed(bytes: &mut Vec<u8>, mut value: i64) {
+    loop {
+        let byte = (value & 0x7f) as u8;
+        value >>= 7;
+
+        if (value == 0 && byte & 0x40 == 0) || (value == -1 && byte & 0x40 != 0) {
+            bytes.push(byte);
+            return;
+        }
+
+        bytes.push(byte | 0x80);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::convert::TryInto;
+
+    use super::*;
+    use crate::ir;
+    use crate::ir::{Node, Op};
+    use crate::stream::Stream;
+
+    const ADDRESS: u64 = 0x1000_0000_00b0;
+
+    fn read_unsigned(bytes: &mut &[u8]) -> u64 {
+        let mut value = 0;
+        let mut shift = 0;
+
+        loop {
+            let byte = bytes[0];
+            *bytes = &bytes[1..];
+            value |= u64::from(byte & 0x7f) << shift;
+            shift += 7;
+
+            if byte & 0x80 == 0 {
+                return value;
+            }
+        }
+    }
+
+    fn read_signed(bytes: &mut &[u8]) -> i64 {
+        let mut value = 0;
+        let mut shift = 0;
+
+        loop {
+            let byte = bytes[0];
+            *bytes = &bytes[1..];
+            value |= i64::from(byte & 0x7f) << shift;
+            shift += 7;
+
+            if byte & 0x80 == 0 {
+                // Extend the sign bit of the last byte
+                if shift < 64 && byte & 0x40 != 0 {
+                    value |= -1 << shift;
+                }
+
+                return value;
+            }
+        }
+    }
+
+    // Run a line number program, returning the address, line and column of each row, and the address at which the
+    // sequence ends
+    fn decode_lines(unit: &[u8]) -> (Vec<(u64, usize, usize)>, u64) {
+        assert_eq!(
+            u32::from_le_bytes(unit[..4].try_into().unwrap()) as usize,
+            unit.len() - 4
+        );
+        assert_eq!(u16::from_le_bytes(unit[4..6].try_into().unwrap()), VERSION);
+
+        let header_length = u32::from_le_bytes(unit[6..10].try_into().unwrap()) as usize;
+        let header = &unit[10..10 + header_length];
+        let line_base = i64::from(header[3] as i8);
+        let line_range = u64::from(header[4]);
+        let opcode_base = header[5];
+        let opcode_lengths = &header[6..5 + opcode_base as usize];
+
+        let mut program = &unit[10 + header_length..];
+        let mut rows = vec![];
+        let (mut address, mut line, mut column) = (0, 1, 0);
+
+        loop {
+            let opcode = program[0];
+            program = &program[1..];
+
+            match opcode {
+                0 => {
+                    let length = read_unsigned(&mut program) as usize;
+                    let (extended, operands) = (program[0], &program[1..length]);
+                    program = &program[length..];
+
+                    match extended {
+                        DW_LNE_END_SEQUENCE => {
+                            assert!(program.is_empty());
+                            return (rows, address);
+                        }
+                        DW_LNE_SET_ADDRESS => address = u64::from_le_bytes(operands.try_into().unwrap()),
+                        _ => panic!("unexpected extended opcode {}", extended),
+                    }
+                }
+                DW_LNS_COPY => rows.push((address, line as usize, column as usize)),
+                DW_LNS_ADVANCE_PC => address += read_unsigned(&mut program),
+                DW_LNS_ADVANCE_LINE => line += read_signed(&mut program),
+                DW_LNS_SET_COLUMN => column = read_unsigned(&mut program),
+                _ if opcode >= opcode_base => {
+                    let adjusted = u64::from(opcode - opcode_base);
+                    address += adjusted / line_range;
+                    line += line_base + (adjusted % line_range) as i64;
+                    rows.push((address, line as usize, column as usize));
+                }
+                _ => {
+                    for _ in 0..opcode_lengths[opcode as usize - 1] {
+                        read_unsigned(&mut program);
+                    }
+                }
+            }
+        }
+    }
+
+    // The position of each command in a program, in the order the compiler generates their code
+    fn positions(nodes: &[Node], positions: &mut Vec<Position>) {
+        for node in nodes {
+            match &node.op {
+                Op::Loop(body) => {
+                    positions.push(node.span.first_character().start);
+                    self::positions(body, positions);
+                    positions.push(node.span.last_character().start);
+                }
+                _ => positions.push(node.span.start),
+            }
+        }
+    }
+
+    fn section<'s>(sections: &'s [Section], name: &str) -> &'s [u8] {
+        &sections.iter().find(|section| section.name == name).unwrap().contents
+    }
+
+    #[test]
+    fn line_table() {
+        let nodes = ir::build(&mut Stream::new(&b"+>\n\t[-]\n  ."[..]), false).unwrap();
+        let mut commands = vec![];
+        positions(&nodes, &mut commands);
+
+        // The tab advances the loop to column 9
+        let lines_and_columns: Vec<_> = commands
+            .iter()
+            .map(|position| (position.line, position.column))
+            .collect();
+        assert_eq!(lines_and_columns, [(1, 1), (1, 2), (2, 9), (2, 10), (2, 11), (3, 3)]);
+
+        // Offsets far enough apart to need more than one byte of LEB128
+        let offsets = [0, 3, 200, 201, 1000, 1003];
+        let rows: Vec<_> = offsets.iter().copied().zip(commands).collect();
+        let sections = sections(Path::new("test.b"), Path::new("/tmp"), ADDRESS, 1100, &rows);
+
+        let (decoded, end) = decode_lines(section(&sections, ".debug_line"));
+        let expected: Vec<_> = rows
+            .iter()
+            .map(|(offset, position)| (ADDRESS + offset, position.line, position.column))
+            .collect();
+
+        assert_eq!(decoded, expected);
+        assert_eq!(end, ADDRESS + 1100);
+    }
+
+    #[test]
+    fn compile_unit() {
+        let sections = sections(Path::new("test.b"), Path::new("/tmp"), ADDRESS, 1100, &[]);
+        let unit = section(&sections, ".debug_info");
+
+        assert_eq!(
+            u32::from_le_bytes(unit[..4].try_into().unwrap()) as usize,
+            unit.len() - 4
+        );
+        assert_eq!(u16::from_le_bytes(unit[4..6].try_into().unwrap()), VERSION);
+        assert_eq!(unit[10], 8);
+        assert_eq!(unit[11], ABBREVIATION_COMPILE_UNIT);
+
+        // Producer, name and compilation directory
+        let strings: Vec<_> = unit[12..].split(|&byte| byte == 0).take(3).collect();
+        assert!(strings[0].starts_with(b"brainrust "));
+        assert_eq!(strings[1], b"test.b");
+        assert_eq!(strings[2], b"/tmp");
+
+        // Offset of the line program, then the range of the code
+        let rest = &unit[12 + strings.iter().map(|string| string.len() + 1).sum::<usize>()..];
+        assert_eq!(u32::from_le_bytes(rest[..4].try_into().unwrap()), 0);
+        assert_eq!(u64::from_le_bytes(rest[4..12].try_into().unwrap()), ADDRESS);
+        assert_eq!(u64::from_le_bytes(rest[12..20].try_into().unwrap()), 1100);
+
+        // The subprogram covers the same range
+        assert_eq!(rest[20], ABBREVIATION_SUBPROGRAM);
+        let name_end = 21 + SUBPROGRAM_NAME.len();
+        assert_eq!(&rest[21..name_end], SUBPROGRAM_NAME.as_bytes());
+        assert_eq!(
+            u64::from_le_bytes(rest[name_end + 1..name_end + 9].try_into().unwrap()),
+            ADDRESS
+        );
+        assert_eq!(
+            u64::from_le_bytes(rest[name_end + 9..name_end + 17].try_into().unwrap()),
+            1100
+        );
+        assert_eq!(&rest[name_end + 17..], [0]);
+    }
+}
@@ -3,53 +3,59 @@
     - ELF header (64 bytes)
     - .text program header (56 bytes)
     - .bss program header (56 bytes)
-    - Dummy section header (64 bytes, offset 0xb0)
-    - .text section header (64 bytes)
-    - .bss section header (64 bytes)
-    - String table section header (64 bytes)
-    - String table contents (22 bytes, offset 0x01b0)
-    - Code (variable length, offset 0x01c6)
+    - Code (variable length, offset 0xb0)
+    - Contents of any other sections, e.g. debugging information (variable length)
+    - String table contents (variable length)
+    - Section headers: dummy, .text, .bss, any other sections, string table (64 bytes
+      each, aligned to 8 bytes)
+
+    Only the code and bss are loaded into memory; the other sections are there for the
+    benefit of tools like debuggers, so they're kept out of the way at the end of the file,
+    where their sizes don't affect the placement of anything that's loaded.
 
     The user-space virtual address space spans from  0x0000000000000000
     to 0x00007fffffffffff.
 
     ELF dictates that "loadable process segments must have congruent values for
     p_vaddr and p_offset, modulo the page size", that is to say that the virtual
-    address of the .text segment must be at an offset 0x01c6 bytes past a page
-    boundary. The most obvious choice is to map .text to 0x00000000000001c6, but
+    address of the .text segment must be at an offset 0xb0 bytes past a page
+    boundary. The most obvious choice is to map .text to 0x00000000000000b0, but
     Linux doesn't like this; the resultant executable immediately segfaults. I
     imagine this is because of some special casing of the first page of virtual
     memory, but I haven't been able to find a reference. Instead, we just map .text
-    to 0x00001000000001c6. This address apears in the ELF header as the entry point,
+    to 0x00001000000000b0. This address apears in the ELF header as the entry point,
     and as the virtual address in both program and section headers for the .text
     segment.
 */
 
-pub const ELF_HEADER: [u8; 64] = [
+// Leading portion of the ELF header, up until the section header table offset
+pub const ELF_HEADER_START: [u8; 40] = [
     0x7f, 0x45, 0x4c, 0x46, // Magic numbers
     0x02, 0x01, 0x01, 0x00, // 64-bit encoding; little-endian encoding; version 1; System V ABI
     0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, // Padding
     0x02, 0x00, // Type (executable file)
     0x3e, 0x00, // Architecture (AMD64)
     0x01, 0x00, 0x00, 0x00, // Version (1, again)
-    0xc6, 0x01, 0x00, 0x00, 0x00, 0x10, 0x00, 0x00, // Entry point
+    0xb0, 0x00, 0x00, 0x00, 0x00, 0x10, 0x00, 0x00, // Entry point
     0x40, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, // Program header table offset
-    0xb0, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, // Section header table offset
+];
+
+// Portion of the ELF header between the section header table offset and the number of section headers, which is
+// followed by the index of the name table section
+pub const ELF_HEADER_MIDDLE: [u8; 12] = [
     0x00, 0x00, 0x00, 0x00, // Flags (unused)
     0x40, 0x00, // Size of ELF header
     0x38, 0x00, // Size of program header
     0x02, 0x00, // Number of program headers (.text, .bss)
     0x40, 0x00, // Size of section header
-    0x04, 0x00, // Number of sections headers (dummy section, .text, .bss, name table)
-    0x03, 0x00, // Index of name table section
 ];
 
 // Leading portion of the .text program header, up until the size fields
 pub const TEXT_PROGRAM_HEADER_START: [u8; 32] = [
     0x01, 0x00, 0x00, 0x00, // Type (loadable segment)
     0x05, 0x00, 0x00, 0x00, // Flags (readable, executable),
-    0xc6, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, // Offset
-    0xc6, 0x01, 0x00, 0x00, 0x00, 0x10, 0x00, 0x00, // Virtual address (same as entry point)
+    0xb0, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, // Offset
+    0xb0, 0x00, 0x00, 0x00, 0x00, 0x10, 0x00, 0x00, // Virtual address (same as entry point)
     0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, // Physical address (unused)
 ];
 
@@ -73,70 +79,211 @@ pub const BSS_PROGRAM_HEADER_END: [u8; 8] = [
     0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, // Alignment (unused)
 ];
 
-pub const DUMMY_SECTION_HEADER: [u8; 64] = [
-    0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
-    0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
-    0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
-    0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
-];
+pub const ELF_HEADER_SIZE: u64 = 64;
+pub const PROGRAM_HEADER_SIZE: u64 = 56;
+pub const SECTION_HEADER_SIZE: u64 = 64;
+pub const SYMBOL_SIZE: u64 = 24;
 
-// Leading portion of .text section header, up until the size field
-pub const TEXT_SECTION_HEADER_START: [u8; 32] = [
-    0x01, 0x00, 0x00, 0x00, // Name offset (1 byte into the table)
-    0x01, 0x00, 0x00, 0x00, // Type (PROGBITS, i.e. program data)
-    0x06, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, // Flags (ALLOC [occupies memory], EXECINSTR [executable])
-    0xc6, 0x01, 0x00, 0x00, 0x00, 0x10, 0x00, 0x00, // Virtual address (same as entry point)
-    0xc6, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, // Offset
-];
+pub const TEXT_OFFSET: u64 = ELF_HEADER_SIZE + 2 * PROGRAM_HEADER_SIZE;
 
-// Trailing portion of .text section header after the size field
-pub const TEXT_SECTION_HEADER_END: [u8; 24] = [
-    0x00, 0x00, 0x00, 0x00, // Linked section (unused)
-    0x00, 0x00, 0x00, 0x00, // Info field (unused)
-    0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, // Alignment (unused)
-    0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, // Entity size (unused)
-];
+// Section types
+pub const SECTION_PROGBITS: u32 = 1;
+pub const SECTION_SYMTAB: u32 = 2;
+pub const SECTION_STRTAB: u32 = 3;
+pub const SECTION_NOBITS: u32 = 8;
 
-// Leading portion of .bss section header, up until the size field
-pub const BSS_SECTION_HEADER_START: [u8; 32] = [
-    0x07, 0x00, 0x00, 0x00, // Name offset (7 bytes into the table)
-    0x08, 0x00, 0x00, 0x00, // Type (NOBITS, i.e. occupies no space on disk)
-    0x03, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, // Flags (WRITE, ALLOC)
-    0x00, 0x00, 0x00, 0x00, 0x00, 0x60, 0x00, 0x00, // Virtual address
-    0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, // Offset (unused)
-];
+// Section flags
+pub const SECTION_WRITE: u64 = 0x1;
+pub const SECTION_ALLOC: u64 = 0x2;
+pub const SECTION_EXECINSTR: u64 = 0x4;
 
-// Trailing portion of the .bss section header after the size field
-pub const BSS_SECTION_HEADER_END: [u8; 24] = [
-    0x00, 0x00, 0x00, 0x00, // Linked section (unused)
-    0x00, 0x00, 0x00, 0x00, // Info field (unused)
-    0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, // Alignment (unused)
-    0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, // Entity size (unused)
-];
+// Indices of the sections which are always present; any others follow them, and the name table comes last
+pub const TEXT_SECTION_INDEX: u16 = 1;
+pub const BSS_SECTION_INDEX: u16 = 2;
+pub const FIRST_OTHER_SECTION_INDEX: u16 = 3;
 
-pub const STRING_TABLE_SECTION_HEADER: [u8; 64] = [
-    0x0c, 0x00, 0x00, 0x00, // Name offset (12 bytes into the table)
-    0x03, 0x00, 0x00, 0x00, // Type (string table)
-    0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, // Flags (none)
-    0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, // Virtual address (unused)
-    0xb0, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, // Offset
-    0x16, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, // Size (22 bytes)
-    0x00, 0x00, 0x00, 0x00, // Linked section (unused)
-    0x00, 0x00, 0x00, 0x00, // Info field (unused)
-    0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, // Alignment (unused)
-    0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, // Entity size (unused)
-];
+// Symbol bindings
+pub const SYMBOL_LOCAL: u8 = 0;
+pub const SYMBOL_GLOBAL: u8 = 1;
 
-pub const STRING_TABLE_CONTENTS: [u8; 22] = [
-    0x00, // Unused index
-    b'.', b't', b'e', b'x', b't', 0x00, // .text (offset 1)
-    b'.', b'b', b's', b's', 0x00, // .bss (offset 7)
-    b'.', b's', b'h', b's', b't', b'r', b't', b'a', b'b', 0x00, // .shstrtab (offset 12)
-];
+// Symbol types
+pub const SYMBOL_OBJECT: u8 = 1;
+pub const SYMBOL_FUNC: u8 = 2;
 
-pub const TEXT_VIRTUAL_ADDRESS: u64 = 0x1000000001c6;
+pub const TEXT_VIRTUAL_ADDRESS: u64 = 0x1000000000b0;
 pub const BSS_VIRTUAL_ADDRESS: u64 = 0x600000000000;
 pub const MAX_VIRTUAL_ADDRESS: u64 = 0x7fffffffffff;
 
 pub const MAX_TEXT_SIZE: u64 = BSS_VIRTUAL_ADDRESS - TEXT_VIRTUAL_ADDRESS;
 pub const MAX_BSS_SIZE: u64 = (1 + 0x7fffffffffff) - BSS_VIRTUAL_ADDRESS;
+
+pub struct SectionHeader {
+    // Offset of the section's name in the name table
+    pub name: u32,
+    pub kind: u32,
+    pub flags: u64,
+    // Zero unless the section is loaded into memory
+    pub address: u64,
+    pub offset: u64,
+    pub size: u64,
+    // The meanings of these depend on the kind of section; for a symbol table, the index of the string table its
+    // names are in, and the index of its first global symbol
+    pub link: u32,
+    pub info: u32,
+    pub alignment: u64,
+    // For sections holding a table, the size of each entry
+    pub entry_size: u64,
+}
+
+impl SectionHeader {
+    pub fn dummy() -> Self {
+        Self {
+            name: 0,
+            kind: 0,
+            flags: 0,
+            address: 0,
+            offset: 0,
+            size: 0,
+            link: 0,
+            info: 0,
+            alignment: 0,
+            entry_size: 0,
+        }
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(SECTION_HEADER_SIZE as usize);
+        bytes.extend(&self.name.to_le_bytes());
+        bytes.extend(&self.kind.to_le_bytes());
+        bytes.extend(&self.flags.to_le_bytes());
+        bytes.extend(&self.address.to_le_bytes());
+        bytes.extend(&self.offset.to_le_bytes());
+        bytes.extend(&self.size.to_le_bytes());
+        bytes.extend(&self.link.to_le_bytes());
+        bytes.extend(&self.info.to_le_bytes());
+        bytes.extend(&self.alignment.to_le_bytes());
+        bytes.extend(&self.entry_size.to_le_bytes());
+        bytes
+    }
+}
+
+// A section which isn't loaded into memory, whose contents follow the code
+pub struct Section {
+    pub name: &'static str,
+    pub kind: u32,
+    pub contents: Vec<u8>,
+    pub link: u32,
+    pub info: u32,
+    pub alignment: u64,
+    pub entry_size: u64,
+}
+
+impl Section {
+    // A section of data meaningful only to other tools, e.g. debugging information
+    pub fn data(name: &'static str, contents: Vec<u8>) -> Self {
+        Self {
+            name,
+            kind: SECTION_PROGBITS,
+            contents,
+            link: 0,
+            info: 0,
+            alignment: 1,
+            entry_size: 0,
+        }
+    }
+}
+
+pub struct Symbol {
+    pub name: String,
+    pub binding: u8,
+    pub kind: u8,
+    // Index of the section the symbol is defined in
+    pub section: u16,
+    pub value: u64,
+    pub size: u64,
+}
+
+impl Symbol {
+    fn to_bytes(&self, name: u32) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(SYMBOL_SIZE as usize);
+        bytes.extend(&name.to_le_bytes());
+        bytes.push(self.binding << 4 | self.kind);
+        bytes.push(0); // Visibility (default)
+        bytes.extend(&self.section.to_le_bytes());
+        bytes.extend(&self.value.to_le_bytes());
+        bytes.extend(&self.size.to_le_bytes());
+        bytes
+    }
+}
+
+// The .symtab and .strtab sections listing the given symbols, where .strtab is to be the section at the given index
+pub fn symbol_table(symbols: &[Symbol], strings_index: u16) -> [Section; 2] {
+    // Local symbols have to come first, after the null symbol at index zero
+    let (locals, globals): (Vec<&Symbol>, Vec<&Symbol>) =
+        symbols.iter().partition(|symbol| symbol.binding == SYMBOL_LOCAL);
+
+    let mut names = StringTable::new();
+    let mut table = vec![0; SYMBOL_SIZE as usize];
+
+    for symbol in locals.iter().chain(&globals) {
+        table.extend(symbol.to_bytes(names.add(symbol.name.as_bytes())));
+    }
+
+    [
+        Section {
+            name: ".symtab",
+            kind: SECTION_SYMTAB,
+            contents: table,
+            link: u32::from(strings_index),
+            info: 1 + locals.len() as u32,
+            alignment: 8,
+            entry_size: SYMBOL_SIZE,
+        },
+        Section {
+            name: ".strtab",
+            kind: SECTION_STRTAB,
+            contents: names.into_bytes(),
+            link: 0,
+            info: 0,
+            alignment: 1,
+            entry_size: 0,
+        },
+    ]
+}
+
+// A table of null-terminated strings, e.g. section names, referred to by their offsets into it
+pub struct StringTable {
+    bytes: Vec<u8>,
+}
+
+impl StringTable {
+    pub fn new() -> Self {
+        // Offset zero is the empty string, which stands for no name at all
+        Self { bytes: vec![0] }
+    }
+
+    pub fn add(&mut self, string: &[u8]) -> u32 {
+        let offset = self.bytes.len() as u32;
+        self.bytes.extend(string);
+        self.bytes.push(0);
+        offset
+    }
+
+    pub fn into_bytes(self) -> Vec<u8> {
+        self.bytes
+    }
+}
+
+impl Default for StringTable {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// The number of bytes needed to bring an offset up to a multiple of the alignment
+pub fn padding(offset: u64, alignment: u64) -> u64 {
+    match alignment {
+        0 | 1 => 0,
+        _ => (alignment - offset % alignment) % alignment,
+    }
+}
@@ -1,133 +1,248 @@
+use std::env;
 use std::io;
+use std::path::{Path, PathBuf};
 
-use crate::assembler::Assembler;
+use crate::assembler::{AllocationError, Assembler};
+use crate::dwarf;
 use crate::elf::*;
+use crate::machine_code::MachineCode;
+use crate::stream::{Position, Span};
 
 type Address = u64;
 type Label = usize;
 
 pub struct ElfAssembler {
     allocation_pointer: u64,
-    label_states: Vec<LabelState>,
-    machine_code: Vec<u8>,
-}
-
-enum LabelState {
-    Unpopulated(Vec<usize>),
-    Populated(usize),
+    machine_code: MachineCode,
+    // With debugging information, the path of the source file and the directory it was compiled from
+    debug_info: Option<(PathBuf, PathBuf)>,
+    // Offsets into the code at which the code for each command starts, with the command's position in the source
+    line_table: Vec<(u64, Position)>,
+    // Names given to labels and to allocated memory, which are listed in the symbol table
+    label_names: Vec<(Label, String)>,
+    memory_names: Vec<(Address, u64, String)>,
 }
 
 impl ElfAssembler {
     pub fn new() -> Self {
         Self {
             allocation_pointer: BSS_VIRTUAL_ADDRESS,
-            label_states: vec![],
-            machine_code: vec![],
+            machine_code: MachineCode::new(),
+            debug_info: None,
+            line_table: vec![],
+            label_names: vec![],
+            memory_names: vec![],
         }
     }
 
-    fn generate_branch(&mut self, label: Label, code: &[u8]) {
-        let state = &mut self.label_states[label];
+    // Include DWARF debugging information, mapping the code back to the given source file; a relative path is taken
+    // to be relative to the current directory
+    pub fn with_debug_info(mut self, source: &Path) -> Self {
+        // If the current directory can't be determined, relative paths just won't resolve
+        let directory = env::current_dir().unwrap_or_default();
+        self.debug_info = Some((source.to_path_buf(), directory));
+        self
+    }
 
-        self.machine_code.extend(code);
+    // The code is mapped directly below the bss, so there's a limit to how much of it there can be
+    pub fn check_code_size(&self) -> Result<(), AllocationError> {
+        let size = self.machine_code.bytes().len() as u64;
 
-        match state {
-            LabelState::Unpopulated(ref mut patch_offsets) => {
-                patch_offsets.push(self.machine_code.len());
-                self.machine_code.extend(&[0x00, 0x00, 0x00, 0x00]);
-            }
-            LabelState::Populated(destination) => {
-                let origin = self.machine_code.len() + 4;
+        if size > MAX_TEXT_SIZE {
+            return Err(AllocationError {
+                size,
+                available: MAX_TEXT_SIZE,
+            });
+        }
 
-                assert!(*destination < origin);
+        Ok(())
+    }
 
-                let relative_offset = {
-                    let difference = origin - *destination;
-                    assert!(difference <= (i32::max_value() as usize)); // FIXME?
-                    -(difference as i32)
-                };
+    // The entry point, which covers all of the code, followed by a local symbol for each named label (whose extent
+    // is left to whoever reads the table) and each named region of memory
+    fn symbols(&self) -> Vec<Symbol> {
+        let mut symbols = vec![Symbol {
+            name: "_start".to_string(),
+            binding: SYMBOL_GLOBAL,
+            kind: SYMBOL_FUNC,
+            section: TEXT_SECTION_INDEX,
+            value: TEXT_VIRTUAL_ADDRESS,
+            size: self.machine_code.bytes().len() as u64,
+        }];
 
-                self.machine_code.extend(&relative_offset.to_le_bytes());
+        for (label, name) in &self.label_names {
+            if let Some(offset) = self.machine_code.label_offset(*label) {
+                symbols.push(Symbol {
+                    name: name.clone(),
+                    binding: SYMBOL_LOCAL,
+                    kind: SYMBOL_FUNC,
+                    section: TEXT_SECTION_INDEX,
+                    value: TEXT_VIRTUAL_ADDRESS + offset as u64,
+                    size: 0,
+                });
             }
         }
-    }
-}
 
-macro_rules! instr {
-    ($name:ident, $code:expr) => {
-        fn $name(&mut self) {
-            self.machine_code.extend(&$code);
+        for (address, size, name) in &self.memory_names {
+            symbols.push(Symbol {
+                name: name.clone(),
+                binding: SYMBOL_LOCAL,
+                kind: SYMBOL_OBJECT,
+                section: BSS_SECTION_INDEX,
+                value: *address,
+                size: *size,
+            });
         }
-    };
 
-    ($name:ident, $operand_type:ty, $code:expr) => {
-        fn $name(&mut self, operand: $operand_type) {
-            self.machine_code.extend(&$code);
-            self.machine_code.extend(&operand.to_le_bytes());
-        }
-    };
+        symbols.sort_by_key(|symbol| (symbol.section, symbol.value));
+        symbols
+    }
 }
 
-macro_rules! instr_branch {
-    ($name:ident, $code:expr) => {
-        fn $name(&mut self, label: Self::Label) {
-            self.generate_branch(label, &$code);
-        }
-    };
+impl Default for ElfAssembler {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl<'a> Assembler<'a> for ElfAssembler {
     type Address = Address;
     type Label = Label;
 
-    fn allocate_memory(&mut self, size: u64) -> Self::Address {
-        assert!(self.allocation_pointer + size <= MAX_VIRTUAL_ADDRESS + 1); // FIXME: overflow
+    fn allocate_memory(&mut self, size: u64) -> Result<Self::Address, AllocationError> {
+        let available = MAX_BSS_SIZE - (self.allocation_pointer - BSS_VIRTUAL_ADDRESS);
+
+        if size > available {
+            return Err(AllocationError { size, available });
+        }
+
         let address = self.allocation_pointer;
         self.allocation_pointer += size;
-        address
+        Ok(address)
     }
 
     fn allocate_label(&mut self) -> Self::Label {
-        let index = self.label_states.len();
-        self.label_states.push(LabelState::Unpopulated(vec![]));
-        index
+        self.machine_code.allocate_label()
     }
 
     fn label(&mut self, label: Self::Label) {
-        let state = &mut self.label_states[label];
-        let destination = self.machine_code.len();
-
-        let patch_offsets = match state {
-            LabelState::Unpopulated(ref offsets) => offsets,
-            LabelState::Populated(_) => panic!("label was defined multiple times"),
-        };
+        self.machine_code.label(label);
+    }
 
-        for patch_offset in patch_offsets {
-            let origin = *patch_offset + 4;
-            assert!(origin <= destination);
+    fn data(&mut self, bytes: &[u8]) {
+        self.machine_code.extend(bytes);
+    }
 
-            let patch_slice = &mut self.machine_code[*patch_offset..*patch_offset + 4];
-            assert!(patch_slice == [0x00, 0x00, 0x00, 0x00]);
+    fn source_span(&mut self, span: Span) {
+        if self.debug_info.is_none() {
+            return;
+        }
 
-            let relative_offset = {
-                let difference = destination - origin;
-                assert!(difference <= (i32::max_value() as usize)); // FIXME?
-                difference as i32
-            };
+        let offset = self.machine_code.bytes().len() as u64;
+        let position = span.start;
 
-            patch_slice.copy_from_slice(&relative_offset.to_le_bytes());
+        // A command which generated no code is superseded by the next
+        match self.line_table.last_mut() {
+            Some(row) if row.0 == offset => *row = (offset, position),
+            _ => self.line_table.push((offset, position)),
         }
+    }
 
-        self.label_states[label] = LabelState::Populated(destination);
+    fn name_label(&mut self, label: Self::Label, name: &str) {
+        self.label_names.push((label, name.to_string()));
+    }
+
+    fn name_memory(&mut self, address: Self::Address, size: u64, name: &str) {
+        self.memory_names.push((address, size, name.to_string()));
     }
 
     fn assemble<W: io::Write>(self, output: &mut W) -> Result<(), io::Error> {
-        assert!((self.machine_code.len() as u64) <= MAX_TEXT_SIZE); // FIXME
+        let code = self.machine_code.bytes();
+        let text_size = code.len() as u64;
+        let bss_size = self.allocation_pointer - BSS_VIRTUAL_ADDRESS;
+
+        self.check_code_size()
+            .map_err(|error| io::Error::new(io::ErrorKind::InvalidInput, error.to_string()))?;
+
+        let mut sections = match &self.debug_info {
+            Some((source, directory)) => {
+                dwarf::sections(source, directory, TEXT_VIRTUAL_ADDRESS, text_size, &self.line_table)
+            }
+            None => vec![],
+        };
+
+        let strings_index = FIRST_OTHER_SECTION_INDEX + sections.len() as u16 + 1;
+        sections.extend(symbol_table(&self.symbols(), strings_index));
+
+        let mut names = StringTable::new();
+
+        let mut headers = vec![
+            SectionHeader::dummy(),
+            SectionHeader {
+                name: names.add(b".text"),
+                kind: SECTION_PROGBITS,
+                flags: SECTION_ALLOC | SECTION_EXECINSTR,
+                address: TEXT_VIRTUAL_ADDRESS,
+                offset: TEXT_OFFSET,
+                size: text_size,
+                ..SectionHeader::dummy()
+            },
+            SectionHeader {
+                name: names.add(b".bss"),
+                kind: SECTION_NOBITS,
+                flags: SECTION_WRITE | SECTION_ALLOC,
+                address: BSS_VIRTUAL_ADDRESS,
+                size: bss_size,
+                ..SectionHeader::dummy()
+            },
+        ];
+
+        // The other sections' contents follow the code, each aligned as it requires
+        let mut offset = TEXT_OFFSET + text_size;
+
+        for section in &sections {
+            offset += padding(offset, section.alignment);
 
-        let le_text_size = self.machine_code.len().to_le_bytes();
-        let le_bss_size = (self.allocation_pointer - BSS_VIRTUAL_ADDRESS).to_le_bytes();
+            headers.push(SectionHeader {
+                name: names.add(section.name.as_bytes()),
+                kind: section.kind,
+                offset,
+                size: section.contents.len() as u64,
+                link: section.link,
+                info: section.info,
+                alignment: section.alignment,
+                entry_size: section.entry_size,
+                ..SectionHeader::dummy()
+            });
 
-        output.write_all(&ELF_HEADER)?;
+            offset += section.contents.len() as u64;
+        }
+
+        // The name table has to hold its own name before its size is known
+        let names_name = names.add(b".shstrtab");
+        let names = names.into_bytes();
+
+        headers.push(SectionHeader {
+            name: names_name,
+            kind: SECTION_STRTAB,
+            offset,
+            size: names.len() as u64,
+            ..SectionHeader::dummy()
+        });
+
+        offset += names.len() as u64;
+
+        let section_headers_padding = padding(offset, 8);
+        let section_headers_offset = offset + section_headers_padding;
+
+        let le_text_size = text_size.to_le_bytes();
+        let le_bss_size = bss_size.to_le_bytes();
+
+        output.write_all(&ELF_HEADER_START)?;
+        output.write_all(&section_headers_offset.to_le_bytes())?;
+        output.write_all(&ELF_HEADER_MIDDLE)?;
+        output.write_all(&(headers.len() as u16).to_le_bytes())?;
+        output.write_all(&(headers.len() as u16 - 1).to_le_bytes())?;
         output.write_all(&TEXT_PROGRAM_HEADER_START)?;
         output.write_all(&le_text_size)?;
         output.write_all(&le_text_size)?;
@@ -135,75 +250,175 @@ impl<'a> Assembler<'a> for ElfAssembler {
         output.write_all(&BSS_PROGRAM_HEADER_START)?;
         output.write_all(&le_bss_size)?;
         output.write_all(&BSS_PROGRAM_HEADER_END)?;
-        output.write_all(&DUMMY_SECTION_HEADER)?;
-        output.write_all(&TEXT_SECTION_HEADER_START)?;
-        output.write_all(&le_text_size)?;
-        output.write_all(&TEXT_SECTION_HEADER_END)?;
-        output.write_all(&BSS_SECTION_HEADER_START)?;
-        output.write_all(&le_bss_size)?;
-        output.write_all(&BSS_SECTION_HEADER_END)?;
-        output.write_all(&STRING_TABLE_SECTION_HEADER)?;
-        output.write_all(&STRING_TABLE_CONTENTS)?;
-        output.write_all(&self.machine_code)?;
+        output.write_all(code)?;
+
+        let mut offset = TEXT_OFFSET + text_size;
+
+        for section in &sections {
+            let padding = padding(offset, section.alignment);
+            output.write_all(&vec![0; padding as usize])?;
+            output.write_all(&section.contents)?;
+            offset += padding + section.contents.len() as u64;
+        }
+
+        output.write_all(&names)?;
+        output.write_all(&vec![0; section_headers_padding as usize])?;
+
+        for header in &headers {
+            output.write_all(&header.to_bytes())?;
+        }
+
         Ok(())
     }
 
-    instr!(add_byte_ptr_rbx_plus_r8_u8, u8, [0x42, 0x80, 0x04, 0x03]);
-    instr!(add_r15_rax, [0x49, 0x01, 0xc7]);
-    instr!(add_r8_i32, i32, [0x49, 0x81, 0xc0]);
-    instr!(add_r8_i8, i8, [0x49, 0x83, 0xc0]);
-    instr!(add_r8_r9, [0x4d, 0x01, 0xc8]);
-    instr!(add_rsi_r15, [0x4c, 0x01, 0xfe]);
-    instr!(cmovae_r8_r15, [0x4d, 0x0f, 0x43, 0xc7]);
-    instr!(cmp_byte_ptr_rbx_plus_r8_u8, u8, [0x42, 0x80, 0x3c, 0x03]);
-    instr!(cmp_r10_r11, [0x4d, 0x39, 0xda]);
-    instr!(cmp_r10_r12, [0x4d, 0x39, 0xe2]);
-    instr!(cmp_r13_u32, u32, [0x49, 0x81, 0xfd]);
-    instr!(cmp_r13_rbp, [0x49, 0x39, 0xed]);
-    instr!(cmp_r15b_u8, u8, [0x41, 0x80, 0xff]);
-    instr!(cmp_r15_r13, [0x4d, 0x39, 0xef]);
-    instr!(cmp_rax_u32, u32, [0x48, 0x3d]);
-    instr!(dec_byte_ptr_rbx_plus_r8, [0x42, 0xfe, 0x0c, 0x03]);
-    instr!(inc_byte_ptr_rbx_plus_r8, [0x42, 0xfe, 0x04, 0x03]);
-    instr!(inc_r10, [0x49, 0xff, 0xc2]);
-    instr!(inc_r13, [0x49, 0xff, 0xc5]);
-    instr_branch!(je, [0x0f, 0x84]);
-    instr_branch!(jg, [0x0f, 0x8f]);
-    instr_branch!(jge, [0x0f, 0x8d]);
-    instr_branch!(jmp, [0xe9]);
-    instr_branch!(jne, [0x0f, 0x85]);
-    instr_branch!(jns, [0x0f, 0x89]);
-    instr!(mov_byte_ptr_rbx_plus_r8_r15b, [0x46, 0x88, 0x3c, 0x03]);
-    instr!(mov_byte_ptr_rsp_plus_r13_r15b, [0x46, 0x88, 0x3c, 0x2c]);
-    instr!(mov_r11_rax, [0x49, 0x89, 0xc3]);
-    instr!(mov_r12_u64, u64, [0x49, 0xbc]);
-    instr!(mov_r12_rax, [0x49, 0x89, 0xc4]);
-    instr!(mov_r13_u32, u32, [0x41, 0xbd]);
-    instr!(mov_r14_addr, Self::Address, [0x49, 0xbe]);
-    instr!(mov_r15b_byte_ptr_r14_plus_r10, [0x47, 0x8a, 0x3c, 0x16]);
-    instr!(mov_r15b_byte_ptr_rbx_plus_r8, [0x46, 0x8a, 0x3c, 0x03]);
-    instr!(mov_r15_r8, [0x4d, 0x89, 0xc7]);
-    instr!(mov_r9_u64, u64, [0x49, 0xb9]);
-    instr!(mov_rax_u32, u32, [0xb8]);
-    instr!(mov_rbp_u64, u64, [0x48, 0xbd]);
-    instr!(mov_rbx_addr, Self::Address, [0x48, 0xbb]);
-    instr!(mov_rdi_u32, u32, [0xbf]);
-    instr!(mov_rdx_u32, u32, [0xba]);
-    instr!(mov_rdx_r12, [0x4c, 0x89, 0xe2]);
-    instr!(mov_rdx_r13, [0x4c, 0x89, 0xea]);
-    instr!(mov_rsi_r14, [0x4c, 0x89, 0xf6]);
-    instr!(mov_rsi_rsp, [0x48, 0x89, 0xe6]);
-    instr!(mov_rsp_addr, Self::Address, [0x48, 0xbc]);
-    instr!(sub_r15_r9, [0x4d, 0x29, 0xcf]);
-    instr!(sub_r8_r9, [0x4d, 0x29, 0xc8]);
-    instr!(sub_rdx_r15, [0x4c, 0x29, 0xfa]);
-    instr!(syscall, [0x0f, 0x05]);
-    instr!(xor_r10_r10, [0x4d, 0x31, 0xd2]);
-    instr!(xor_r11_r11, [0x4d, 0x31, 0xdb]);
-    instr!(xor_r12_r12, [0x4d, 0x31, 0xe4]);
-    instr!(xor_r13_r13, [0x4d, 0x31, 0xed]);
-    instr!(xor_r15_r15, [0x4d, 0x31, 0xff]);
-    instr!(xor_r8_r8, [0x4d, 0x31, 0xc0]);
-    instr!(xor_rax_rax, [0x48, 0x31, 0xc0]);
-    instr!(xor_rdi_rdi, [0x48, 0x31, 0xff]);
+    instructions!();
+}
+
+#[cfg(test)]
+mod tests {
+    use std::convert::TryInto;
+
+    use super::*;
+    use crate::options::{CellWidth, Options};
+
+    fn u16_at(bytes: &[u8], offset: usize) -> u16 {
+        u16::from_le_bytes(bytes[offset..offset + 2].try_into().unwrap())
+    }
+
+    fn u32_at(bytes: &[u8], offset: usize) -> u32 {
+        u32::from_le_bytes(bytes[offset..offset + 4].try_into().unwrap())
+    }
+
+    fn u64_at(bytes: &[u8], offset: usize) -> u64 {
+        u64::from_le_bytes(bytes[offset..offset + 8].try_into().unwrap())
+    }
+
+    fn string_at(table: &[u8], offset: usize) -> String {
+        let end = offset + table[offset..].iter().position(|&byte| byte == 0).unwrap();
+        String::from_utf8(table[offset..end].to_vec()).unwrap()
+    }
+
+    struct SectionHeader {
+        name: String,
+        address: u64,
+        offset: usize,
+        size: usize,
+        link: usize,
+        info: usize,
+    }
+
+    struct ParsedSymbol {
+        name: String,
+        binding: u8,
+        kind: u8,
+        section: u16,
+        value: u64,
+        size: u64,
+    }
+
+    fn section_headers(elf: &[u8]) -> Vec<SectionHeader> {
+        let table = u64_at(elf, 0x28) as usize;
+        let count = u16_at(elf, 0x3c) as usize;
+        let names_index = u16_at(elf, 0x3e) as usize;
+
+        let header = |index: usize| &elf[table + index * 64..table + (index + 1) * 64];
+        let names = header(names_index);
+        let names = &elf[u64_at(names, 24) as usize..][..u64_at(names, 32) as usize];
+
+        (0..count)
+            .map(|index| {
+                let header = header(index);
+
+                SectionHeader {
+                    name: string_at(names, u32_at(header, 0) as usize),
+                    address: u64_at(header, 16),
+                    offset: u64_at(header, 24) as usize,
+                    size: u64_at(header, 32) as usize,
+                    link: u32_at(header, 40) as usize,
+                    info: u32_at(header, 44) as usize,
+                }
+            })
+            .collect()
+    }
+
+    // The symbols in .symtab, without the null symbol, along with the index of the first global one
+    fn symbols(elf: &[u8]) -> (Vec<ParsedSymbol>, usize) {
+        let headers = section_headers(elf);
+        let table = headers.iter().find(|header| header.name == ".symtab").unwrap();
+        let names = &headers[table.link];
+        assert_eq!(names.name, ".strtab");
+
+        let names = &elf[names.offset..names.offset + names.size];
+        let entries = &elf[table.offset..table.offset + table.size];
+        assert!(entries[..24].iter().all(|&byte| byte == 0));
+
+        let symbols = entries[24..]
+            .chunks(24)
+            .map(|entry| ParsedSymbol {
+                name: string_at(names, u32_at(entry, 0) as usize),
+                binding: entry[4] >> 4,
+                kind: entry[4] & 0xf,
+                section: u16_at(entry, 6),
+                value: u64_at(entry, 8),
+                size: u64_at(entry, 16),
+            })
+            .collect();
+
+        (symbols, table.info - 1)
+    }
+
+    fn check_symbols(options: &Options) {
+        let elf = crate::compile_to_vec(b"+\n  [-.]\n.", options).unwrap();
+        let headers = section_headers(&elf);
+        let (symbols, first_global) = symbols(&elf);
+
+        let text = &headers[TEXT_SECTION_INDEX as usize];
+        let bss = &headers[BSS_SECTION_INDEX as usize];
+        assert_eq!(text.name, ".text");
+        assert_eq!(bss.name, ".bss");
+
+        let symbol = |name: &str| match symbols.iter().find(|symbol| symbol.name == name) {
+            Some(symbol) => symbol,
+            None => panic!("no symbol named {}", name),
+        };
+
+        // Local symbols come first, with the entry point as the only global one
+        assert!(symbols[..first_global]
+            .iter()
+            .all(|symbol| symbol.binding == SYMBOL_LOCAL));
+        assert_eq!(symbols[first_global..].len(), 1);
+
+        let start = symbol("_start");
+        assert_eq!(start.binding, SYMBOL_GLOBAL);
+        assert_eq!((start.kind, start.section), (SYMBOL_FUNC, TEXT_SECTION_INDEX));
+        assert_eq!((start.value, start.size), (text.address, text.size as u64));
+        assert_eq!(start.value, TEXT_VIRTUAL_ADDRESS);
+
+        for name in &["flush", "exit", "loop_L2_C3"] {
+            let label = symbol(name);
+            assert_eq!(
+                (label.kind, label.section, label.size),
+                (SYMBOL_FUNC, TEXT_SECTION_INDEX, 0)
+            );
+            assert!(text.address < label.value && label.value < text.address + text.size as u64);
+        }
+
+        let tape = symbol("tape");
+        assert_eq!((tape.kind, tape.section), (SYMBOL_OBJECT, BSS_SECTION_INDEX));
+        assert_eq!(tape.size, options.tape_length * options.cell_width.bytes());
+        assert!(bss.address <= tape.value && tape.value + tape.size <= bss.address + bss.size as u64);
+    }
+
+    #[test]
+    fn symbol_table() {
+        check_symbols(&Options::default());
+
+        // Debugging information adds sections ahead of the symbol table
+        check_symbols(&Options {
+            tape_length: 1000,
+            cell_width: CellWidth::Bits32,
+            debug_info: Some("test.b".into()),
+            ..Options::default()
+        });
+    }
 }
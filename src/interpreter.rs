@@ -0,0 +1,318 @@
+use std::convert::TryFrom;
+use std::fmt;
+use std::fmt::{Debug, Display, Formatter};
+use std::io;
+use std::io::Write;
+
+use crate::ir::{Node, Op, DEBUG_RADIUS};
+use crate::options::{Boundary, Eof, Options, OptionsError};
+use crate::parser::ParseError;
+use crate::stream::Position;
+
+/*
+    The interpreter walks the same tree that the compiler generates code from, following
+    the same rules for the tape boundaries, cell widths and end of input, so that it can
+    serve as a reference for the behavior of compiled programs. It makes no attempt to be
+    fast; optimized nodes are executed by the definitions in ir.rs rather than the tricks
+    the compiler uses for them.
+
+    Cells are stored as u64 whatever their width, and are kept reduced modulo the cell size.
+*/
+
+pub struct Interpreter<R, W> {
+    options: Options,
+    tape: Vec<u64>,
+    pointer: usize,
+    input: R,
+    output: W,
+}
+
+impl<R: io::Read, W: io::Write> Interpreter<R, W> {
+    pub fn new(options: &Options, input: R, output: W) -> Result<Self, RunError> {
+        options.validate()?;
+
+        let mut tape = vec![];
+        resize_tape(&mut tape, options.tape_length)?;
+
+        Ok(Self {
+            options: options.clone(),
+            tape,
+            pointer: 0,
+            input,
+            output,
+        })
+    }
+
+    // Execute a sequence of nodes, then flush any buffered output (even if execution failed)
+    pub fn run(&mut self, nodes: &[Node]) -> Result<(), RunError> {
+        let result = nodes.iter().try_for_each(|node| self.execute(node));
+        let flushed = self.output.flush().map_err(RunError::Write);
+        result.and(flushed)
+    }
+
+    // Execute a single node; a loop is executed until it exits
+    pub fn execute(&mut self, node: &Node) -> Result<(), RunError> {
+        let position = node.span.start;
+
+        match &node.op {
+            Op::Move(shift) => self.shift(*shift, position)?,
+            Op::Add(value) => self.add(*value),
+            Op::Read => self.read()?,
+            Op::Write => self.write()?,
+            Op::Clear => self.tape[self.pointer] = 0,
+            Op::Debug if self.options.debug_command => self.dump(position)?,
+            // Parsed regardless by the debugger, which stops at it
+            Op::Debug => (),
+            Op::Loop(body) => {
+                while self.cell() != 0 {
+                    for node in body {
+                        self.execute(node)?;
+                    }
+                }
+            }
+            Op::MultiplyAdd(terms) => {
+                let value = self.cell() as i64;
+
+                if value != 0 {
+                    let mut current = 0;
+
+                    for &(offset, factor) in terms {
+                        self.shift(offset - current, position)?;
+                        current = offset;
+                        self.add(factor.wrapping_mul(value));
+                    }
+
+                    self.shift(-current, position)?;
+                    self.tape[self.pointer] = 0;
+                }
+            }
+            Op::Scan(stride) => {
+                while self.cell() != 0 {
+                    self.shift(*stride, position)?;
+                }
+            }
+            Op::Block(ops, _) => {
+                for (_, node) in ops {
+                    self.execute(node)?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn tape(&self) -> &[u64] {
+        &self.tape
+    }
+
+    pub fn pointer(&self) -> usize {
+        self.pointer
+    }
+
+    pub fn cell(&self) -> u64 {
+        self.tape[self.pointer]
+    }
+
+    // Store a value, reduced modulo the cell size, in the given cell; returns false if the cell is past the end of
+    // the tape
+    pub fn set(&mut self, index: usize, value: i64) -> bool {
+        match self.tape.get_mut(index) {
+            Some(cell) => {
+                *cell = self.options.cell_width.wrap(value);
+                true
+            }
+            None => false,
+        }
+    }
+
+    // Clear the tape, shrinking it back to its initial length if it has grown, and move the pointer to cell 0
+    pub fn reset(&mut self) {
+        self.tape.truncate(self.options.tape_length as usize);
+        self.tape.fill(0);
+        self.pointer = 0;
+    }
+
+    pub fn streams_mut(&mut self) -> (&mut R, &mut W) {
+        (&mut self.input, &mut self.output)
+    }
+
+    pub fn output_mut(&mut self) -> &mut W {
+        &mut self.output
+    }
+
+    // Move the tape pointer, handling the ends of the tape according to the boundary mode; the position is that of
+    // the command responsible for the move, which is reported if the move is an error
+    fn shift(&mut self, shift: i64, position: Position) -> Result<(), RunError> {
+        // The tape can't be longer than the address space, so its length fits in an i64 with room to spare
+        let length = self.tape.len() as i64;
+        let pointer = self.pointer as i64;
+
+        if self.options.boundary == Boundary::Wrap {
+            self.pointer = (pointer + shift % length).rem_euclid(length) as usize;
+            return Ok(());
+        }
+
+        let target = match pointer.checked_add(shift) {
+            Some(target) if target >= 0 => target,
+            _ if shift < 0 => return Err(RunError::OutOfBounds(position, End::Left)),
+            _ => return Err(RunError::OutOfBounds(position, End::Right)),
+        };
+
+        if target >= length {
+            if self.options.boundary == Boundary::Abort {
+                return Err(RunError::OutOfBounds(position, End::Right));
+            }
+
+            // As in compiled programs, grow the tape to cover the new position and at least double its length
+            let new_length = (target as u64)
+                .checked_add(length as u64)
+                .ok_or(RunError::OutOfMemory)?;
+            resize_tape(&mut self.tape, new_length)?;
+        }
+
+        self.pointer = target as usize;
+        Ok(())
+    }
+
+    fn add(&mut self, value: i64) {
+        let cell = &mut self.tape[self.pointer];
+        *cell = self.options.cell_width.wrap((*cell as i64).wrapping_add(value));
+    }
+
+    fn read(&mut self) -> Result<(), RunError> {
+        // Like compiled programs, flush any buffered output before waiting for input
+        self.output.flush().map_err(RunError::Write)?;
+
+        let mut byte = [0];
+
+        loop {
+            match self.input.read(&mut byte) {
+                Ok(0) => {
+                    match self.options.eof {
+                        Eof::Unchanged => (),
+                        Eof::Zero => self.tape[self.pointer] = 0,
+                        Eof::MinusOne => self.tape[self.pointer] = self.options.cell_width.wrap(-1),
+                    }
+
+                    return Ok(());
+                }
+                Ok(_) => {
+                    self.tape[self.pointer] = u64::from(byte[0]);
+                    return Ok(());
+                }
+                Err(ref error) if error.kind() == io::ErrorKind::Interrupted => continue,
+                Err(error) => return Err(RunError::Read(error)),
+            }
+        }
+    }
+
+    fn write(&mut self) -> Result<(), RunError> {
+        // Only the low 8 bits of the cell are written
+        let byte = self.cell() as u8;
+        self.output.write_all(&[byte]).map_err(RunError::Write)?;
+
+        if self.options.flush_on_newline && byte == b'\n' {
+            self.output.flush().map_err(RunError::Write)?;
+        }
+
+        Ok(())
+    }
+
+    // Write the tape pointer and the cells around it to standard error, in the same format as compiled programs
+    fn dump(&mut self, position: Position) -> Result<(), RunError> {
+        self.output.flush().map_err(RunError::Write)?;
+
+        let radius = usize::from(DEBUG_RADIUS);
+        let start = self.pointer.saturating_sub(radius);
+        let end = self.pointer.saturating_add(radius).min(self.tape.len() - 1);
+
+        let mut text = format!(
+            "debug: line {}, column {}: pointer {}, cells {} to {}:",
+            position.line, position.column, self.pointer, start, end
+        );
+
+        for index in start..=end {
+            if index == self.pointer {
+                text.push_str(&format!(" [{}]", self.tape[index]));
+            } else {
+                text.push_str(&format!(" {}", self.tape[index]));
+            }
+        }
+
+        text.push('\n');
+
+        // As in compiled programs, nothing can be done if this fails
+        let _ = io::stderr().write_all(text.as_bytes());
+        Ok(())
+    }
+}
+
+// Grow the tape to the given length with zeroed cells, failing gracefully if there isn't enough memory
+fn resize_tape(tape: &mut Vec<u64>, length: u64) -> Result<(), RunError> {
+    let additional = usize::try_from(length)
+        .ok()
+        .and_then(|length| length.checked_sub(tape.len()))
+        .ok_or(RunError::OutOfMemory)?;
+
+    tape.try_reserve_exact(additional).map_err(|_| RunError::OutOfMemory)?;
+    tape.resize(tape.len() + additional, 0);
+    Ok(())
+}
+
+// The ends of the tape
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum End {
+    Left,
+    Right,
+}
+
+pub enum RunError {
+    Parse(ParseError),
+    Options(OptionsError),
+    Read(io::Error),
+    Write(io::Error),
+    OutOfBounds(Position, End),
+    OutOfMemory,
+}
+
+impl Display for RunError {
+    fn fmt(&self, formatter: &mut Formatter) -> fmt::Result {
+        match self {
+            RunError::Parse(error) => Display::fmt(error, formatter),
+            RunError::Options(error) => Display::fmt(error, formatter),
+            RunError::Read(error) => write!(formatter, "cannot read input: {}", error),
+            RunError::Write(error) => write!(formatter, "cannot write output: {}", error),
+            RunError::OutOfBounds(position, end) => write!(
+                formatter,
+                "tape pointer moved past the {} end of the tape at line {}, column {}",
+                if *end == End::Left { "left" } else { "right" },
+                position.line,
+                position.column
+            ),
+            RunError::OutOfMemory => write!(formatter, "out of memory for the tape"),
+        }
+    }
+}
+
+impl Debug for RunError {
+    fn fmt(&self, formatter: &mut Formatter) -> fmt::Result {
+        match self {
+            RunError::Parse(error) => Debug::fmt(error, formatter),
+            RunError::Read(error) => Debug::fmt(error, formatter),
+            RunError::Write(error) => Debug::fmt(error, formatter),
+            error => Display::fmt(error, formatter),
+        }
+    }
+}
+
+impl From<ParseError> for RunError {
+    fn from(error: ParseError) -> Self {
+        RunError::Parse(error)
+    }
+}
+
+impl From<OptionsError> for RunError {
+    fn from(error: OptionsError) -> Self {
+        RunError::Options(error)
+    }
+}
@@ -0,0 +1,165 @@
+use std::io;
+use std::mem;
+
+use crate::parser::{parse, ParseError, SyntaxError, Token};
+use crate::stream::{Span, Stream};
+
+/*
+    The intermediate representation is a tree: a program is a sequence of nodes, and
+    each loop node owns the sequence of nodes making up its body. Every node records
+    the span of source it was built from; a loop's span runs from its `[` to its `]`.
+
+    Bracket matching happens while building the tree, so any program that has been
+    successfully converted to the IR is well-formed.
+*/
+
+// The number of cells on either side of the tape pointer which `#` shows
+pub const DEBUG_RADIUS: u8 = 8;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Node {
+    pub op: Op,
+    pub span: Span,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Op {
+    Move(i64),
+    Add(i64),
+    Read,
+    Write,
+    Loop(Vec<Node>),
+    // The `#` debugging command, which is only recognized on request
+    Debug,
+    // Set the current cell to zero; produced by the optimizer from loops like [-]
+    Clear,
+    // For each (offset, factor) pair, add factor times the current cell to the cell at that offset, then set
+    // the current cell to zero; produced by the optimizer from loops like [->+>++<<]
+    MultiplyAdd(Vec<(i64, i64)>),
+    // Move by the given stride until the current cell is zero; produced by the optimizer from loops like [>]
+    Scan(i64),
+    // A run of moves and cell updates like >+>>-<, each paired with its offset from the cell the run started on
+    // (for a move, the offset it moves from), and the net shift of the run; produced by the optimizer, so that
+    // the cells can be addressed relative to the tape pointer and the pointer moved only once
+    Block(Vec<(i64, Node)>, i64),
+}
+
+impl Node {
+    pub fn new(op: Op, span: Span) -> Self {
+        Self { op, span }
+    }
+}
+
+// With debug set, `#` is parsed as a command rather than a comment
+pub fn build<R: io::Read>(stream: &mut Stream<R>, debug: bool) -> Result<Vec<Node>, ParseError> {
+    // For each loop that is currently open, the nodes preceding it and the span of its `[`
+    let mut open_loops: Vec<(Vec<Node>, Span)> = vec![];
+    let mut nodes = vec![];
+
+    loop {
+        let (token, span) = parse(stream, debug)?;
+
+        let op = match token {
+            Token::Move(shift) => Op::Move(shift),
+            Token::Add(value) => Op::Add(value),
+            Token::ReadChar => Op::Read,
+            Token::WriteChar => Op::Write,
+            Token::Debug => Op::Debug,
+            Token::LoopStart => {
+                open_loops.push((mem::take(&mut nodes), span));
+                continue;
+            }
+            Token::LoopEnd => {
+                let (outer, start) = match open_loops.pop() {
+                    Some(entry) => entry,
+                    None => return Err(SyntaxError::new(span.start, "unmatched `]`").into()),
+                };
+
+                let body = mem::replace(&mut nodes, outer);
+                nodes.push(Node::new(Op::Loop(body), Span::new(start.start, span.end)));
+                continue;
+            }
+            Token::EndOfFile => break,
+        };
+
+        nodes.push(Node::new(op, span));
+    }
+
+    // Report the outermost unclosed loop, noting the position of every other loop that was left open
+    if let Some((_, span)) = open_loops.first() {
+        let mut error = SyntaxError::new(span.start, "unclosed `[`");
+
+        for (_, span) in &open_loops[1..] {
+            error.add_note(span.start, "loop opened here is also unclosed");
+        }
+
+        return Err(error.into());
+    }
+
+    Ok(nodes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn build_str(source: &str) -> Result<Vec<Node>, ParseError> {
+        build(&mut Stream::new(source.as_bytes()), false)
+    }
+
+    // The position of a syntax error, and those of its notes, as (line, column) pairs
+    fn syntax_error(source: &str) -> (String, (usize, usize), Vec<(usize, usize)>) {
+        let error = match build_str(source) {
+            Err(ParseError::Syntax(error)) => error,
+            result => panic!("expected a syntax error, got {:?}", result),
+        };
+
+        let position = error.position();
+        let notes = error
+            .notes()
+            .iter()
+            .map(|note| (note.position.line, note.position.column))
+            .collect();
+
+        (error.message().to_string(), (position.line, position.column), notes)
+    }
+
+    #[test]
+    fn unmatched_loop_end() {
+        assert_eq!(syntax_error("+]"), ("unmatched `]`".to_string(), (1, 2), vec![]));
+        assert_eq!(syntax_error("[]\n ]["), ("unmatched `]`".to_string(), (2, 2), vec![]));
+    }
+
+    #[test]
+    fn unclosed_loop_start() {
+        assert_eq!(syntax_error("+["), ("unclosed `[`".to_string(), (1, 2), vec![]));
+
+        // The outermost unclosed loop is reported, with a note for each other one
+        assert_eq!(
+            syntax_error("[\n[]\n[ [+"),
+            ("unclosed `[`".to_string(), (1, 1), vec![(3, 1), (3, 3)])
+        );
+    }
+
+    #[test]
+    fn loops() {
+        let nodes = build_str("+[>[-]<]").unwrap();
+        assert_eq!(nodes.len(), 2);
+
+        let outer = &nodes[1];
+        assert_eq!((outer.span.start.column, outer.span.end.column), (2, 9));
+
+        let body = match &outer.op {
+            Op::Loop(body) => body,
+            op => panic!("expected a loop, got {:?}", op),
+        };
+
+        assert_eq!(body.len(), 3);
+        assert_eq!(body[0].op, Op::Move(1));
+        assert_eq!(body[2].op, Op::Move(-1));
+
+        let inner = &body[1];
+        assert_eq!((inner.span.start.column, inner.span.end.column), (4, 7));
+        assert!(matches!(&inner.op, Op::Loop(body) if body.len() == 1 && body[0].op == Op::Add(-1)));
+    }
+}
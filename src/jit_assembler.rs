@@ -0,0 +1,179 @@
+use std::io;
+use std::mem;
+use std::os::raw::{c_int, c_long, c_void};
+use std::ptr;
+
+use crate::assembler::{AllocationError, Assembler};
+use crate::elf::MAX_BSS_SIZE;
+use crate::machine_code::MachineCode;
+
+type Address = u64;
+type Label = usize;
+
+/*
+    The JIT assembler produces the same machine code as the ELF assembler, but allocates
+    memory in the current process, so that addresses embedded in the code are real
+    pointers, and maps the finished code into memory rather than writing an executable.
+    The code must be generated with function linkage, so that it returns to its caller
+    instead of exiting the process.
+
+    The generated code points rsp at the output buffer. If a signal is delivered to a
+    handler on the ordinary stack while it runs, the kernel pushes the signal frame below
+    rsp, so every allocation is preceded by some padding to absorb it.
+*/
+
+const PADDING: usize = 64 * 1024;
+
+const PROT_READ: c_int = 0x01;
+const PROT_WRITE: c_int = 0x02;
+const PROT_EXEC: c_int = 0x04;
+
+const MAP_PRIVATE: c_int = 0x02;
+const MAP_ANONYMOUS: c_int = 0x20;
+const MAP_NORESERVE: c_int = 0x4000;
+
+extern "C" {
+    fn mmap(address: *mut c_void, length: usize, prot: c_int, flags: c_int, fd: c_int, offset: c_long) -> *mut c_void;
+    fn mprotect(address: *mut c_void, length: usize, prot: c_int) -> c_int;
+    fn munmap(address: *mut c_void, length: usize) -> c_int;
+}
+
+// Zeroed, readable and writable memory, which is unmapped when dropped
+struct Mapping {
+    address: *mut c_void,
+    length: usize,
+}
+
+impl Mapping {
+    fn new(length: usize) -> io::Result<Self> {
+        // Memory is only committed as it's touched, as for the bss of an executable
+        let flags = MAP_PRIVATE | MAP_ANONYMOUS | MAP_NORESERVE;
+        let address = unsafe { mmap(ptr::null_mut(), length, PROT_READ | PROT_WRITE, flags, -1, 0) };
+
+        // MAP_FAILED is -1
+        if address as isize == -1 {
+            return Err(io::Error::last_os_error());
+        }
+
+        Ok(Self { address, length })
+    }
+
+    fn make_executable(&self) -> io::Result<()> {
+        if unsafe { mprotect(self.address, self.length, PROT_READ | PROT_EXEC) } != 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        Ok(())
+    }
+}
+
+impl Drop for Mapping {
+    fn drop(&mut self) {
+        unsafe {
+            munmap(self.address, self.length);
+        }
+    }
+}
+
+pub struct JitAssembler {
+    allocated: u64,
+    mappings: Vec<Mapping>,
+    machine_code: MachineCode,
+}
+
+impl JitAssembler {
+    pub fn new() -> Self {
+        Self {
+            allocated: 0,
+            mappings: vec![],
+            machine_code: MachineCode::new(),
+        }
+    }
+
+    // Map the code into memory as executable, returning a program which can be run
+    pub fn finish(self) -> io::Result<JitProgram> {
+        let code = Mapping::new(self.machine_code.bytes().len())?;
+
+        unsafe {
+            let bytes = self.machine_code.bytes();
+            ptr::copy_nonoverlapping(bytes.as_ptr(), code.address as *mut u8, bytes.len());
+        }
+
+        code.make_executable()?;
+
+        Ok(JitProgram {
+            code,
+            _memory: self.mappings,
+        })
+    }
+}
+
+impl Default for JitAssembler {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<'a> Assembler<'a> for JitAssembler {
+    type Address = Address;
+    type Label = Label;
+
+    // Allows as much memory as an executable's bss, so that the same programs can be compiled either way
+    fn allocate_memory(&mut self, size: u64) -> Result<Self::Address, AllocationError> {
+        let available = MAX_BSS_SIZE - self.allocated;
+
+        if size > available {
+            return Err(AllocationError { size, available });
+        }
+
+        // The kernel may refuse even so, in which case there's no telling how much memory it would have allowed
+        let mapping = Mapping::new(PADDING + size as usize).map_err(|_| AllocationError { size, available: 0 })?;
+        let address = mapping.address as u64 + PADDING as u64;
+
+        self.allocated += size;
+        self.mappings.push(mapping);
+        Ok(address)
+    }
+
+    fn allocate_label(&mut self) -> Self::Label {
+        self.machine_code.allocate_label()
+    }
+
+    fn label(&mut self, label: Self::Label) {
+        self.machine_code.label(label);
+    }
+
+    fn data(&mut self, bytes: &[u8]) {
+        self.machine_code.extend(bytes);
+    }
+
+    // Write the raw machine code, which only makes sense alongside the memory this assembler has allocated
+    fn assemble<W: io::Write>(self, output: &mut W) -> Result<(), io::Error> {
+        output.write_all(self.machine_code.bytes())
+    }
+
+    instructions!();
+}
+
+// Machine code mapped into memory, along with the memory it uses
+pub struct JitProgram {
+    code: Mapping,
+    _memory: Vec<Mapping>,
+}
+
+impl JitProgram {
+    /// Run the program, returning its exit code. The program reads and writes the standard streams directly, without
+    /// regard for any buffering in io::Stdin and io::Stdout. It can only be run once, since it doesn't reset its
+    /// memory; a growable tape, which the program maps itself, is unmapped before it returns.
+    ///
+    /// # Safety
+    ///
+    /// The code must have been generated by `compiler::generate` with function linkage, using memory allocated by
+    /// the same assembler, since it's called as is. While it runs, rsp points into that memory rather than the
+    /// thread's stack, so signal handlers which run on the ordinary stack must fit in the padding before each
+    /// allocation.
+    pub unsafe fn run(self) -> i32 {
+        let entry: extern "sysv64" fn() -> i32 = mem::transmute(self.code.address);
+        entry()
+    }
+}
@@ -0,0 +1,85 @@
+// Declared first, since its macros are used by the assemblers
+#[macro_use]
+pub mod machine_code;
+
+pub mod assembler;
+pub mod compiler;
+pub mod console;
+pub mod debugger;
+pub mod diagnostic;
+pub mod dwarf;
+pub mod elf;
+pub mod elf_assembler;
+pub mod interpreter;
+pub mod ir;
+pub mod jit_assembler;
+pub mod listing_assembler;
+pub mod optimizer;
+pub mod options;
+pub mod parser;
+pub mod repl;
+pub mod stream;
+
+use std::io;
+use std::io::Write;
+
+pub use crate::assembler::AllocationError;
+pub use crate::compiler::CompileError;
+pub use crate::interpreter::RunError;
+pub use crate::options::{Boundary, CellWidth, Eof, Options, OptionsError, Passes};
+pub use crate::parser::{ParseError, SyntaxError};
+pub use crate::stream::Position;
+
+use crate::interpreter::Interpreter;
+use crate::stream::Stream;
+
+/// Compile the brainfuck program read from `input` into an x86-64 Linux ELF executable, written to `output`.
+pub fn compile<R: io::Read, W: io::Write>(input: R, output: &mut W, options: &Options) -> Result<(), CompileError> {
+    compiler::compile(output, Stream::with_tab_width(input, options.tab_width), options)
+}
+
+/// Compile the brainfuck program in `source`, returning the contents of the resulting executable.
+pub fn compile_to_vec(source: &[u8], options: &Options) -> Result<Vec<u8>, CompileError> {
+    let mut executable = vec![];
+    compile(source, &mut executable, options)?;
+    Ok(executable)
+}
+
+/// Write an assembly listing of the executable the brainfuck program in `source` compiles to, in NASM syntax, to
+/// `output`. Each command's code is preceded by a comment quoting the command and giving its line and column.
+pub fn listing<W: io::Write>(source: &[u8], output: &mut W, options: &Options) -> Result<(), CompileError> {
+    compiler::listing(output, source, options)
+}
+
+/// Run the brainfuck program read from `source` directly, without compiling it, reading the program's input from
+/// `input` and writing its output to `output`. The program behaves as its compiled executable would under the same
+/// options.
+pub fn run<S: io::Read, R: io::Read, W: io::Write>(
+    source: S,
+    input: R,
+    output: W,
+    options: &Options,
+) -> Result<(), RunError> {
+    // The interpreter checks the options, so it's created before the program is parsed
+    let mut interpreter = Interpreter::new(options, input, output)?;
+
+    let mut stream = Stream::with_tab_width(source, options.tab_width);
+    let program = optimizer::optimize(ir::build(&mut stream, options.debug_command)?, &options.passes);
+    interpreter.run(&program)
+}
+
+/// Compile the brainfuck program read from `source` into memory and run it within the current process, returning
+/// the status its executable would exit with. The program reads and writes the standard streams directly, after
+/// anything buffered in `io::Stdout` has been flushed.
+pub fn jit<R: io::Read>(source: R, options: &Options) -> Result<i32, CompileError> {
+    let program = compiler::jit(Stream::with_tab_width(source, options.tab_width), options)?;
+
+    // Whatever was buffered would have been written before the program's own output, so failing to write it is a
+    // failure to write output like any other
+    if io::stdout().flush().is_err() {
+        return Ok(compiler::EXIT_WRITE_ERROR as i32);
+    }
+
+    // The program was generated by compiler::jit, with the memory it maps alongside it
+    Ok(unsafe { program.run() })
+}
@@ -0,0 +1,837 @@
+use std::collections::HashSet;
+use std::fmt::Write as _;
+use std::io;
+
+use crate::assembler::{AllocationError, Assembler};
+use crate::elf::{BSS_VIRTUAL_ADDRESS, MAX_BSS_SIZE};
+use crate::stream::Span;
+
+type Address = u64;
+type Label = usize;
+
+/*
+    The listing assembler renders the code the ELF assembler would produce as assembly in
+    NASM syntax, rather than machine code. Each instruction is written as the line of
+    instructions.list it's encoded from, with its operands filled in; labels, and memory
+    the compiler has named, are referred to by name, and the code for each command is
+    preceded by a comment quoting the command. Memory is allocated at the same addresses
+    as in an executable, and listed up front.
+
+    Nothing is rendered until the end, since a label may be named after it's first used.
+*/
+
+// The widest a quoted command gets before it's cut short
+const MAX_QUOTE_LENGTH: usize = 40;
+
+const INDENT: &str = "        ";
+
+// The characters which are commands, apart from `#`, which is only a command on its own
+const COMMANDS: &[u8] = b"+-<>[],.";
+
+enum Operand {
+    Number(String),
+    Address(Address),
+    Label(Label),
+}
+
+enum Line {
+    Instruction(&'static str, Vec<Operand>),
+    Label(Label),
+    Data(Vec<u8>),
+    Source(Span),
+}
+
+pub struct ListingAssembler<'s> {
+    source: &'s [u8],
+    allocation_pointer: u64,
+    lines: Vec<Line>,
+    label_names: Vec<Option<String>>,
+    // Each allocation's address and size, with its name if it has one
+    allocations: Vec<(Address, u64, Option<String>)>,
+}
+
+impl<'s> ListingAssembler<'s> {
+    // The source is that of the program being compiled, from which commands are quoted
+    pub fn new(source: &'s [u8]) -> Self {
+        Self {
+            source,
+            allocation_pointer: BSS_VIRTUAL_ADDRESS,
+            lines: vec![],
+            label_names: vec![],
+            allocations: vec![],
+        }
+    }
+
+    fn instruction(&mut self, template: &'static str, operands: Vec<Operand>) {
+        self.lines.push(Line::Instruction(template, operands));
+    }
+
+    // Every label gets a distinct name: unnamed labels are numbered, and a name given to several labels (e.g. the
+    // stubs for moves off the tape in the fast and slow paths of a block) is numbered after its first use
+    fn resolve_label_names(&self) -> Vec<String> {
+        let mut used = HashSet::new();
+
+        self.label_names
+            .iter()
+            .enumerate()
+            .map(|(label, name)| {
+                let base = match name {
+                    Some(name) => name.clone(),
+                    None => format!("L{}", label),
+                };
+
+                let mut name = base.clone();
+                let mut count = 1;
+
+                while !used.insert(name.clone()) {
+                    count += 1;
+                    name = format!("{}_{}", base, count);
+                }
+
+                name
+            })
+            .collect()
+    }
+
+    fn address_name(&self, address: Address) -> String {
+        let allocation = self.allocations.iter().find(|(start, _, _)| *start == address);
+
+        match allocation {
+            Some((_, _, Some(name))) => name.clone(),
+            _ => format!("{:#x}", address),
+        }
+    }
+
+    // The instruction's template with each of its operands substituted in turn
+    fn render_instruction(&self, template: &str, operands: &[Operand], label_names: &[String]) -> String {
+        let mut text = String::new();
+        let mut operands = operands.iter();
+        let mut rest = template;
+
+        while let Some(index) = rest.find('$') {
+            let (before, after) = rest.split_at(index);
+            let length = after[1..]
+                .find(|character: char| !character.is_ascii_alphanumeric())
+                .map_or(after.len(), |length| length + 1);
+
+            let operand = match operands.next().unwrap() {
+                Operand::Number(number) => number.clone(),
+                Operand::Address(address) => self.address_name(*address),
+                Operand::Label(label) => label_names[*label].clone(),
+            };
+
+            // A negative displacement is subtracted, rather than added
+            match (before.strip_suffix('+'), operand.strip_prefix('-')) {
+                (Some(before), Some(magnitude)) => write!(text, "{}-{}", before, magnitude).unwrap(),
+                _ => write!(text, "{}{}", before, operand).unwrap(),
+            }
+
+            rest = &after[length..];
+        }
+
+        text.push_str(rest);
+        text
+    }
+
+    // The commands in a span, without any comment characters between them
+    fn quote(&self, span: Span) -> String {
+        let text = &self.source[span.start.offset..span.end.offset];
+        let commands: String = text
+            .iter()
+            .filter(|byte| COMMANDS.contains(byte))
+            .map(|&byte| byte as char)
+            .collect();
+
+        let commands = if commands.is_empty() { "#".to_string() } else { commands };
+
+        if commands.len() > MAX_QUOTE_LENGTH {
+            format!("{}...", &commands[..MAX_QUOTE_LENGTH])
+        } else {
+            commands
+        }
+    }
+}
+
+// Bytes as the operands of db: runs of printable characters are quoted, and anything else is given in decimal. A lone
+// printable character is more likely part of a number than of text, so it's given in decimal too
+fn render_data(bytes: &[u8]) -> String {
+    let mut parts = vec![];
+    let mut run = vec![];
+
+    for &byte in bytes {
+        if (b' '..=b'~').contains(&byte) && byte != b'"' {
+            run.push(byte);
+            continue;
+        }
+
+        push_run(&mut parts, &mut run);
+        parts.push(byte.to_string());
+    }
+
+    push_run(&mut parts, &mut run);
+    parts.join(", ")
+}
+
+fn push_run(parts: &mut Vec<String>, run: &mut Vec<u8>) {
+    match run.len() {
+        0 => (),
+        1 => parts.push(run[0].to_string()),
+        _ => parts.push(format!("\"{}\"", String::from_utf8_lossy(run))),
+    }
+
+    run.clear();
+}
+
+/*
+    The macros below implement the instruction methods of the Assembler trait for the
+    listing assembler. The body of `listing_instructions!` is generated from
+    instructions.list by script/generate-listing-methods.
+*/
+
+macro_rules! listing_instr {
+    ($name:ident, $template:expr) => {
+        fn $name(&mut self) {
+            self.instruction($template, vec![]);
+        }
+    };
+
+    ($name:ident, $template:expr, label) => {
+        fn $name(&mut self, label: Self::Label) {
+            self.instruction($template, vec![Operand::Label(label)]);
+        }
+    };
+
+    ($name:ident, $template:expr, addr) => {
+        fn $name(&mut self, addr: Self::Address) {
+            self.instruction($template, vec![Operand::Address(addr)]);
+        }
+    };
+
+    ($name:ident, $template:expr, $operand_type:ty) => {
+        fn $name(&mut self, operand: $operand_type) {
+            self.instruction($template, vec![Operand::Number(operand.to_string())]);
+        }
+    };
+
+    ($name:ident, $template:expr, $displacement_type:ty, $operand_type:ty) => {
+        fn $name(&mut self, displacement: $displacement_type, operand: $operand_type) {
+            let operands = vec![
+                Operand::Number(displacement.to_string()),
+                Operand::Number(operand.to_string()),
+            ];
+
+            self.instruction($template, operands);
+        }
+    };
+}
+
+macro_rules! listing_instructions {
+    () => {
+        listing_instr!(
+            add_byte_ptr_rbx_plus_r8_plus_i32_u8,
+            "add BYTE [rbx+r8+$i32], $u8",
+            i32,
+            u8
+        );
+        listing_instr!(
+            add_byte_ptr_rbx_plus_r8_plus_i32_al,
+            "add BYTE [rbx+r8+$i32], al",
+            i32
+        );
+        listing_instr!(
+            add_byte_ptr_rbx_plus_r8_plus_i32_cl,
+            "add BYTE [rbx+r8+$i32], cl",
+            i32
+        );
+        listing_instr!(add_byte_ptr_rbx_plus_r8_u8, "add BYTE [rbx+r8], $u8", u8);
+        listing_instr!(add_byte_ptr_rbx_plus_r8_al, "add BYTE [rbx+r8], al");
+        listing_instr!(add_byte_ptr_rbx_plus_r8_cl, "add BYTE [rbx+r8], cl");
+        listing_instr!(add_dl_u8, "add dl, $u8", u8);
+        listing_instr!(
+            add_dword_ptr_rbx_plus_r8_times_4_plus_i32_u32,
+            "add DWORD [rbx+r8*4+$i32], $u32",
+            i32,
+            u32
+        );
+        listing_instr!(
+            add_dword_ptr_rbx_plus_r8_times_4_plus_i32_eax,
+            "add DWORD [rbx+r8*4+$i32], eax",
+            i32
+        );
+        listing_instr!(
+            add_dword_ptr_rbx_plus_r8_times_4_plus_i32_ecx,
+            "add DWORD [rbx+r8*4+$i32], ecx",
+            i32
+        );
+        listing_instr!(
+            add_dword_ptr_rbx_plus_r8_times_4_u32,
+            "add DWORD [rbx+r8*4], $u32",
+            u32
+        );
+        listing_instr!(add_dword_ptr_rbx_plus_r8_times_4_eax, "add DWORD [rbx+r8*4], eax");
+        listing_instr!(add_dword_ptr_rbx_plus_r8_times_4_ecx, "add DWORD [rbx+r8*4], ecx");
+        listing_instr!(
+            add_qword_ptr_rbx_plus_r8_times_8_i32,
+            "add QWORD [rbx+r8*8], $i32",
+            i32
+        );
+        listing_instr!(
+            add_qword_ptr_rbx_plus_r8_times_8_plus_i32_i32,
+            "add QWORD [rbx+r8*8+$i32], $i32",
+            i32,
+            i32
+        );
+        listing_instr!(
+            add_qword_ptr_rbx_plus_r8_times_8_plus_i32_r15,
+            "add QWORD [rbx+r8*8+$i32], r15",
+            i32
+        );
+        listing_instr!(
+            add_qword_ptr_rbx_plus_r8_times_8_plus_i32_rax,
+            "add QWORD [rbx+r8*8+$i32], rax",
+            i32
+        );
+        listing_instr!(
+            add_qword_ptr_rbx_plus_r8_times_8_plus_i32_rcx,
+            "add QWORD [rbx+r8*8+$i32], rcx",
+            i32
+        );
+        listing_instr!(add_qword_ptr_rbx_plus_r8_times_8_r15, "add QWORD [rbx+r8*8], r15");
+        listing_instr!(add_qword_ptr_rbx_plus_r8_times_8_rax, "add QWORD [rbx+r8*8], rax");
+        listing_instr!(add_qword_ptr_rbx_plus_r8_times_8_rcx, "add QWORD [rbx+r8*8], rcx");
+        listing_instr!(add_r11_i8, "add r11, $i8", i8);
+        listing_instr!(add_r15_i32, "add r15, $i32", i32);
+        listing_instr!(add_r15_i8, "add r15, $i8", i8);
+        listing_instr!(add_r15_rax, "add r15, rax");
+        listing_instr!(add_r8_i32, "add r8, $i32", i32);
+        listing_instr!(add_r8_i8, "add r8, $i8", i8);
+        listing_instr!(add_r8_r15, "add r8, r15");
+        listing_instr!(add_r8_r9, "add r8, r9");
+        listing_instr!(add_r8_rax, "add r8, rax");
+        listing_instr!(add_rdx_r9, "add rdx, r9");
+        listing_instr!(add_rsi_i8, "add rsi, $i8", i8);
+        listing_instr!(add_rsi_r15, "add rsi, r15");
+        listing_instr!(
+            add_word_ptr_rbx_plus_r8_times_2_plus_i32_u16,
+            "add WORD [rbx+r8*2+$i32], $u16",
+            i32,
+            u16
+        );
+        listing_instr!(
+            add_word_ptr_rbx_plus_r8_times_2_plus_i32_ax,
+            "add WORD [rbx+r8*2+$i32], ax",
+            i32
+        );
+        listing_instr!(
+            add_word_ptr_rbx_plus_r8_times_2_plus_i32_cx,
+            "add WORD [rbx+r8*2+$i32], cx",
+            i32
+        );
+        listing_instr!(
+            add_word_ptr_rbx_plus_r8_times_2_u16,
+            "add WORD [rbx+r8*2], $u16",
+            u16
+        );
+        listing_instr!(add_word_ptr_rbx_plus_r8_times_2_ax, "add WORD [rbx+r8*2], ax");
+        listing_instr!(add_word_ptr_rbx_plus_r8_times_2_cx, "add WORD [rbx+r8*2], cx");
+        listing_instr!(and_eax_u32, "and eax, $u32", u32);
+        listing_instr!(bsf_eax_eax, "bsf eax, eax");
+        listing_instr!(bsr_eax_eax, "bsr eax, eax");
+        listing_instr!(cmovae_r8_r15, "cmovae r8, r15");
+        listing_instr!(cmp_byte_ptr_rbx_plus_r8_u8, "cmp BYTE [rbx+r8], $u8", u8);
+        listing_instr!(
+            cmp_dword_ptr_rbx_plus_r8_times_4_u32,
+            "cmp DWORD [rbx+r8*4], $u32",
+            u32
+        );
+        listing_instr!(
+            cmp_qword_ptr_rbx_plus_r8_times_8_i32,
+            "cmp QWORD [rbx+r8*8], $i32",
+            i32
+        );
+        listing_instr!(cmp_r10_r11, "cmp r10, r11");
+        listing_instr!(cmp_r10_r12, "cmp r10, r12");
+        listing_instr!(cmp_r11_r9, "cmp r11, r9");
+        listing_instr!(cmp_r13_u32, "cmp r13, $u32", u32);
+        listing_instr!(cmp_r13_rbp, "cmp r13, rbp");
+        listing_instr!(cmp_r15b_u8, "cmp r15b, $u8", u8);
+        listing_instr!(cmp_r15_r13, "cmp r15, r13");
+        listing_instr!(cmp_r15_r9, "cmp r15, r9");
+        listing_instr!(cmp_r8_i32, "cmp r8, $i32", i32);
+        listing_instr!(cmp_r8_i8, "cmp r8, $i8", i8);
+        listing_instr!(cmp_r8_r9, "cmp r8, r9");
+        listing_instr!(cmp_rax_u32, "cmp rax, $u32", u32);
+        listing_instr!(cmp_rbp_r11, "cmp rbp, r11");
+        listing_instr!(cmp_rbp_r8, "cmp rbp, r8");
+        listing_instr!(
+            cmp_word_ptr_rbx_plus_r8_times_2_u16,
+            "cmp WORD [rbx+r8*2], $u16",
+            u16
+        );
+        listing_instr!(dec_byte_ptr_rbx_plus_r8, "dec BYTE [rbx+r8]");
+        listing_instr!(dec_byte_ptr_rbx_plus_r8_plus_i32, "dec BYTE [rbx+r8+$i32]", i32);
+        listing_instr!(dec_dword_ptr_rbx_plus_r8_times_4, "dec DWORD [rbx+r8*4]");
+        listing_instr!(
+            dec_dword_ptr_rbx_plus_r8_times_4_plus_i32,
+            "dec DWORD [rbx+r8*4+$i32]",
+            i32
+        );
+        listing_instr!(dec_qword_ptr_rbx_plus_r8_times_8, "dec QWORD [rbx+r8*8]");
+        listing_instr!(
+            dec_qword_ptr_rbx_plus_r8_times_8_plus_i32,
+            "dec QWORD [rbx+r8*8+$i32]",
+            i32
+        );
+        listing_instr!(dec_r11, "dec r11");
+        listing_instr!(dec_rsi, "dec rsi");
+        listing_instr!(dec_word_ptr_rbx_plus_r8_times_2, "dec WORD [rbx+r8*2]");
+        listing_instr!(
+            dec_word_ptr_rbx_plus_r8_times_2_plus_i32,
+            "dec WORD [rbx+r8*2+$i32]",
+            i32
+        );
+        listing_instr!(div_rcx, "div rcx");
+        listing_instr!(imul_ecx_eax_i32, "imul ecx, eax, $i32", i32);
+        listing_instr!(imul_rcx_rax, "imul rcx, rax");
+        listing_instr!(inc_byte_ptr_rbx_plus_r8, "inc BYTE [rbx+r8]");
+        listing_instr!(inc_byte_ptr_rbx_plus_r8_plus_i32, "inc BYTE [rbx+r8+$i32]", i32);
+        listing_instr!(inc_dword_ptr_rbx_plus_r8_times_4, "inc DWORD [rbx+r8*4]");
+        listing_instr!(
+            inc_dword_ptr_rbx_plus_r8_times_4_plus_i32,
+            "inc DWORD [rbx+r8*4+$i32]",
+            i32
+        );
+        listing_instr!(inc_qword_ptr_rbx_plus_r8_times_8, "inc QWORD [rbx+r8*8]");
+        listing_instr!(
+            inc_qword_ptr_rbx_plus_r8_times_8_plus_i32,
+            "inc QWORD [rbx+r8*8+$i32]",
+            i32
+        );
+        listing_instr!(inc_r10, "inc r10");
+        listing_instr!(inc_r13, "inc r13");
+        listing_instr!(inc_r15, "inc r15");
+        listing_instr!(inc_rbp, "inc rbp");
+        listing_instr!(inc_rdi, "inc rdi");
+        listing_instr!(inc_word_ptr_rbx_plus_r8_times_2, "inc WORD [rbx+r8*2]");
+        listing_instr!(
+            inc_word_ptr_rbx_plus_r8_times_2_plus_i32,
+            "inc WORD [rbx+r8*2+$i32]",
+            i32
+        );
+        listing_instr!(jae, "jae $label", label);
+        listing_instr!(jb, "jb $label", label);
+        listing_instr!(je, "je $label", label);
+        listing_instr!(jg, "jg $label", label);
+        listing_instr!(jge, "jge $label", label);
+        listing_instr!(jmp, "jmp $label", label);
+        listing_instr!(jmp_r15, "jmp r15");
+        listing_instr!(jne, "jne $label", label);
+        listing_instr!(jns, "jns $label", label);
+        listing_instr!(js, "js $label", label);
+        listing_instr!(lea_r15_ptr_rel, "lea r15, [rel $label]", label);
+        listing_instr!(lea_rbp_ptr_rel, "lea rbp, [rel $label]", label);
+        listing_instr!(lea_rsi_ptr_rbp_plus_i8, "lea rsi, [rbp+$i8]", i8);
+        listing_instr!(
+            mov_byte_ptr_rbx_plus_r8_plus_i32_u8,
+            "mov BYTE [rbx+r8+$i32], $u8",
+            i32,
+            u8
+        );
+        listing_instr!(mov_byte_ptr_rbx_plus_r8_u8, "mov BYTE [rbx+r8], $u8", u8);
+        listing_instr!(mov_byte_ptr_rbx_plus_r8_r15b, "mov BYTE [rbx+r8], r15b");
+        listing_instr!(mov_byte_ptr_rdi_u8, "mov BYTE [rdi], $u8", u8);
+        listing_instr!(mov_byte_ptr_rsi_dl, "mov BYTE [rsi], dl");
+        listing_instr!(mov_byte_ptr_rsp_plus_r13_r15b, "mov BYTE [rsp+r13], r15b");
+        listing_instr!(movdqu_xmm1_ptr_rbx_plus_r15, "movdqu xmm1, [rbx+r15]");
+        listing_instr!(movdqu_xmm1_ptr_rbx_plus_r8, "movdqu xmm1, [rbx+r8]");
+        listing_instr!(
+            mov_dword_ptr_rbx_plus_r8_times_4_plus_i32_u32,
+            "mov DWORD [rbx+r8*4+$i32], $u32",
+            i32,
+            u32
+        );
+        listing_instr!(
+            mov_dword_ptr_rbx_plus_r8_times_4_u32,
+            "mov DWORD [rbx+r8*4], $u32",
+            u32
+        );
+        listing_instr!(
+            mov_dword_ptr_rbx_plus_r8_times_4_r15d,
+            "mov DWORD [rbx+r8*4], r15d"
+        );
+        listing_instr!(mov_eax_dword_ptr_rbx_plus_r8_times_4, "mov eax, DWORD [rbx+r8*4]");
+        listing_instr!(
+            mov_eax_dword_ptr_rbx_plus_r8_times_4_plus_i32,
+            "mov eax, DWORD [rbx+r8*4+$i32]",
+            i32
+        );
+        listing_instr!(
+            mov_eax_dword_ptr_rbx_plus_rbp_times_4,
+            "mov eax, DWORD [rbx+rbp*4]"
+        );
+        listing_instr!(mov_qword_ptr_r15_rsp, "mov QWORD [r15], rsp");
+        listing_instr!(mov_qword_ptr_rax_plus_i8_rdx, "mov QWORD [rax+$i8], rdx", i8);
+        listing_instr!(mov_qword_ptr_rax_r15, "mov QWORD [rax], r15");
+        listing_instr!(mov_qword_ptr_rax_rbx, "mov QWORD [rax], rbx");
+        listing_instr!(
+            mov_qword_ptr_rbx_plus_r8_times_8_i32,
+            "mov QWORD [rbx+r8*8], $i32",
+            i32
+        );
+        listing_instr!(
+            mov_qword_ptr_rbx_plus_r8_times_8_plus_i32_i32,
+            "mov QWORD [rbx+r8*8+$i32], $i32",
+            i32,
+            i32
+        );
+        listing_instr!(mov_qword_ptr_rbx_plus_r8_times_8_r15, "mov QWORD [rbx+r8*8], r15");
+        listing_instr!(mov_r10_u32, "mov r10, $u32", u32);
+        listing_instr!(mov_r10_rbp, "mov r10, rbp");
+        listing_instr!(mov_r11_r8, "mov r11, r8");
+        listing_instr!(mov_r11_r9, "mov r11, r9");
+        listing_instr!(mov_r11_rax, "mov r11, rax");
+        listing_instr!(mov_r12_u64, "mov r12, $u64", u64);
+        listing_instr!(mov_r12_rax, "mov r12, rax");
+        listing_instr!(mov_r13_u32, "mov r13, $u32", u32);
+        listing_instr!(mov_r14_addr, "mov r14, $addr", addr);
+        listing_instr!(mov_r15_addr, "mov r15, $addr", addr);
+        listing_instr!(mov_r15_u64, "mov r15, $u64", u64);
+        listing_instr!(mov_r15b_byte_ptr_r14_plus_r10, "mov r15b, BYTE [r14+r10]");
+        listing_instr!(mov_r15b_byte_ptr_rbx_plus_r8, "mov r15b, BYTE [rbx+r8]");
+        listing_instr!(mov_r15b_byte_ptr_rbx_plus_r8_times_2, "mov r15b, BYTE [rbx+r8*2]");
+        listing_instr!(mov_r15b_byte_ptr_rbx_plus_r8_times_4, "mov r15b, BYTE [rbx+r8*4]");
+        listing_instr!(mov_r15b_byte_ptr_rbx_plus_r8_times_8, "mov r15b, BYTE [rbx+r8*8]");
+        listing_instr!(mov_r15_qword_ptr_rax, "mov r15, QWORD [rax]");
+        listing_instr!(mov_r15_r8, "mov r15, r8");
+        listing_instr!(mov_r8_u64, "mov r8, $u64", u64);
+        listing_instr!(mov_r8_r15, "mov r8, r15");
+        listing_instr!(mov_r9_u64, "mov r9, $u64", u64);
+        listing_instr!(mov_r9_rax, "mov r9, rax");
+        listing_instr!(mov_r9_rdx, "mov r9, rdx");
+        listing_instr!(mov_rax_addr, "mov rax, $addr", addr);
+        listing_instr!(mov_rax_u32, "mov rax, $u32", u32);
+        listing_instr!(mov_rax_qword_ptr_rbx_plus_r8_times_8, "mov rax, QWORD [rbx+r8*8]");
+        listing_instr!(
+            mov_rax_qword_ptr_rbx_plus_r8_times_8_plus_i32,
+            "mov rax, QWORD [rbx+r8*8+$i32]",
+            i32
+        );
+        listing_instr!(
+            mov_rax_qword_ptr_rbx_plus_rbp_times_8,
+            "mov rax, QWORD [rbx+rbp*8]"
+        );
+        listing_instr!(mov_rax_r11, "mov rax, r11");
+        listing_instr!(mov_rax_r12, "mov rax, r12");
+        listing_instr!(mov_rax_r8, "mov rax, r8");
+        listing_instr!(mov_rax_r9, "mov rax, r9");
+        listing_instr!(mov_rax_rbp, "mov rax, rbp");
+        listing_instr!(mov_rax_rdi, "mov rax, rdi");
+        listing_instr!(mov_rbp_u64, "mov rbp, $u64", u64);
+        listing_instr!(mov_rbp_r10, "mov rbp, r10");
+        listing_instr!(mov_rbp_r8, "mov rbp, r8");
+        listing_instr!(mov_rbx_addr, "mov rbx, $addr", addr);
+        listing_instr!(mov_rbx_rax, "mov rbx, rax");
+        listing_instr!(mov_rcx_u32, "mov rcx, $u32", u32);
+        listing_instr!(mov_rcx_u64, "mov rcx, $u64", u64);
+        listing_instr!(mov_rcx_qword_ptr_rbp, "mov rcx, QWORD [rbp]");
+        listing_instr!(mov_rcx_r15, "mov rcx, r15");
+        listing_instr!(mov_rdi_addr, "mov rdi, $addr", addr);
+        listing_instr!(mov_rdi_u32, "mov rdi, $u32", u32);
+        listing_instr!(mov_rdi_qword_ptr_rax, "mov rdi, QWORD [rax]");
+        listing_instr!(mov_rdi_rbx, "mov rdi, rbx");
+        listing_instr!(mov_rdx_u32, "mov rdx, $u32", u32);
+        listing_instr!(mov_rdx_r12, "mov rdx, r12");
+        listing_instr!(mov_rdx_r13, "mov rdx, r13");
+        listing_instr!(mov_rdx_r8, "mov rdx, r8");
+        listing_instr!(mov_rdx_rdi, "mov rdx, rdi");
+        listing_instr!(mov_rdx_rsi, "mov rdx, rsi");
+        listing_instr!(mov_rsi_addr, "mov rsi, $addr", addr);
+        listing_instr!(mov_rsi_u64, "mov rsi, $u64", u64);
+        listing_instr!(mov_rsi_qword_ptr_rax_plus_i8, "mov rsi, QWORD [rax+$i8]", i8);
+        listing_instr!(mov_rsi_r14, "mov rsi, r14");
+        listing_instr!(mov_rsi_r9, "mov rsi, r9");
+        listing_instr!(mov_rsi_rbp, "mov rsi, rbp");
+        listing_instr!(mov_rsi_rsp, "mov rsi, rsp");
+        listing_instr!(mov_rsp_addr, "mov rsp, $addr", addr);
+        listing_instr!(mov_rsp_qword_ptr_r15, "mov rsp, QWORD [r15]");
+        listing_instr!(
+            mov_word_ptr_rbx_plus_r8_times_2_plus_i32_u16,
+            "mov WORD [rbx+r8*2+$i32], $u16",
+            i32,
+            u16
+        );
+        listing_instr!(
+            mov_word_ptr_rbx_plus_r8_times_2_u16,
+            "mov WORD [rbx+r8*2], $u16",
+            u16
+        );
+        listing_instr!(mov_word_ptr_rbx_plus_r8_times_2_r15w, "mov WORD [rbx+r8*2], r15w");
+        listing_instr!(movzx_eax_byte_ptr_rbx_plus_r8, "movzx eax, BYTE [rbx+r8]");
+        listing_instr!(
+            movzx_eax_byte_ptr_rbx_plus_r8_plus_i32,
+            "movzx eax, BYTE [rbx+r8+$i32]",
+            i32
+        );
+        listing_instr!(movzx_eax_byte_ptr_rbx_plus_rbp, "movzx eax, BYTE [rbx+rbp]");
+        listing_instr!(
+            movzx_eax_word_ptr_rbx_plus_r8_times_2,
+            "movzx eax, WORD [rbx+r8*2]"
+        );
+        listing_instr!(
+            movzx_eax_word_ptr_rbx_plus_r8_times_2_plus_i32,
+            "movzx eax, WORD [rbx+r8*2+$i32]",
+            i32
+        );
+        listing_instr!(
+            movzx_eax_word_ptr_rbx_plus_rbp_times_2,
+            "movzx eax, WORD [rbx+rbp*2]"
+        );
+        listing_instr!(movzx_r15d_byte_ptr_r14_plus_r10, "movzx r15d, BYTE [r14+r10]");
+        listing_instr!(pcmpeqb_xmm1_xmm0, "pcmpeqb xmm1, xmm0");
+        listing_instr!(pmovmskb_eax_xmm1, "pmovmskb eax, xmm1");
+        listing_instr!(pop_r12, "pop r12");
+        listing_instr!(pop_r13, "pop r13");
+        listing_instr!(pop_r14, "pop r14");
+        listing_instr!(pop_r15, "pop r15");
+        listing_instr!(pop_rbp, "pop rbp");
+        listing_instr!(pop_rbx, "pop rbx");
+        listing_instr!(push_r12, "push r12");
+        listing_instr!(push_r13, "push r13");
+        listing_instr!(push_r14, "push r14");
+        listing_instr!(push_r15, "push r15");
+        listing_instr!(push_rbp, "push rbp");
+        listing_instr!(push_rbx, "push rbx");
+        listing_instr!(pxor_xmm0_xmm0, "pxor xmm0, xmm0");
+        listing_instr!(rep_movsb, "rep movsb");
+        listing_instr!(ret, "ret");
+        listing_instr!(shl_rdx_u8, "shl rdx, $u8", u8);
+        listing_instr!(shl_rsi_u8, "shl rsi, $u8", u8);
+        listing_instr!(shr_rdx_u8, "shr rdx, $u8", u8);
+        listing_instr!(
+            sub_byte_ptr_rbx_plus_r8_plus_i32_al,
+            "sub BYTE [rbx+r8+$i32], al",
+            i32
+        );
+        listing_instr!(sub_byte_ptr_rbx_plus_r8_al, "sub BYTE [rbx+r8], al");
+        listing_instr!(
+            sub_dword_ptr_rbx_plus_r8_times_4_plus_i32_eax,
+            "sub DWORD [rbx+r8*4+$i32], eax",
+            i32
+        );
+        listing_instr!(sub_dword_ptr_rbx_plus_r8_times_4_eax, "sub DWORD [rbx+r8*4], eax");
+        listing_instr!(
+            sub_qword_ptr_rbx_plus_r8_times_8_plus_i32_rax,
+            "sub QWORD [rbx+r8*8+$i32], rax",
+            i32
+        );
+        listing_instr!(sub_qword_ptr_rbx_plus_r8_times_8_rax, "sub QWORD [rbx+r8*8], rax");
+        listing_instr!(sub_r15_r9, "sub r15, r9");
+        listing_instr!(sub_r8_r9, "sub r8, r9");
+        listing_instr!(sub_rbp_i8, "sub rbp, $i8", i8);
+        listing_instr!(sub_rdx_r15, "sub rdx, r15");
+        listing_instr!(sub_rdx_rsi, "sub rdx, rsi");
+        listing_instr!(
+            sub_word_ptr_rbx_plus_r8_times_2_plus_i32_ax,
+            "sub WORD [rbx+r8*2+$i32], ax",
+            i32
+        );
+        listing_instr!(sub_word_ptr_rbx_plus_r8_times_2_ax, "sub WORD [rbx+r8*2], ax");
+        listing_instr!(syscall, "syscall");
+        listing_instr!(test_rax_rax, "test rax, rax");
+        listing_instr!(xor_r10_r10, "xor r10, r10");
+        listing_instr!(xor_r11_r11, "xor r11, r11");
+        listing_instr!(xor_r12_r12, "xor r12, r12");
+        listing_instr!(xor_r13_r13, "xor r13, r13");
+        listing_instr!(xor_r15_r15, "xor r15, r15");
+        listing_instr!(xor_r8_r8, "xor r8, r8");
+        listing_instr!(xor_r9_r9, "xor r9, r9");
+        listing_instr!(xor_rax_rax, "xor rax, rax");
+        listing_instr!(xor_rbp_rbp, "xor rbp, rbp");
+        listing_instr!(xor_rdi_rdi, "xor rdi, rdi");
+        listing_instr!(xor_rdx_rdx, "xor rdx, rdx");
+    };
+}
+
+impl<'a, 's> Assembler<'a> for ListingAssembler<'s> {
+    type Address = Address;
+    type Label = Label;
+
+    fn allocate_memory(&mut self, size: u64) -> Result<Self::Address, AllocationError> {
+        let available = MAX_BSS_SIZE - (self.allocation_pointer - BSS_VIRTUAL_ADDRESS);
+
+        if size > available {
+            return Err(AllocationError { size, available });
+        }
+
+        let address = self.allocation_pointer;
+        self.allocation_pointer += size;
+        self.allocations.push((address, size, None));
+        Ok(address)
+    }
+
+    fn allocate_label(&mut self) -> Self::Label {
+        self.label_names.push(None);
+        self.label_names.len() - 1
+    }
+
+    fn label(&mut self, label: Self::Label) {
+        self.lines.push(Line::Label(label));
+    }
+
+    fn data(&mut self, bytes: &[u8]) {
+        self.lines.push(Line::Data(bytes.to_vec()));
+    }
+
+    fn source_span(&mut self, span: Span) {
+        self.lines.push(Line::Source(span));
+    }
+
+    fn name_label(&mut self, label: Self::Label, name: &str) {
+        self.label_names[label] = Some(name.to_string());
+    }
+
+    fn name_memory(&mut self, address: Self::Address, _size: u64, name: &str) {
+        if let Some(allocation) = self.allocations.iter_mut().find(|(start, _, _)| *start == address) {
+            allocation.2 = Some(name.to_string());
+        }
+    }
+
+    fn assemble<W: io::Write>(self, output: &mut W) -> Result<(), io::Error> {
+        let label_names = self.resolve_label_names();
+
+        writeln!(output, "; Generated by brainrust {}", env!("CARGO_PKG_VERSION"))?;
+        writeln!(output, "bits 64")?;
+
+        if self.allocations.iter().any(|(_, _, name)| name.is_some()) {
+            writeln!(output)?;
+            writeln!(output, "; Memory, which is zeroed at startup")?;
+
+            let named = self
+                .allocations
+                .iter()
+                .filter_map(|(address, size, name)| name.as_ref().map(|name| (name, address, size)));
+            let width = named.clone().map(|(name, _, _)| name.len()).max().unwrap_or(0);
+
+            for (name, address, size) in named {
+                writeln!(
+                    output,
+                    "{:width$} equ {:#x} ; {} bytes",
+                    name,
+                    address,
+                    size,
+                    width = width
+                )?;
+            }
+        }
+
+        writeln!(output)?;
+        writeln!(output, "_start:")?;
+
+        for line in &self.lines {
+            match line {
+                Line::Instruction(template, operands) => {
+                    let text = self.render_instruction(template, operands, &label_names);
+                    writeln!(output, "{}{}", INDENT, text)?;
+                }
+                Line::Label(label) => writeln!(output, "{}:", label_names[*label])?,
+                Line::Data(bytes) => writeln!(output, "{}db {}", INDENT, render_data(bytes))?,
+                Line::Source(span) => {
+                    let position = span.start;
+                    writeln!(
+                        output,
+                        "{}; {}:{}: {}",
+                        INDENT,
+                        position.line,
+                        position.column,
+                        self.quote(*span)
+                    )?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    listing_instructions!();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn displacements() {
+        let mut asm = ListingAssembler::new(b"");
+        let tape = asm.allocate_memory(16).unwrap();
+        asm.name_memory(tape, 16, "tape");
+        let label = asm.allocate_label();
+        asm.name_label(label, "flush");
+        let label_names = asm.resolve_label_names();
+
+        let render = |template: &str, operands: Vec<Operand>| asm.render_instruction(template, &operands, &label_names);
+        let number = |number: i32| Operand::Number(number.to_string());
+
+        assert_eq!(
+            render("add BYTE [rbx+r8+$i32], $u8", vec![number(-3), number(5)]),
+            "add BYTE [rbx+r8-3], 5"
+        );
+        assert_eq!(
+            render("add BYTE [rbx+r8+$i32], $u8", vec![number(3), number(5)]),
+            "add BYTE [rbx+r8+3], 5"
+        );
+        // Only a displacement is subtracted; other negative operands are left as they are
+        assert_eq!(render("add r8, $i8", vec![number(-1)]), "add r8, -1");
+        assert_eq!(render("lea rsi, [rbp+$i8]", vec![number(-128)]), "lea rsi, [rbp-128]");
+        assert_eq!(render("mov rax, $addr", vec![Operand::Address(tape)]), "mov rax, tape");
+        assert_eq!(
+            render("mov rax, $addr", vec![Operand::Address(tape + 8)]),
+            format!("mov rax, {:#x}", tape + 8)
+        );
+        assert_eq!(render("jmp $label", vec![Operand::Label(label)]), "jmp flush");
+    }
+
+    #[test]
+    fn label_names() {
+        let mut asm = ListingAssembler::new(b"");
+        let names = [
+            None,
+            Some("flush"),
+            Some("out_of_bounds_L1_C2"),
+            Some("out_of_bounds_L1_C2"),
+            None,
+            Some("out_of_bounds_L1_C2"),
+            Some("L0"),
+        ];
+
+        for name in &names {
+            let label = asm.allocate_label();
+
+            if let Some(name) = name {
+                asm.name_label(label, name);
+            }
+        }
+
+        assert_eq!(
+            asm.resolve_label_names(),
+            [
+                "L0",
+                "flush",
+                "out_of_bounds_L1_C2",
+                "out_of_bounds_L1_C2_2",
+                "L4",
+                "out_of_bounds_L1_C2_3",
+                "L0_2",
+            ]
+        );
+    }
+
+    #[test]
+    fn data() {
+        assert_eq!(render_data(b""), "");
+        assert_eq!(render_data(b"error: out of memory\n"), "\"error: out of memory\", 10");
+        assert_eq!(render_data(b"\x1b[0m"), "27, \"[0m\"");
+        // Lone printable characters and quotes are given in decimal
+        assert_eq!(render_data(b"a\nbc"), "97, 10, \"bc\"");
+        assert_eq!(render_data(b"say \"hi\""), "\"say \", 34, \"hi\", 34");
+    }
+}
@@ -0,0 +1,567 @@
+// Machine code under construction, with branches to labels which may not have been placed yet. This is shared by
+// the assemblers, which differ only in where memory is allocated and what becomes of the finished code
+pub struct MachineCode {
+    bytes: Vec<u8>,
+    label_states: Vec<LabelState>,
+}
+
+enum LabelState {
+    Unpopulated(Vec<usize>),
+    Populated(usize),
+}
+
+impl MachineCode {
+    pub fn new() -> Self {
+        Self {
+            bytes: vec![],
+            label_states: vec![],
+        }
+    }
+
+    pub fn bytes(&self) -> &[u8] {
+        &self.bytes
+    }
+
+    pub fn extend(&mut self, bytes: &[u8]) {
+        self.bytes.extend(bytes);
+    }
+
+    // The offset at which a label was placed, if it has been
+    pub fn label_offset(&self, label: usize) -> Option<usize> {
+        match self.label_states[label] {
+            LabelState::Unpopulated(_) => None,
+            LabelState::Populated(offset) => Some(offset),
+        }
+    }
+
+    pub fn allocate_label(&mut self) -> usize {
+        let index = self.label_states.len();
+        self.label_states.push(LabelState::Unpopulated(vec![]));
+        index
+    }
+
+    pub fn label(&mut self, label: usize) {
+        let state = &mut self.label_states[label];
+        let destination = self.bytes.len();
+
+        let patch_offsets = match state {
+            LabelState::Unpopulated(ref offsets) => offsets,
+            LabelState::Populated(_) => panic!("label was defined multiple times"),
+        };
+
+        for patch_offset in patch_offsets {
+            let origin = *patch_offset + 4;
+            assert!(origin <= destination);
+
+            let patch_slice = &mut self.bytes[*patch_offset..*patch_offset + 4];
+            assert!(patch_slice == [0x00, 0x00, 0x00, 0x00]);
+
+            let relative_offset = {
+                let difference = destination - origin;
+                assert!(difference <= (i32::MAX as usize)); // FIXME?
+                difference as i32
+            };
+
+            patch_slice.copy_from_slice(&relative_offset.to_le_bytes());
+        }
+
+        self.label_states[label] = LabelState::Populated(destination);
+    }
+
+    // Emit an instruction ending in the 32-bit offset of the given label, relative to the end of the instruction
+    pub fn generate_branch(&mut self, label: usize, code: &[u8]) {
+        let state = &mut self.label_states[label];
+
+        self.bytes.extend(code);
+
+        match state {
+            LabelState::Unpopulated(ref mut patch_offsets) => {
+                patch_offsets.push(self.bytes.len());
+                self.bytes.extend(&[0x00, 0x00, 0x00, 0x00]);
+            }
+            LabelState::Populated(destination) => {
+                let origin = self.bytes.len() + 4;
+
+                assert!(*destination < origin);
+
+                let relative_offset = {
+                    let difference = origin - *destination;
+                    assert!(difference <= (i32::MAX as usize)); // FIXME?
+                    -(difference as i32)
+                };
+
+                self.bytes.extend(&relative_offset.to_le_bytes());
+            }
+        }
+    }
+}
+
+impl Default for MachineCode {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/*
+    The macros below implement the instruction methods of the Assembler trait for any
+    assembler whose code is in a MachineCode field named machine_code. The body of
+    `instructions!` is generated from instructions.list by script/generate-elf-methods.
+*/
+
+macro_rules! instr {
+    ($name:ident, $code:expr) => {
+        fn $name(&mut self) {
+            self.machine_code.extend(&$code);
+        }
+    };
+
+    ($name:ident, $operand_type:ty, $code:expr) => {
+        fn $name(&mut self, operand: $operand_type) {
+            self.machine_code.extend(&$code);
+            self.machine_code.extend(&operand.to_le_bytes());
+        }
+    };
+
+    ($name:ident, $displacement_type:ty, $operand_type:ty, $code:expr) => {
+        fn $name(&mut self, displacement: $displacement_type, operand: $operand_type) {
+            self.machine_code.extend(&$code);
+            self.machine_code.extend(&displacement.to_le_bytes());
+            self.machine_code.extend(&operand.to_le_bytes());
+        }
+    };
+}
+
+macro_rules! instr_branch {
+    ($name:ident, $code:expr) => {
+        fn $name(&mut self, label: Self::Label) {
+            self.machine_code.generate_branch(label, &$code);
+        }
+    };
+}
+
+macro_rules! instructions {
+    () => {
+        instr!(
+            add_byte_ptr_rbx_plus_r8_plus_i32_u8,
+            i32,
+            u8,
+            [0x42, 0x80, 0x84, 0x03]
+        );
+        instr!(
+            add_byte_ptr_rbx_plus_r8_plus_i32_al,
+            i32,
+            [0x42, 0x00, 0x84, 0x03]
+        );
+        instr!(
+            add_byte_ptr_rbx_plus_r8_plus_i32_cl,
+            i32,
+            [0x42, 0x00, 0x8c, 0x03]
+        );
+        instr!(add_byte_ptr_rbx_plus_r8_u8, u8, [0x42, 0x80, 0x04, 0x03]);
+        instr!(add_byte_ptr_rbx_plus_r8_al, [0x42, 0x00, 0x04, 0x03]);
+        instr!(add_byte_ptr_rbx_plus_r8_cl, [0x42, 0x00, 0x0c, 0x03]);
+        instr!(add_dl_u8, u8, [0x80, 0xc2]);
+        instr!(
+            add_dword_ptr_rbx_plus_r8_times_4_plus_i32_u32,
+            i32,
+            u32,
+            [0x42, 0x81, 0x84, 0x83]
+        );
+        instr!(
+            add_dword_ptr_rbx_plus_r8_times_4_plus_i32_eax,
+            i32,
+            [0x42, 0x01, 0x84, 0x83]
+        );
+        instr!(
+            add_dword_ptr_rbx_plus_r8_times_4_plus_i32_ecx,
+            i32,
+            [0x42, 0x01, 0x8c, 0x83]
+        );
+        instr!(
+            add_dword_ptr_rbx_plus_r8_times_4_u32,
+            u32,
+            [0x42, 0x81, 0x04, 0x83]
+        );
+        instr!(add_dword_ptr_rbx_plus_r8_times_4_eax, [0x42, 0x01, 0x04, 0x83]);
+        instr!(add_dword_ptr_rbx_plus_r8_times_4_ecx, [0x42, 0x01, 0x0c, 0x83]);
+        instr!(
+            add_qword_ptr_rbx_plus_r8_times_8_i32,
+            i32,
+            [0x4a, 0x81, 0x04, 0xc3]
+        );
+        instr!(
+            add_qword_ptr_rbx_plus_r8_times_8_plus_i32_i32,
+            i32,
+            i32,
+            [0x4a, 0x81, 0x84, 0xc3]
+        );
+        instr!(
+            add_qword_ptr_rbx_plus_r8_times_8_plus_i32_r15,
+            i32,
+            [0x4e, 0x01, 0xbc, 0xc3]
+        );
+        instr!(
+            add_qword_ptr_rbx_plus_r8_times_8_plus_i32_rax,
+            i32,
+            [0x4a, 0x01, 0x84, 0xc3]
+        );
+        instr!(
+            add_qword_ptr_rbx_plus_r8_times_8_plus_i32_rcx,
+            i32,
+            [0x4a, 0x01, 0x8c, 0xc3]
+        );
+        instr!(add_qword_ptr_rbx_plus_r8_times_8_r15, [0x4e, 0x01, 0x3c, 0xc3]);
+        instr!(add_qword_ptr_rbx_plus_r8_times_8_rax, [0x4a, 0x01, 0x04, 0xc3]);
+        instr!(add_qword_ptr_rbx_plus_r8_times_8_rcx, [0x4a, 0x01, 0x0c, 0xc3]);
+        instr!(add_r11_i8, i8, [0x49, 0x83, 0xc3]);
+        instr!(add_r15_i32, i32, [0x49, 0x81, 0xc7]);
+        instr!(add_r15_i8, i8, [0x49, 0x83, 0xc7]);
+        instr!(add_r15_rax, [0x49, 0x01, 0xc7]);
+        instr!(add_r8_i32, i32, [0x49, 0x81, 0xc0]);
+        instr!(add_r8_i8, i8, [0x49, 0x83, 0xc0]);
+        instr!(add_r8_r15, [0x4d, 0x01, 0xf8]);
+        instr!(add_r8_r9, [0x4d, 0x01, 0xc8]);
+        instr!(add_r8_rax, [0x49, 0x01, 0xc0]);
+        instr!(add_rdx_r9, [0x4c, 0x01, 0xca]);
+        instr!(add_rsi_i8, i8, [0x48, 0x83, 0xc6]);
+        instr!(add_rsi_r15, [0x4c, 0x01, 0xfe]);
+        instr!(
+            add_word_ptr_rbx_plus_r8_times_2_plus_i32_u16,
+            i32,
+            u16,
+            [0x66, 0x42, 0x81, 0x84, 0x43]
+        );
+        instr!(
+            add_word_ptr_rbx_plus_r8_times_2_plus_i32_ax,
+            i32,
+            [0x66, 0x42, 0x01, 0x84, 0x43]
+        );
+        instr!(
+            add_word_ptr_rbx_plus_r8_times_2_plus_i32_cx,
+            i32,
+            [0x66, 0x42, 0x01, 0x8c, 0x43]
+        );
+        instr!(
+            add_word_ptr_rbx_plus_r8_times_2_u16,
+            u16,
+            [0x66, 0x42, 0x81, 0x04, 0x43]
+        );
+        instr!(
+            add_word_ptr_rbx_plus_r8_times_2_ax,
+            [0x66, 0x42, 0x01, 0x04, 0x43]
+        );
+        instr!(
+            add_word_ptr_rbx_plus_r8_times_2_cx,
+            [0x66, 0x42, 0x01, 0x0c, 0x43]
+        );
+        instr!(and_eax_u32, u32, [0x25]);
+        instr!(bsf_eax_eax, [0x0f, 0xbc, 0xc0]);
+        instr!(bsr_eax_eax, [0x0f, 0xbd, 0xc0]);
+        instr!(cmovae_r8_r15, [0x4d, 0x0f, 0x43, 0xc7]);
+        instr!(cmp_byte_ptr_rbx_plus_r8_u8, u8, [0x42, 0x80, 0x3c, 0x03]);
+        instr!(
+            cmp_dword_ptr_rbx_plus_r8_times_4_u32,
+            u32,
+            [0x42, 0x81, 0x3c, 0x83]
+        );
+        instr!(
+            cmp_qword_ptr_rbx_plus_r8_times_8_i32,
+            i32,
+            [0x4a, 0x81, 0x3c, 0xc3]
+        );
+        instr!(cmp_r10_r11, [0x4d, 0x39, 0xda]);
+        instr!(cmp_r10_r12, [0x4d, 0x39, 0xe2]);
+        instr!(cmp_r11_r9, [0x4d, 0x39, 0xcb]);
+        instr!(cmp_r13_u32, u32, [0x49, 0x81, 0xfd]);
+        instr!(cmp_r13_rbp, [0x49, 0x39, 0xed]);
+        instr!(cmp_r15b_u8, u8, [0x41, 0x80, 0xff]);
+        instr!(cmp_r15_r13, [0x4d, 0x39, 0xef]);
+        instr!(cmp_r15_r9, [0x4d, 0x39, 0xcf]);
+        instr!(cmp_r8_i32, i32, [0x49, 0x81, 0xf8]);
+        instr!(cmp_r8_i8, i8, [0x49, 0x83, 0xf8]);
+        instr!(cmp_r8_r9, [0x4d, 0x39, 0xc8]);
+        instr!(cmp_rax_u32, u32, [0x48, 0x3d]);
+        instr!(cmp_rbp_r11, [0x4c, 0x39, 0xdd]);
+        instr!(cmp_rbp_r8, [0x4c, 0x39, 0xc5]);
+        instr!(
+            cmp_word_ptr_rbx_plus_r8_times_2_u16,
+            u16,
+            [0x66, 0x42, 0x81, 0x3c, 0x43]
+        );
+        instr!(dec_byte_ptr_rbx_plus_r8, [0x42, 0xfe, 0x0c, 0x03]);
+        instr!(dec_byte_ptr_rbx_plus_r8_plus_i32, i32, [0x42, 0xfe, 0x8c, 0x03]);
+        instr!(dec_dword_ptr_rbx_plus_r8_times_4, [0x42, 0xff, 0x0c, 0x83]);
+        instr!(
+            dec_dword_ptr_rbx_plus_r8_times_4_plus_i32,
+            i32,
+            [0x42, 0xff, 0x8c, 0x83]
+        );
+        instr!(dec_qword_ptr_rbx_plus_r8_times_8, [0x4a, 0xff, 0x0c, 0xc3]);
+        instr!(
+            dec_qword_ptr_rbx_plus_r8_times_8_plus_i32,
+            i32,
+            [0x4a, 0xff, 0x8c, 0xc3]
+        );
+        instr!(dec_r11, [0x49, 0xff, 0xcb]);
+        instr!(dec_rsi, [0x48, 0xff, 0xce]);
+        instr!(dec_word_ptr_rbx_plus_r8_times_2, [0x66, 0x42, 0xff, 0x0c, 0x43]);
+        instr!(
+            dec_word_ptr_rbx_plus_r8_times_2_plus_i32,
+            i32,
+            [0x66, 0x42, 0xff, 0x8c, 0x43]
+        );
+        instr!(div_rcx, [0x48, 0xf7, 0xf1]);
+        instr!(imul_ecx_eax_i32, i32, [0x69, 0xc8]);
+        instr!(imul_rcx_rax, [0x48, 0x0f, 0xaf, 0xc8]);
+        instr!(inc_byte_ptr_rbx_plus_r8, [0x42, 0xfe, 0x04, 0x03]);
+        instr!(inc_byte_ptr_rbx_plus_r8_plus_i32, i32, [0x42, 0xfe, 0x84, 0x03]);
+        instr!(inc_dword_ptr_rbx_plus_r8_times_4, [0x42, 0xff, 0x04, 0x83]);
+        instr!(
+            inc_dword_ptr_rbx_plus_r8_times_4_plus_i32,
+            i32,
+            [0x42, 0xff, 0x84, 0x83]
+        );
+        instr!(inc_qword_ptr_rbx_plus_r8_times_8, [0x4a, 0xff, 0x04, 0xc3]);
+        instr!(
+            inc_qword_ptr_rbx_plus_r8_times_8_plus_i32,
+            i32,
+            [0x4a, 0xff, 0x84, 0xc3]
+        );
+        instr!(inc_r10, [0x49, 0xff, 0xc2]);
+        instr!(inc_r13, [0x49, 0xff, 0xc5]);
+        instr!(inc_r15, [0x49, 0xff, 0xc7]);
+        instr!(inc_rbp, [0x48, 0xff, 0xc5]);
+        instr!(inc_rdi, [0x48, 0xff, 0xc7]);
+        instr!(inc_word_ptr_rbx_plus_r8_times_2, [0x66, 0x42, 0xff, 0x04, 0x43]);
+        instr!(
+            inc_word_ptr_rbx_plus_r8_times_2_plus_i32,
+            i32,
+            [0x66, 0x42, 0xff, 0x84, 0x43]
+        );
+        instr_branch!(jae, [0x0f, 0x83]);
+        instr_branch!(jb, [0x0f, 0x82]);
+        instr_branch!(je, [0x0f, 0x84]);
+        instr_branch!(jg, [0x0f, 0x8f]);
+        instr_branch!(jge, [0x0f, 0x8d]);
+        instr_branch!(jmp, [0xe9]);
+        instr!(jmp_r15, [0x41, 0xff, 0xe7]);
+        instr_branch!(jne, [0x0f, 0x85]);
+        instr_branch!(jns, [0x0f, 0x89]);
+        instr_branch!(js, [0x0f, 0x88]);
+        instr_branch!(lea_r15_ptr_rel, [0x4c, 0x8d, 0x3d]);
+        instr_branch!(lea_rbp_ptr_rel, [0x48, 0x8d, 0x2d]);
+        instr!(lea_rsi_ptr_rbp_plus_i8, i8, [0x48, 0x8d, 0x75]);
+        instr!(
+            mov_byte_ptr_rbx_plus_r8_plus_i32_u8,
+            i32,
+            u8,
+            [0x42, 0xc6, 0x84, 0x03]
+        );
+        instr!(mov_byte_ptr_rbx_plus_r8_u8, u8, [0x42, 0xc6, 0x04, 0x03]);
+        instr!(mov_byte_ptr_rbx_plus_r8_r15b, [0x46, 0x88, 0x3c, 0x03]);
+        instr!(mov_byte_ptr_rdi_u8, u8, [0xc6, 0x07]);
+        instr!(mov_byte_ptr_rsi_dl, [0x88, 0x16]);
+        instr!(mov_byte_ptr_rsp_plus_r13_r15b, [0x46, 0x88, 0x3c, 0x2c]);
+        instr!(movdqu_xmm1_ptr_rbx_plus_r15, [0xf3, 0x42, 0x0f, 0x6f, 0x0c, 0x3b]);
+        instr!(movdqu_xmm1_ptr_rbx_plus_r8, [0xf3, 0x42, 0x0f, 0x6f, 0x0c, 0x03]);
+        instr!(
+            mov_dword_ptr_rbx_plus_r8_times_4_plus_i32_u32,
+            i32,
+            u32,
+            [0x42, 0xc7, 0x84, 0x83]
+        );
+        instr!(
+            mov_dword_ptr_rbx_plus_r8_times_4_u32,
+            u32,
+            [0x42, 0xc7, 0x04, 0x83]
+        );
+        instr!(mov_dword_ptr_rbx_plus_r8_times_4_r15d, [0x46, 0x89, 0x3c, 0x83]);
+        instr!(mov_eax_dword_ptr_rbx_plus_r8_times_4, [0x42, 0x8b, 0x04, 0x83]);
+        instr!(
+            mov_eax_dword_ptr_rbx_plus_r8_times_4_plus_i32,
+            i32,
+            [0x42, 0x8b, 0x84, 0x83]
+        );
+        instr!(mov_eax_dword_ptr_rbx_plus_rbp_times_4, [0x8b, 0x04, 0xab]);
+        instr!(mov_qword_ptr_r15_rsp, [0x49, 0x89, 0x27]);
+        instr!(mov_qword_ptr_rax_plus_i8_rdx, i8, [0x48, 0x89, 0x50]);
+        instr!(mov_qword_ptr_rax_r15, [0x4c, 0x89, 0x38]);
+        instr!(mov_qword_ptr_rax_rbx, [0x48, 0x89, 0x18]);
+        instr!(
+            mov_qword_ptr_rbx_plus_r8_times_8_i32,
+            i32,
+            [0x4a, 0xc7, 0x04, 0xc3]
+        );
+        instr!(
+            mov_qword_ptr_rbx_plus_r8_times_8_plus_i32_i32,
+            i32,
+            i32,
+            [0x4a, 0xc7, 0x84, 0xc3]
+        );
+        instr!(mov_qword_ptr_rbx_plus_r8_times_8_r15, [0x4e, 0x89, 0x3c, 0xc3]);
+        instr!(mov_r10_u32, u32, [0x41, 0xba]);
+        instr!(mov_r10_rbp, [0x49, 0x89, 0xea]);
+        instr!(mov_r11_r8, [0x4d, 0x89, 0xc3]);
+        instr!(mov_r11_r9, [0x4d, 0x89, 0xcb]);
+        instr!(mov_r11_rax, [0x49, 0x89, 0xc3]);
+        instr!(mov_r12_u64, u64, [0x49, 0xbc]);
+        instr!(mov_r12_rax, [0x49, 0x89, 0xc4]);
+        instr!(mov_r13_u32, u32, [0x41, 0xbd]);
+        instr!(mov_r14_addr, Self::Address, [0x49, 0xbe]);
+        instr!(mov_r15_addr, Self::Address, [0x49, 0xbf]);
+        instr!(mov_r15_u64, u64, [0x49, 0xbf]);
+        instr!(mov_r15b_byte_ptr_r14_plus_r10, [0x47, 0x8a, 0x3c, 0x16]);
+        instr!(mov_r15b_byte_ptr_rbx_plus_r8, [0x46, 0x8a, 0x3c, 0x03]);
+        instr!(mov_r15b_byte_ptr_rbx_plus_r8_times_2, [0x46, 0x8a, 0x3c, 0x43]);
+        instr!(mov_r15b_byte_ptr_rbx_plus_r8_times_4, [0x46, 0x8a, 0x3c, 0x83]);
+        instr!(mov_r15b_byte_ptr_rbx_plus_r8_times_8, [0x46, 0x8a, 0x3c, 0xc3]);
+        instr!(mov_r15_qword_ptr_rax, [0x4c, 0x8b, 0x38]);
+        instr!(mov_r15_r8, [0x4d, 0x89, 0xc7]);
+        instr!(mov_r8_u64, u64, [0x49, 0xb8]);
+        instr!(mov_r8_r15, [0x4d, 0x89, 0xf8]);
+        instr!(mov_r9_u64, u64, [0x49, 0xb9]);
+        instr!(mov_r9_rax, [0x49, 0x89, 0xc1]);
+        instr!(mov_r9_rdx, [0x49, 0x89, 0xd1]);
+        instr!(mov_rax_addr, Self::Address, [0x48, 0xb8]);
+        instr!(mov_rax_u32, u32, [0xb8]);
+        instr!(mov_rax_qword_ptr_rbx_plus_r8_times_8, [0x4a, 0x8b, 0x04, 0xc3]);
+        instr!(
+            mov_rax_qword_ptr_rbx_plus_r8_times_8_plus_i32,
+            i32,
+            [0x4a, 0x8b, 0x84, 0xc3]
+        );
+        instr!(mov_rax_qword_ptr_rbx_plus_rbp_times_8, [0x48, 0x8b, 0x04, 0xeb]);
+        instr!(mov_rax_r11, [0x4c, 0x89, 0xd8]);
+        instr!(mov_rax_r12, [0x4c, 0x89, 0xe0]);
+        instr!(mov_rax_r8, [0x4c, 0x89, 0xc0]);
+        instr!(mov_rax_r9, [0x4c, 0x89, 0xc8]);
+        instr!(mov_rax_rbp, [0x48, 0x89, 0xe8]);
+        instr!(mov_rax_rdi, [0x48, 0x89, 0xf8]);
+        instr!(mov_rbp_u64, u64, [0x48, 0xbd]);
+        instr!(mov_rbp_r10, [0x4c, 0x89, 0xd5]);
+        instr!(mov_rbp_r8, [0x4c, 0x89, 0xc5]);
+        instr!(mov_rbx_addr, Self::Address, [0x48, 0xbb]);
+        instr!(mov_rbx_rax, [0x48, 0x89, 0xc3]);
+        instr!(mov_rcx_u32, u32, [0xb9]);
+        instr!(mov_rcx_u64, u64, [0x48, 0xb9]);
+        instr!(mov_rcx_qword_ptr_rbp, [0x48, 0x8b, 0x4d, 0x00]);
+        instr!(mov_rcx_r15, [0x4c, 0x89, 0xf9]);
+        instr!(mov_rdi_addr, Self::Address, [0x48, 0xbf]);
+        instr!(mov_rdi_u32, u32, [0xbf]);
+        instr!(mov_rdi_qword_ptr_rax, [0x48, 0x8b, 0x38]);
+        instr!(mov_rdi_rbx, [0x48, 0x89, 0xdf]);
+        instr!(mov_rdx_u32, u32, [0xba]);
+        instr!(mov_rdx_r12, [0x4c, 0x89, 0xe2]);
+        instr!(mov_rdx_r13, [0x4c, 0x89, 0xea]);
+        instr!(mov_rdx_r8, [0x4c, 0x89, 0xc2]);
+        instr!(mov_rdx_rdi, [0x48, 0x89, 0xfa]);
+        instr!(mov_rdx_rsi, [0x48, 0x89, 0xf2]);
+        instr!(mov_rsi_addr, Self::Address, [0x48, 0xbe]);
+        instr!(mov_rsi_u64, u64, [0x48, 0xbe]);
+        instr!(mov_rsi_qword_ptr_rax_plus_i8, i8, [0x48, 0x8b, 0x70]);
+        instr!(mov_rsi_r14, [0x4c, 0x89, 0xf6]);
+        instr!(mov_rsi_r9, [0x4c, 0x89, 0xce]);
+        instr!(mov_rsi_rbp, [0x48, 0x89, 0xee]);
+        instr!(mov_rsi_rsp, [0x48, 0x89, 0xe6]);
+        instr!(mov_rsp_addr, Self::Address, [0x48, 0xbc]);
+        instr!(mov_rsp_qword_ptr_r15, [0x49, 0x8b, 0x27]);
+        instr!(
+            mov_word_ptr_rbx_plus_r8_times_2_plus_i32_u16,
+            i32,
+            u16,
+            [0x66, 0x42, 0xc7, 0x84, 0x43]
+        );
+        instr!(
+            mov_word_ptr_rbx_plus_r8_times_2_u16,
+            u16,
+            [0x66, 0x42, 0xc7, 0x04, 0x43]
+        );
+        instr!(
+            mov_word_ptr_rbx_plus_r8_times_2_r15w,
+            [0x66, 0x46, 0x89, 0x3c, 0x43]
+        );
+        instr!(movzx_eax_byte_ptr_rbx_plus_r8, [0x42, 0x0f, 0xb6, 0x04, 0x03]);
+        instr!(
+            movzx_eax_byte_ptr_rbx_plus_r8_plus_i32,
+            i32,
+            [0x42, 0x0f, 0xb6, 0x84, 0x03]
+        );
+        instr!(movzx_eax_byte_ptr_rbx_plus_rbp, [0x0f, 0xb6, 0x04, 0x2b]);
+        instr!(
+            movzx_eax_word_ptr_rbx_plus_r8_times_2,
+            [0x42, 0x0f, 0xb7, 0x04, 0x43]
+        );
+        instr!(
+            movzx_eax_word_ptr_rbx_plus_r8_times_2_plus_i32,
+            i32,
+            [0x42, 0x0f, 0xb7, 0x84, 0x43]
+        );
+        instr!(movzx_eax_word_ptr_rbx_plus_rbp_times_2, [0x0f, 0xb7, 0x04, 0x6b]);
+        instr!(movzx_r15d_byte_ptr_r14_plus_r10, [0x47, 0x0f, 0xb6, 0x3c, 0x16]);
+        instr!(pcmpeqb_xmm1_xmm0, [0x66, 0x0f, 0x74, 0xc8]);
+        instr!(pmovmskb_eax_xmm1, [0x66, 0x0f, 0xd7, 0xc1]);
+        instr!(pop_r12, [0x41, 0x5c]);
+        instr!(pop_r13, [0x41, 0x5d]);
+        instr!(pop_r14, [0x41, 0x5e]);
+        instr!(pop_r15, [0x41, 0x5f]);
+        instr!(pop_rbp, [0x5d]);
+        instr!(pop_rbx, [0x5b]);
+        instr!(push_r12, [0x41, 0x54]);
+        instr!(push_r13, [0x41, 0x55]);
+        instr!(push_r14, [0x41, 0x56]);
+        instr!(push_r15, [0x41, 0x57]);
+        instr!(push_rbp, [0x55]);
+        instr!(push_rbx, [0x53]);
+        instr!(pxor_xmm0_xmm0, [0x66, 0x0f, 0xef, 0xc0]);
+        instr!(rep_movsb, [0xf3, 0xa4]);
+        instr!(ret, [0xc3]);
+        instr!(shl_rdx_u8, u8, [0x48, 0xc1, 0xe2]);
+        instr!(shl_rsi_u8, u8, [0x48, 0xc1, 0xe6]);
+        instr!(shr_rdx_u8, u8, [0x48, 0xc1, 0xea]);
+        instr!(
+            sub_byte_ptr_rbx_plus_r8_plus_i32_al,
+            i32,
+            [0x42, 0x28, 0x84, 0x03]
+        );
+        instr!(sub_byte_ptr_rbx_plus_r8_al, [0x42, 0x28, 0x04, 0x03]);
+        instr!(
+            sub_dword_ptr_rbx_plus_r8_times_4_plus_i32_eax,
+            i32,
+            [0x42, 0x29, 0x84, 0x83]
+        );
+        instr!(sub_dword_ptr_rbx_plus_r8_times_4_eax, [0x42, 0x29, 0x04, 0x83]);
+        instr!(
+            sub_qword_ptr_rbx_plus_r8_times_8_plus_i32_rax,
+            i32,
+            [0x4a, 0x29, 0x84, 0xc3]
+        );
+        instr!(sub_qword_ptr_rbx_plus_r8_times_8_rax, [0x4a, 0x29, 0x04, 0xc3]);
+        instr!(sub_r15_r9, [0x4d, 0x29, 0xcf]);
+        instr!(sub_r8_r9, [0x4d, 0x29, 0xc8]);
+        instr!(sub_rbp_i8, i8, [0x48, 0x83, 0xed]);
+        instr!(sub_rdx_r15, [0x4c, 0x29, 0xfa]);
+        instr!(sub_rdx_rsi, [0x48, 0x29, 0xf2]);
+        instr!(
+            sub_word_ptr_rbx_plus_r8_times_2_plus_i32_ax,
+            i32,
+            [0x66, 0x42, 0x29, 0x84, 0x43]
+        );
+        instr!(
+            sub_word_ptr_rbx_plus_r8_times_2_ax,
+            [0x66, 0x42, 0x29, 0x04, 0x43]
+        );
+        instr!(syscall, [0x0f, 0x05]);
+        instr!(test_rax_rax, [0x48, 0x85, 0xc0]);
+        instr!(xor_r10_r10, [0x4d, 0x31, 0xd2]);
+        instr!(xor_r11_r11, [0x4d, 0x31, 0xdb]);
+        instr!(xor_r12_r12, [0x4d, 0x31, 0xe4]);
+        instr!(xor_r13_r13, [0x4d, 0x31, 0xed]);
+        instr!(xor_r15_r15, [0x4d, 0x31, 0xff]);
+        instr!(xor_r8_r8, [0x4d, 0x31, 0xc0]);
+        instr!(xor_r9_r9, [0x4d, 0x31, 0xc9]);
+        instr!(xor_rax_rax, [0x48, 0x31, 0xc0]);
+        instr!(xor_rbp_rbp, [0x48, 0x31, 0xed]);
+        instr!(xor_rdi_rdi, [0x48, 0x31, 0xff]);
+        instr!(xor_rdx_rdx, [0x48, 0x31, 0xd2]);
+    };
+}
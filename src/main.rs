@@ -1,19 +1,900 @@
-mod assembler;
-mod compiler;
-mod elf;
-mod elf_assembler;
-mod parser;
-mod stream;
-
-use std::fs::File;
+use std::env;
+use std::ffi::{OsStr, OsString};
+use std::fmt;
+use std::fmt::{Display, Formatter};
+use std::fs;
+use std::fs::{File, OpenOptions};
 use std::io;
+use std::io::{IsTerminal, Read, Write};
+use std::os::fd::AsFd;
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Path, PathBuf};
+use std::process;
+use std::str::FromStr;
 
-use crate::compiler::compile;
-use crate::stream::Stream;
+use brainrust::compiler::{EXIT_OUT_OF_BOUNDS, EXIT_OUT_OF_MEMORY, EXIT_READ_ERROR, EXIT_WRITE_ERROR};
+use brainrust::debugger;
+use brainrust::debugger::Debugger;
+use brainrust::diagnostic::{Renderer, Style};
+use brainrust::ir::Node;
+use brainrust::options::{DEFAULT_OPTIMIZATION_LEVEL, MAX_BUFFER_SIZE, MAX_OPTIMIZATION_LEVEL};
+use brainrust::repl::Repl;
+use brainrust::{
+    compile, AllocationError, Boundary, CellWidth, CompileError, Eof, Options, OptionsError, ParseError, Passes,
+    RunError,
+};
+
+const USAGE: &str = "\
+Usage: brainrust [OPTIONS] [FILE]...
+       brainrust run [OPTIONS] FILE
+       brainrust jit [OPTIONS] FILE
+       brainrust debug [OPTIONS] FILE
+       brainrust repl [OPTIONS]
+
+Compile brainfuck programs into x86-64 Linux executables, or run one directly.
+
+Options:
+  -o, --output <PATH>  Write the executable to PATH ('-' for standard output)
+  -g                   Include debugging information, so that the program can
+                       be stepped through a line of brainfuck at a time in a
+                       debugger such as gdb
+  -O<LEVEL>            Optimization level, from 0 (none) to 3 [default: 3]
+  -f<PASS>, -fno-<PASS>
+                       Enable or disable a single optimization pass,
+                       overriding the optimization level
+      --tape-length <N>
+                       Number of cells on the tape [default: 30000]
+      --boundary <MODE>
+                       What moving off the end of the tape does: wrap to the
+                       other end, abort, or grow the tape [default: wrap]
+      --cell-width <BITS>
+                       Size of each cell: 8, 16, 32 or 64 [default: 8]
+      --eof <MODE>     What ',' does at the end of input: leave the cell
+                       unchanged, or set it to zero or minus-one
+                       [default: unchanged]
+      --input-buffer <BYTES>
+                       Size of the input buffer [default: 65536]
+      --output-buffer <BYTES>
+                       Size of the output buffer [default: 65536]
+      --unbuffered     Read and write a byte at a time, as with buffer sizes
+                       of 1
+      --no-newline-flush
+                       Don't flush buffered output after each newline
+      --debug-command  Treat '#' as a command which writes the tape pointer
+                       and the cells around it to standard error
+      --emit <KIND>    Also write KIND alongside each executable; the only KIND
+                       is asm, an assembly listing in NASM syntax, written to
+                       the executable's path with the extension .s
+      --tab-width <N>  Columns between tab stops in diagnostics [default: 8]
+      --color <WHEN>   Colorize diagnostics: auto, always or never [default: auto]
+  -h, --help           Print this message and exit
+  -V, --version        Print version information and exit
+
+Optimization passes:
+  clear-loops          Compile [-] to a single store (-O1)
+  multiply-loops       Compile [->+<] and similar to multiplications (-O1)
+  scan-loops           Compile [>] and similar to a search for a zero cell (-O1)
+  offset-addressing    Move the tape pointer once per run of commands (-O2)
+  vectorize-scans      Search for zero cells 16 at a time (-O3)
+
+With no FILE, or when FILE is '-', the program is read from standard input.
+A single program is written to a.out unless -o is given; when several FILEs
+are given, each is written next to its source with the extension removed.
+
+With run, the program is interpreted rather than compiled, taking its input
+from standard input. With jit, it's compiled into memory and run immediately,
+without writing an executable. Either way, any options which affect the
+program's behavior apply.
+
+With debug, the program is run in a debugger, which reads commands from
+standard input; enter help for a list. It stops at breakpoints, at each '#'
+in the program, and when watched cells change. Optimization options have no
+effect, so that each command can be stepped through.
+
+With repl, brainfuck is read from standard input a line at a time and run
+against a tape which persists between lines, showing the cells around the
+tape pointer after each one; ',' reads from the lines that follow. Enter
+:help for commands to inspect and modify the tape.
+
+Compiled programs exit with status 1 if writing output fails and 2 if reading
+input fails. With --boundary abort or grow, they exit with status 3 if the
+tape pointer moves off the tape, and with grow, 4 if the tape can't be grown.
+The position reported for a move off the tape within a loop compiled to a
+multiplication (with -O1 and above) is that of the loop's '['.
+Programs run with run or jit exit with the same statuses.
+Cells wider than 8 bits are written to output truncated to their low 8 bits.
+";
+
+// Exit codes
+const EXIT_SUCCESS: i32 = 0;
+const EXIT_FAILURE: i32 = 1;
+const EXIT_USAGE: i32 = 2;
+
+const DEFAULT_OUTPUT: &str = "a.out";
+
+// rwxr-xr-x
+const EXECUTABLE_MODE: u32 = 0o755;
+
+// rw-r--r--
+const LISTING_MODE: u32 = 0o644;
+
+const LISTING_EXTENSION: &str = "s";
 
 fn main() {
-    let stdin = io::stdin();
-    let stream = Stream::new(stdin.lock());
-    let mut output = File::create("a.out").unwrap();
-    compile(&mut output, stream).unwrap();
+    let code = match parse_arguments(env::args_os().skip(1)) {
+        Ok(Command::Help) => {
+            print!("{}", USAGE);
+            EXIT_SUCCESS
+        }
+        Ok(Command::Version) => {
+            println!("brainrust {}", env!("CARGO_PKG_VERSION"));
+            EXIT_SUCCESS
+        }
+        Ok(Command::Compile(jobs, options, color)) => run_jobs(&jobs, &options, color.style()),
+        Ok(Command::Execute(engine, source, options, color)) => {
+            execute_program(engine, &source, &options, color.style())
+        }
+        Ok(Command::Repl(options, color)) => run_repl(&options, color.style()),
+        Err(message) => {
+            eprintln!("brainrust: {}", message);
+            eprintln!("Try 'brainrust --help' for more information.");
+            EXIT_USAGE
+        }
+    };
+
+    process::exit(code);
+}
+
+enum Command {
+    Help,
+    Version,
+    Compile(Vec<Job>, Options, Color),
+    Execute(Engine, Source, Options, Color),
+    Repl(Options, Color),
+}
+
+// What to do with the inputs, chosen by a subcommand given as the first argument
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Mode {
+    Compile,
+    Execute(Engine),
+    Repl,
+}
+
+// How a program is executed directly
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Engine {
+    Interpreter,
+    Jit,
+    Debugger,
+}
+
+impl Engine {
+    fn subcommand(self) -> &'static str {
+        match self {
+            Engine::Interpreter => "run",
+            Engine::Jit => "jit",
+            Engine::Debugger => "debug",
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+enum Color {
+    Auto,
+    Always,
+    Never,
+}
+
+impl Color {
+    // Diagnostics go to standard error, so that's the stream whose terminal-ness matters
+    fn style(self) -> Style {
+        let enabled = match self {
+            Color::Auto => io::stderr().is_terminal() && env::var_os("NO_COLOR").is_none_or(|value| value.is_empty()),
+            Color::Always => true,
+            Color::Never => false,
+        };
+
+        if enabled {
+            Style::Colored
+        } else {
+            Style::Plain
+        }
+    }
+}
+
+// A single program to be compiled, and where to put the result
+struct Job {
+    input: Source,
+    output: Destination,
+    // With -g, the path of the source as recorded in the executable's debugging information
+    debug_info: Option<PathBuf>,
+    // With --emit asm, where to write the assembly listing
+    listing: Option<PathBuf>,
+}
+
+impl Job {
+    fn new(input: Source, output: Destination, debug_info: bool, emit_listing: bool) -> Result<Self, String> {
+        // A program read from standard input gets a placeholder, which a debugger won't find but can still show
+        let debug_info = debug_info.then(|| match &input {
+            Source::Stdin => PathBuf::from(input.to_string()),
+            Source::File(path) => path.clone(),
+        });
+
+        let listing = match (emit_listing, &output) {
+            (false, _) => None,
+            (true, Destination::Stdout) => {
+                return Err("option '--emit' cannot be used when writing to standard output".to_string())
+            }
+            (true, Destination::File(path)) => Some(listing_path(&input, path)?),
+        };
+
+        Ok(Self {
+            input,
+            output,
+            debug_info,
+            listing,
+        })
+    }
+}
+
+#[derive(Clone)]
+enum Source {
+    Stdin,
+    File(PathBuf),
+}
+
+enum Destination {
+    Stdout,
+    File(PathBuf),
+}
+
+impl Display for Source {
+    fn fmt(&self, formatter: &mut Formatter) -> fmt::Result {
+        match self {
+            Source::Stdin => write!(formatter, "<stdin>"),
+            Source::File(path) => write!(formatter, "{}", path.display()),
+        }
+    }
+}
+
+impl Display for Destination {
+    fn fmt(&self, formatter: &mut Formatter) -> fmt::Result {
+        match self {
+            Destination::Stdout => write!(formatter, "<stdout>"),
+            Destination::File(path) => write!(formatter, "{}", path.display()),
+        }
+    }
+}
+
+fn parse_arguments<I: Iterator<Item = OsString>>(arguments: I) -> Result<Command, String> {
+    let mut arguments = arguments.peekable();
+
+    let mode = match arguments.peek().and_then(|argument| argument.to_str()) {
+        Some("run") => Mode::Execute(Engine::Interpreter),
+        Some("jit") => Mode::Execute(Engine::Jit),
+        Some("debug") => Mode::Execute(Engine::Debugger),
+        Some("repl") => Mode::Repl,
+        _ => Mode::Compile,
+    };
+
+    if mode != Mode::Compile {
+        arguments.next();
+    }
+
+    let mut inputs = vec![];
+    let mut output = None;
+    let mut options = Options::default();
+    let mut color = Color::Auto;
+    let mut level = DEFAULT_OPTIMIZATION_LEVEL;
+    let mut toggles = vec![];
+    let mut debug_info = false;
+    let mut emit_listing = false;
+    let mut only_files = false;
+
+    while let Some(argument) = arguments.next() {
+        let text = match argument.to_str() {
+            Some(text) if !only_files => text,
+            _ => {
+                inputs.push(source(argument));
+                continue;
+            }
+        };
+
+        if text == "--" {
+            only_files = true;
+        } else if text == "-h" || text == "--help" {
+            return Ok(Command::Help);
+        } else if text == "-V" || text == "--version" {
+            return Ok(Command::Version);
+        } else if let Some(path) = option_value(text, Some("-o"), "--output", &mut arguments)? {
+            set_output(&mut output, path)?;
+        } else if text == "-g" {
+            debug_info = true;
+        } else if let Some(value) = text.strip_prefix("-O") {
+            level = value
+                .parse()
+                .ok()
+                .filter(|&level| level <= MAX_OPTIMIZATION_LEVEL)
+                .ok_or_else(|| format!("invalid optimization level '{}'", text))?;
+        } else if let Some(name) = text.strip_prefix("-f") {
+            // Toggles are applied after the optimization level, regardless of the order they were given in
+            let toggle = match name.strip_prefix("no-") {
+                Some(name) => (name, false),
+                None => (name, true),
+            };
+
+            if !Passes::NAMES.contains(&toggle.0) {
+                return Err(format!(
+                    "unknown optimization pass '{}' (expected one of: {})",
+                    toggle.0,
+                    Passes::NAMES.join(", ")
+                ));
+            }
+
+            toggles.push((toggle.0.to_string(), toggle.1));
+        } else if let Some(value) = option_value(text, None, "--tape-length", &mut arguments)? {
+            options.tape_length = parse_number(&value)
+                .filter(|&length| length > 0)
+                .ok_or_else(|| format!("invalid argument '{}' for '--tape-length'", value.to_string_lossy()))?;
+        } else if let Some(value) = option_value(text, None, "--boundary", &mut arguments)? {
+            options.boundary = match value.to_str() {
+                Some("wrap") => Boundary::Wrap,
+                Some("abort") => Boundary::Abort,
+                Some("grow") => Boundary::Grow,
+                _ => {
+                    return Err(format!(
+                        "invalid argument '{}' for '--boundary'",
+                        value.to_string_lossy()
+                    ))
+                }
+            };
+        } else if let Some(value) = option_value(text, None, "--cell-width", &mut arguments)? {
+            options.cell_width = parse_number(&value)
+                .and_then(CellWidth::from_bits)
+                .ok_or_else(|| format!("invalid argument '{}' for '--cell-width'", value.to_string_lossy()))?;
+        } else if let Some(value) = option_value(text, None, "--eof", &mut arguments)? {
+            options.eof = match value.to_str() {
+                Some("unchanged") => Eof::Unchanged,
+                Some("zero") => Eof::Zero,
+                Some("minus-one") => Eof::MinusOne,
+                _ => return Err(format!("invalid argument '{}' for '--eof'", value.to_string_lossy())),
+            };
+        } else if let Some(value) = option_value(text, None, "--input-buffer", &mut arguments)? {
+            options.input_buffer_size = parse_number(&value)
+                .filter(|&size| 0 < size && size <= MAX_BUFFER_SIZE)
+                .ok_or_else(|| format!("invalid argument '{}' for '--input-buffer'", value.to_string_lossy()))?;
+        } else if let Some(value) = option_value(text, None, "--output-buffer", &mut arguments)? {
+            options.output_buffer_size = parse_number(&value)
+                .filter(|&size| 0 < size && size <= MAX_BUFFER_SIZE)
+                .ok_or_else(|| format!("invalid argument '{}' for '--output-buffer'", value.to_string_lossy()))?;
+        } else if text == "--unbuffered" {
+            options.input_buffer_size = 1;
+            options.output_buffer_size = 1;
+        } else if text == "--no-newline-flush" {
+            options.flush_on_newline = false;
+        } else if text == "--debug-command" {
+            options.debug_command = true;
+        } else if let Some(value) = option_value(text, None, "--emit", &mut arguments)? {
+            emit_listing = match value.to_str() {
+                Some("asm") => true,
+                _ => return Err(format!("invalid argument '{}' for '--emit'", value.to_string_lossy())),
+            };
+        } else if let Some(value) = option_value(text, None, "--tab-width", &mut arguments)? {
+            options.tab_width = parse_number(&value)
+                .ok_or_else(|| format!("invalid argument '{}' for '--tab-width'", value.to_string_lossy()))?;
+        } else if let Some(value) = option_value(text, None, "--color", &mut arguments)? {
+            color = match value.to_str() {
+                Some("auto") => Color::Auto,
+                Some("always") => Color::Always,
+                Some("never") => Color::Never,
+                _ => return Err(format!("invalid argument '{}' for '--color'", value.to_string_lossy())),
+            };
+        } else if text.starts_with('-') && text != "-" {
+            return Err(format!("unrecognized option '{}'", text));
+        } else {
+            inputs.push(source(argument));
+        }
+    }
+
+    options.passes = Passes::level(level);
+
+    for (name, enabled) in toggles {
+        options.passes.set(&name, enabled);
+    }
+
+    if mode == Mode::Repl {
+        if output.is_some() {
+            return Err("option '--output' cannot be used with 'repl'".to_string());
+        }
+
+        if debug_info {
+            return Err("option '-g' cannot be used with 'repl'".to_string());
+        }
+
+        if emit_listing {
+            return Err("option '--emit' cannot be used with 'repl'".to_string());
+        }
+
+        if !inputs.is_empty() {
+            return Err("'repl' doesn't take a program file".to_string());
+        }
+
+        return Ok(Command::Repl(options, color));
+    }
+
+    if let Mode::Execute(engine) = mode {
+        let subcommand = engine.subcommand();
+
+        if output.is_some() {
+            return Err(format!("option '--output' cannot be used with '{}'", subcommand));
+        }
+
+        if debug_info {
+            return Err(format!("option '-g' cannot be used with '{}'", subcommand));
+        }
+
+        if emit_listing {
+            return Err(format!("option '--emit' cannot be used with '{}'", subcommand));
+        }
+
+        return match inputs.pop() {
+            Some(_) if !inputs.is_empty() => Err(format!("'{}' takes a single program file", subcommand)),
+            Some(source @ Source::File(_)) => Ok(Command::Execute(engine, source, options, color)),
+            // Standard input is the program's input
+            _ => Err(format!("'{}' requires a program file", subcommand)),
+        };
+    }
+
+    if inputs.is_empty() {
+        inputs.push(Source::Stdin);
+    }
+
+    if inputs.len() == 1 {
+        let output = output.unwrap_or_else(|| Destination::File(PathBuf::from(DEFAULT_OUTPUT)));
+        let input = inputs.pop().unwrap();
+        let jobs = vec![Job::new(input, output, debug_info, emit_listing)?];
+        return Ok(Command::Compile(jobs, options, color));
+    }
+
+    if output.is_some() {
+        return Err("option '--output' cannot be used with multiple input files".to_string());
+    }
+
+    let jobs = inputs
+        .into_iter()
+        .map(|input| match input {
+            Source::File(ref path) => {
+                let output = Destination::File(derived_output_path(path)?);
+                Job::new(input, output, debug_info, emit_listing)
+            }
+            Source::Stdin => Err("standard input cannot be combined with other input files".to_string()),
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+
+    Ok(Command::Compile(jobs, options, color))
+}
+
+// Recognize an option which takes a value, given as "-o VALUE", "-oVALUE", "--output VALUE" or "--output=VALUE";
+// returns None if the argument is some other option
+fn option_value<I: Iterator<Item = OsString>>(
+    text: &str,
+    short: Option<&str>,
+    long: &str,
+    arguments: &mut I,
+) -> Result<Option<OsString>, String> {
+    if text == long || Some(text) == short {
+        let value = arguments
+            .next()
+            .ok_or_else(|| format!("option '{}' requires an argument", long))?;
+        return Ok(Some(value));
+    }
+
+    let attached = text
+        .strip_prefix(long)
+        .and_then(|rest| rest.strip_prefix('='))
+        .or_else(|| short.and_then(|short| text.strip_prefix(short)));
+
+    Ok(attached.map(OsString::from))
+}
+
+fn parse_number<T: FromStr>(value: &OsStr) -> Option<T> {
+    value.to_str().and_then(|value| value.parse().ok())
+}
+
+fn source(argument: OsString) -> Source {
+    if argument == "-" {
+        Source::Stdin
+    } else {
+        Source::File(PathBuf::from(argument))
+    }
+}
+
+fn set_output(output: &mut Option<Destination>, path: OsString) -> Result<(), String> {
+    if output.is_some() {
+        return Err("option '--output' was given more than once".to_string());
+    }
+
+    *output = Some(if path == "-" {
+        Destination::Stdout
+    } else {
+        Destination::File(PathBuf::from(path))
+    });
+
+    Ok(())
+}
+
+// foo/bar.bf is compiled to foo/bar; refuse inputs without an extension, which would be overwritten
+fn derived_output_path(input: &Path) -> Result<PathBuf, String> {
+    match (input.file_stem(), input.extension()) {
+        (Some(stem), Some(_)) => Ok(input.with_file_name(stem)),
+        _ => Err(format!(
+            "cannot derive an output file name for '{}'; compile it separately with -o",
+            input.display()
+        )),
+    }
+}
+
+// foo/bar is listed in foo/bar.s; refuse to overwrite the program itself
+fn listing_path(input: &Source, output: &Path) -> Result<PathBuf, String> {
+    let path = output.with_extension(LISTING_EXTENSION);
+
+    match input {
+        Source::File(input) if *input == path => Err(format!(
+            "cannot write the listing for '{}' to '{}', which is the program itself",
+            input.display(),
+            path.display()
+        )),
+        _ => Ok(path),
+    }
+}
+
+fn run_jobs(jobs: &[Job], options: &Options, style: Style) -> i32 {
+    let mut code = EXIT_SUCCESS;
+
+    for job in jobs {
+        match run_job(job, options, style) {
+            Ok(()) => continue,
+            Err(JobError::Compile(diagnostic)) => eprint!("{}", diagnostic),
+            Err(error) => eprintln!("brainrust: {}", error),
+        }
+
+        code = EXIT_FAILURE;
+    }
+
+    code
+}
+
+// The program is read into memory up front so that diagnostics can quote it, and so that a program read from
+// standard input can be compiled into both an executable and a listing
+fn run_job<'a>(job: &'a Job, options: &Options, style: Style) -> Result<(), JobError<'a>> {
+    let input = &job.input;
+    let program = read_program(input).map_err(|error| JobError::Read(input.clone(), error))?;
+
+    let options = &Options {
+        debug_info: job.debug_info.clone(),
+        ..options.clone()
+    };
+
+    let write_error = |error| JobError::Write(&job.output, error);
+
+    match &job.output {
+        Destination::Stdout => {
+            // Buffer the whole executable so that nothing is written if compilation fails
+            let mut executable = vec![];
+            compile_source(job, &program, &mut executable, options, style)?;
+
+            let stdout = io::stdout();
+            let mut lock = stdout.lock();
+            lock.write_all(&executable)
+                .and_then(|_| lock.flush())
+                .map_err(write_error)?;
+        }
+        Destination::File(path) => {
+            // Compile into a temporary file and only move it into place once it's complete, so that a
+            // failed compilation never leaves a truncated executable behind
+            let temporary = TemporaryFile::create(path).map_err(write_error)?;
+
+            let mut writer = io::BufWriter::new(&temporary.file);
+            compile_source(job, &program, &mut writer, options, style)?;
+            writer.flush().map_err(write_error)?;
+            drop(writer);
+
+            temporary.persist(path, EXECUTABLE_MODE).map_err(write_error)?;
+        }
+    }
+
+    match &job.listing {
+        Some(path) => write_listing(job, &program, path, options, style),
+        None => Ok(()),
+    }
+}
+
+fn compile_source<'a, W: Write>(
+    job: &'a Job,
+    program: &[u8],
+    output: &mut W,
+    options: &Options,
+    style: Style,
+) -> Result<(), JobError<'a>> {
+    compile(program, output, options).map_err(|error| match error {
+        CompileError::Write(error) => JobError::Write(&job.output, error),
+        error => compile_error(job, program, error, options, style),
+    })
+}
+
+// The listing is only written once the executable has been, so the program is known to compile
+fn write_listing<'a>(
+    job: &'a Job,
+    program: &[u8],
+    path: &'a Path,
+    options: &Options,
+    style: Style,
+) -> Result<(), JobError<'a>> {
+    let write_error = |error| JobError::WriteListing(path, error);
+    let temporary = TemporaryFile::create(path).map_err(write_error)?;
+
+    let mut writer = io::BufWriter::new(&temporary.file);
+
+    brainrust::listing(program, &mut writer, options).map_err(|error| match error {
+        CompileError::Write(error) => write_error(error),
+        error => compile_error(job, program, error, options, style),
+    })?;
+
+    writer.flush().map_err(write_error)?;
+    drop(writer);
+
+    temporary.persist(path, LISTING_MODE).map_err(write_error)
+}
+
+// Report a failure to compile the program, other than a failure to write the result
+fn compile_error<'a>(
+    job: &'a Job,
+    program: &[u8],
+    error: CompileError,
+    options: &Options,
+    style: Style,
+) -> JobError<'a> {
+    let input = &job.input;
+
+    match error {
+        CompileError::Parse(error) => {
+            let file_name = input.to_string();
+            let renderer = Renderer::new(&file_name, program, style).with_tab_width(options.tab_width);
+            JobError::Compile(renderer.render(&error))
+        }
+        CompileError::Allocation(error) => JobError::Allocation(input.clone(), error),
+        CompileError::Options(error) => JobError::Options(error),
+        CompileError::Write(_) => unreachable!("write errors are reported by the caller"),
+        CompileError::Map(_) => unreachable!("executables aren't mapped into memory"),
+    }
+}
+
+fn read_program(source: &Source) -> io::Result<Vec<u8>> {
+    match source {
+        Source::Stdin => {
+            let mut program = vec![];
+            io::stdin().read_to_end(&mut program)?;
+            Ok(program)
+        }
+        Source::File(path) => fs::read(path),
+    }
+}
+
+// Run a program without producing an executable, returning the status its executable would exit with
+fn execute_program(engine: Engine, source: &Source, options: &Options, style: Style) -> i32 {
+    let program = match read_program(source) {
+        Ok(program) => program,
+        Err(error) => {
+            eprintln!("brainrust: {}", JobError::Read(source.clone(), error));
+            return EXIT_FAILURE;
+        }
+    };
+
+    let render = |error: &ParseError| {
+        let file_name = source.to_string();
+        let renderer = Renderer::new(&file_name, &program, style).with_tab_width(options.tab_width);
+        eprint!("{}", renderer.render(error));
+    };
+
+    match engine {
+        Engine::Interpreter => interpret_program(&program, options, render),
+        Engine::Debugger => match debugger::parse(&program[..], options) {
+            Ok(nodes) => {
+                let file_name = source.to_string();
+                let renderer = Renderer::new(&file_name, &program, style).with_tab_width(options.tab_width);
+                debug_program(&nodes, renderer, options)
+            }
+            Err(error) => {
+                render(&error);
+                EXIT_FAILURE
+            }
+        },
+        Engine::Jit => match brainrust::jit(&program[..], options) {
+            Ok(code) => code,
+            Err(CompileError::Parse(ParseError::Io(error))) => {
+                eprintln!("brainrust: {}", JobError::Read(source.clone(), error));
+                EXIT_FAILURE
+            }
+            Err(CompileError::Parse(error)) => {
+                render(&error);
+                EXIT_FAILURE
+            }
+            Err(CompileError::Allocation(error)) => {
+                eprintln!("brainrust: {}", JobError::Allocation(source.clone(), error));
+                EXIT_FAILURE
+            }
+            Err(error @ CompileError::Options(_))
+            | Err(error @ CompileError::Write(_))
+            | Err(error @ CompileError::Map(_)) => {
+                eprintln!("brainrust: {}", error);
+                EXIT_FAILURE
+            }
+        },
+    }
+}
+
+fn interpret_program<F: Fn(&ParseError)>(program: &[u8], options: &Options, render: F) -> i32 {
+    let (input, output) = match standard_streams(options) {
+        Ok(streams) => streams,
+        Err(error) => {
+            eprintln!("brainrust: cannot open standard streams: {}", error);
+            return EXIT_FAILURE;
+        }
+    };
+
+    let error = match brainrust::run(program, input, output, options) {
+        Ok(()) => return EXIT_SUCCESS,
+        Err(RunError::Parse(error)) => {
+            render(&error);
+            return EXIT_FAILURE;
+        }
+        Err(RunError::Options(error)) => {
+            eprintln!("brainrust: {}", error);
+            return EXIT_FAILURE;
+        }
+        Err(error) => error,
+    };
+
+    // Report the error as the executable would
+    eprintln!("error: {}", error);
+
+    match error {
+        RunError::Parse(_) | RunError::Options(_) => EXIT_FAILURE,
+        RunError::Write(_) => EXIT_WRITE_ERROR as i32,
+        RunError::Read(_) => EXIT_READ_ERROR as i32,
+        RunError::OutOfBounds(..) => EXIT_OUT_OF_BOUNDS as i32,
+        RunError::OutOfMemory => EXIT_OUT_OF_MEMORY as i32,
+    }
+}
+
+fn debug_program(program: &[Node], renderer: Renderer, options: &Options) -> i32 {
+    let (input, output) = match standard_streams(options) {
+        Ok(streams) => streams,
+        Err(error) => {
+            eprintln!("brainrust: cannot open standard streams: {}", error);
+            return EXIT_FAILURE;
+        }
+    };
+
+    match Debugger::new(program, renderer, options, input, output).and_then(|mut debugger| debugger.run()) {
+        Ok(()) => EXIT_SUCCESS,
+        Err(error) => {
+            eprintln!("brainrust: {}", error);
+            EXIT_FAILURE
+        }
+    }
+}
+
+fn run_repl(options: &Options, style: Style) -> i32 {
+    let (input, output) = match standard_streams(options) {
+        Ok(streams) => streams,
+        Err(error) => {
+            eprintln!("brainrust: cannot open standard streams: {}", error);
+            return EXIT_FAILURE;
+        }
+    };
+
+    match Repl::new(options, input, output, style).and_then(|mut repl| repl.run()) {
+        Ok(()) => EXIT_SUCCESS,
+        Err(error) => {
+            eprintln!("brainrust: {}", error);
+            EXIT_FAILURE
+        }
+    }
+}
+
+// Buffered standard input and output, with the buffer sizes given in the options. The streams are accessed through
+// duplicates of their file descriptors, bypassing the buffering in io::Stdin and io::Stdout, so that with buffers of
+// a single byte the program never reads more input than it uses, or holds back output
+fn standard_streams(options: &Options) -> io::Result<(io::BufReader<File>, io::BufWriter<File>)> {
+    let input = File::from(io::stdin().as_fd().try_clone_to_owned()?);
+    let output = File::from(io::stdout().as_fd().try_clone_to_owned()?);
+
+    Ok((
+        io::BufReader::with_capacity(options.input_buffer_size as usize, input),
+        io::BufWriter::with_capacity(options.output_buffer_size as usize, output),
+    ))
+}
+
+// A file which is deleted when dropped, unless it has been renamed into place by persist
+struct TemporaryFile {
+    path: PathBuf,
+    file: File,
+    persisted: bool,
+}
+
+impl TemporaryFile {
+    // Create a uniquely-named file alongside the destination, so that the final rename stays within one
+    // filesystem (and is therefore atomic)
+    fn create(destination: &Path) -> io::Result<Self> {
+        let directory = destination.parent().unwrap_or_else(|| Path::new(""));
+
+        let mut prefix = OsString::from(".");
+        prefix.push(destination.file_name().unwrap_or_else(|| OsStr::new(DEFAULT_OUTPUT)));
+        prefix.push(format!(".{}", process::id()));
+
+        let mut attempt = 0;
+
+        loop {
+            let mut name = prefix.clone();
+            name.push(format!(".{}.tmp", attempt));
+            let path = directory.join(name);
+
+            match OpenOptions::new().write(true).create_new(true).open(&path) {
+                Ok(file) => {
+                    return Ok(Self {
+                        path,
+                        file,
+                        persisted: false,
+                    })
+                }
+                Err(ref error) if error.kind() == io::ErrorKind::AlreadyExists => attempt += 1,
+                Err(error) => return Err(error),
+            }
+        }
+    }
+
+    fn persist(mut self, destination: &Path, mode: u32) -> io::Result<()> {
+        self.file.sync_all()?;
+        self.file.set_permissions(fs::Permissions::from_mode(mode))?;
+        fs::rename(&self.path, destination)?;
+        self.persisted = true;
+        Ok(())
+    }
+}
+
+impl Drop for TemporaryFile {
+    fn drop(&mut self) {
+        if !self.persisted {
+            // Best-effort cleanup; whatever caused us to get here is more interesting than a failure to delete
+            let _ = fs::remove_file(&self.path);
+        }
+    }
+}
+
+enum JobError<'a> {
+    Read(Source, io::Error),
+    Compile(String),
+    Allocation(Source, AllocationError),
+    Options(OptionsError),
+    Write(&'a Destination, io::Error),
+    WriteListing(&'a Path, io::Error),
+}
+
+impl<'a> Display for JobError<'a> {
+    fn fmt(&self, formatter: &mut Formatter) -> fmt::Result {
+        match self {
+            JobError::Read(source, error) => write!(formatter, "{}: error reading program: {}", source, error),
+            JobError::Compile(diagnostic) => write!(formatter, "{}", diagnostic.trim_end()),
+            JobError::Allocation(source, error) => write!(formatter, "{}: {}", source, error),
+            JobError::Options(error) => write!(formatter, "{}", error),
+            JobError::Write(destination, error) => {
+                write!(formatter, "{}: error writing executable: {}", destination, error)
+            }
+            JobError::WriteListing(path, error) => {
+                write!(formatter, "{}: error writing listing: {}", path.display(), error)
+            }
+        }
+    }
 }
@@ -0,0 +1,304 @@
+use std::collections::BTreeMap;
+use std::mem;
+
+use crate::ir::{Node, Op};
+use crate::options::Passes;
+use crate::stream::Span;
+
+/*
+    Optimizations are implemented as rewrites of the IR tree. Loop bodies are optimized
+    before the loops containing them, so each pass only needs to recognize patterns
+    one level deep.
+
+    Offsets are folded last, once no further loops will be recognized, since the loop
+    passes expect to see plain moves and additions.
+
+    Each pass can be disabled; with all of them disabled, the tree is returned unchanged.
+*/
+
+// Recognizes a loop by its (already optimized) body, returning the operation to replace it with
+type LoopRewrite = fn(&[Node]) -> Option<Op>;
+
+pub fn optimize(nodes: Vec<Node>, passes: &Passes) -> Vec<Node> {
+    let nodes = nodes.into_iter().map(|node| optimize_node(node, passes)).collect();
+    fold_offsets(nodes, passes)
+}
+
+fn optimize_node(node: Node, passes: &Passes) -> Node {
+    let body = match node.op {
+        Op::Loop(body) => body
+            .into_iter()
+            .map(|node| optimize_node(node, passes))
+            .collect::<Vec<_>>(),
+        op => return Node::new(op, node.span),
+    };
+
+    let rewrites: [(bool, LoopRewrite); 3] = [
+        (passes.clear_loops, clear_loop),
+        (passes.multiply_loops, multiply_loop),
+        (passes.scan_loops, scan_loop),
+    ];
+
+    let op = rewrites
+        .iter()
+        .filter(|(enabled, _)| *enabled)
+        .find_map(|(_, rewrite)| rewrite(&body))
+        .unwrap_or_else(|| Op::Loop(fold_offsets(body, passes)));
+    Node::new(op, node.span)
+}
+
+// A loop whose body only adds an odd value to the current cell always terminates with the cell at zero,
+// because an odd value generates every residue modulo the cell size; [-] and [+] are the common cases
+fn clear_loop(body: &[Node]) -> Option<Op> {
+    match body {
+        [Node { op: Op::Add(value), .. }] if value % 2 != 0 => Some(Op::Clear),
+        _ => None,
+    }
+}
+
+// A loop whose body only moves and adds, returns to the cell it started on, and adds exactly -1 or +1 to
+// that cell runs a number of times determined by the cell's initial value; its effect on every other cell
+// is to add a fixed multiple of that value. Cells the loop visits but leaves unchanged are kept as terms with a
+// factor of zero, so that moving off the tape to reach them is still caught. Such an error is reported at the
+// loop's `[` rather than at the move within it
+fn multiply_loop(body: &[Node]) -> Option<Op> {
+    let mut offset = 0i64;
+    let mut deltas = BTreeMap::new();
+
+    for node in body {
+        match node.op {
+            Op::Move(shift) => offset = offset.checked_add(shift)?,
+            Op::Add(value) => {
+                let delta = deltas.entry(offset).or_insert(0i64);
+                *delta = delta.checked_add(value)?;
+            }
+            _ => return None,
+        }
+    }
+
+    if offset != 0 {
+        return None;
+    }
+
+    // A loop which decrements the current cell runs exactly that many times. One which increments it runs
+    // the negation of that many times (modulo the cell size), so we negate its factors instead
+    let sign = match deltas.remove(&0) {
+        Some(-1) => 1,
+        Some(1) => -1,
+        _ => return None,
+    };
+
+    let terms = deltas
+        .into_iter()
+        .map(|(offset, delta)| Some((offset, delta.checked_mul(sign)?)))
+        .collect::<Option<Vec<_>>>()?;
+
+    if terms.is_empty() {
+        return Some(Op::Clear);
+    }
+
+    Some(Op::MultiplyAdd(terms))
+}
+
+// A loop whose body is a single move searches for a zero cell
+fn scan_loop(body: &[Node]) -> Option<Op> {
+    match body {
+        [Node {
+            op: Op::Move(stride), ..
+        }] if *stride != 0 => Some(Op::Scan(*stride)),
+        _ => None,
+    }
+}
+
+// Runs of moves and cell updates are turned into blocks which address cells relative to the tape pointer as
+// it was at the start of the run; loops, scans and I/O end a run, since they need the real tape pointer
+fn fold_offsets(nodes: Vec<Node>, passes: &Passes) -> Vec<Node> {
+    if !passes.offset_addressing {
+        return nodes;
+    }
+
+    let mut folded = vec![];
+    let mut run = vec![];
+
+    for node in nodes {
+        match node.op {
+            Op::Move(_) | Op::Add(_) | Op::Clear | Op::MultiplyAdd(_) => run.push(node),
+            _ => {
+                fold_run(&mut folded, mem::take(&mut run));
+                folded.push(node);
+            }
+        }
+    }
+
+    fold_run(&mut folded, run);
+    folded
+}
+
+fn fold_run(folded: &mut Vec<Node>, run: Vec<Node>) {
+    let mut offset = 0i64;
+    let mut ops = vec![];
+
+    // A block only pays off if it touches some cell other than the one it started on
+    let mut displaced = false;
+
+    for node in &run {
+        ops.push((offset, node.clone()));
+
+        match node.op {
+            Op::Move(shift) => match offset.checked_add(shift) {
+                Some(sum) => offset = sum,
+                None => {
+                    folded.extend(run);
+                    return;
+                }
+            },
+            Op::MultiplyAdd(_) => displaced = true,
+            _ => displaced |= offset != 0,
+        }
+    }
+
+    if !displaced {
+        folded.extend(run);
+        return;
+    }
+
+    let span = Span::new(run.first().unwrap().span.start, run.last().unwrap().span.end);
+    folded.push(Node::new(Op::Block(ops, offset), span));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ir;
+    use crate::stream::Stream;
+
+    const NONE: Passes = Passes {
+        clear_loops: false,
+        multiply_loops: false,
+        scan_loops: false,
+        offset_addressing: false,
+        vectorize_scans: false,
+    };
+
+    // The optimized program, described without its spans
+    fn optimized(source: &str, passes: Passes) -> Vec<String> {
+        let nodes = ir::build(&mut Stream::new(source.as_bytes()), false).unwrap();
+        optimize(nodes, &passes).iter().map(describe).collect()
+    }
+
+    fn describe(node: &Node) -> String {
+        match &node.op {
+            Op::Move(shift) => format!("move {}", shift),
+            Op::Add(value) => format!("add {}", value),
+            Op::Read => "read".to_string(),
+            Op::Write => "write".to_string(),
+            Op::Debug => "debug".to_string(),
+            Op::Clear => "clear".to_string(),
+            Op::Loop(body) => format!("loop({})", body.iter().map(describe).collect::<Vec<_>>().join(", ")),
+            Op::MultiplyAdd(terms) => format!("multiply {:?}", terms),
+            Op::Scan(stride) => format!("scan {}", stride),
+            Op::Block(ops, shift) => {
+                let ops = ops
+                    .iter()
+                    .map(|(offset, node)| format!("{}: {}", offset, describe(node)))
+                    .collect::<Vec<_>>();
+                format!("block({}; {})", ops.join(", "), shift)
+            }
+        }
+    }
+
+    #[test]
+    fn no_passes() {
+        assert_eq!(
+            optimized("+[-]>[>]", NONE),
+            ["add 1", "loop(add -1)", "move 1", "loop(move 1)"]
+        );
+    }
+
+    #[test]
+    fn clear_loops() {
+        let passes = Passes {
+            clear_loops: true,
+            ..NONE
+        };
+
+        assert_eq!(optimized("[-][+][---]", passes), ["clear", "clear", "clear"]);
+
+        // An even value might never reach zero, so the loop is left alone
+        assert_eq!(optimized("[--]", passes), ["loop(add -2)"]);
+        assert_eq!(optimized("[-]", NONE), ["loop(add -1)"]);
+    }
+
+    #[test]
+    fn multiply_loops() {
+        let passes = Passes {
+            multiply_loops: true,
+            ..NONE
+        };
+
+        assert_eq!(optimized("[->++>>+++<<<]", passes), ["multiply [(1, 2), (3, 3)]"]);
+        assert_eq!(optimized("[<<-->>-]", passes), ["multiply [(-2, -2)]"]);
+
+        // Incrementing the counter negates the factors
+        assert_eq!(optimized("[>+++<+]", passes), ["multiply [(1, -3)]"]);
+
+        // Cells which are visited but end up unchanged are kept, with a factor of zero
+        assert_eq!(optimized("[->+-<<<++>>]", passes), ["multiply [(-2, 2), (1, 0)]"]);
+
+        // The counter has to change by exactly one, and the loop has to return to the cell it started on
+        assert_eq!(
+            optimized("[->+<-]", passes),
+            ["loop(add -1, move 1, add 1, move -1, add -1)"]
+        );
+        assert_eq!(optimized("[->+<<]", passes), ["loop(add -1, move 1, add 1, move -2)"]);
+        assert_eq!(optimized("[->.<]", passes), ["loop(add -1, move 1, write, move -1)"]);
+        assert_eq!(optimized("[->+<]", NONE), ["loop(add -1, move 1, add 1, move -1)"]);
+    }
+
+    #[test]
+    fn scan_loops() {
+        let passes = Passes {
+            scan_loops: true,
+            ..NONE
+        };
+
+        assert_eq!(optimized("[>][<<<]", passes), ["scan 1", "scan -3"]);
+        assert_eq!(optimized("[><]", passes), ["loop(move 0)"]);
+        assert_eq!(optimized("[>]", NONE), ["loop(move 1)"]);
+    }
+
+    #[test]
+    fn offset_addressing() {
+        let passes = Passes {
+            offset_addressing: true,
+            ..NONE
+        };
+
+        assert_eq!(
+            optimized("+>++>-<.", passes),
+            [
+                "block(0: add 1, 0: move 1, 1: add 2, 1: move 1, 2: add -1, 2: move -1; 1)",
+                "write"
+            ]
+        );
+
+        // Runs within loops are folded too, but runs which only touch the cell they start on aren't
+        assert_eq!(
+            optimized("+[>+<-]", passes),
+            ["add 1", "loop(block(0: move 1, 1: add 1, 1: move -1, 0: add -1; 0))"]
+        );
+        assert_eq!(optimized("+>.", passes), ["add 1", "move 1", "write"]);
+        assert_eq!(optimized("+>++.", NONE), ["add 1", "move 1", "add 2", "write"]);
+    }
+
+    #[test]
+    fn passes_combined() {
+        assert_eq!(
+            optimized("+[->+<]>[-]>[>]", Passes::level(2)),
+            [
+                "block(0: add 1, 0: multiply [(1, 1)], 0: move 1, 1: clear, 1: move 1; 2)",
+                "scan 1"
+            ]
+        );
+    }
+}
@@ -0,0 +1,245 @@
+use std::fmt;
+use std::fmt::{Display, Formatter};
+use std::num::NonZeroUsize;
+use std::path::PathBuf;
+
+use crate::stream::DEFAULT_TAB_WIDTH;
+
+/// The number of cells on the tape when no length is given.
+pub const DEFAULT_TAPE_LENGTH: u64 = 30000;
+
+/// The size in bytes of the input and output buffers when none is given.
+pub const DEFAULT_BUFFER_SIZE: u64 = 64 * 1024;
+
+/// The largest supported size in bytes of the input and output buffers.
+pub const MAX_BUFFER_SIZE: u64 = 1 << 30;
+
+/// The optimization level used when none is given.
+pub const DEFAULT_OPTIMIZATION_LEVEL: u8 = 3;
+
+/// The highest supported optimization level.
+pub const MAX_OPTIMIZATION_LEVEL: u8 = 3;
+
+/// Settings which control how a program is compiled. `Options::default()` produces the same executable as the
+/// `brainrust` command with no flags.
+#[derive(Clone, Debug)]
+pub struct Options {
+    /// Distance between tab stops, used when computing the columns reported in diagnostics.
+    pub tab_width: NonZeroUsize,
+    /// Number of cells on the tape (initially, if the tape grows); must be nonzero.
+    pub tape_length: u64,
+    /// What happens when the tape pointer moves past either end of the tape.
+    pub boundary: Boundary,
+    /// The size of each cell on the tape.
+    pub cell_width: CellWidth,
+    /// What `,` does at the end of input.
+    pub eof: Eof,
+    /// Size in bytes of the buffer input is read into, from 1 to `MAX_BUFFER_SIZE`. With a size of 1, input is
+    /// read a byte at a time, so the program never consumes more input than it uses.
+    pub input_buffer_size: u64,
+    /// Size in bytes of the buffer output is collected in, from 1 to `MAX_BUFFER_SIZE`. With a size of 1, each
+    /// byte is written as soon as it's output. Buffered output is always flushed before reading input.
+    pub output_buffer_size: u64,
+    /// Whether writing a newline flushes the output buffer.
+    pub flush_on_newline: bool,
+    /// Whether `#` is a command, which writes the tape pointer and the cells around it to standard error, rather
+    /// than a comment.
+    pub debug_command: bool,
+    /// The optimization passes to run.
+    pub passes: Passes,
+    /// The path of the source file, if the executable is to include DWARF debugging information mapping its code
+    /// back to the source, so that debuggers can set breakpoints on and step through lines of brainfuck. A relative
+    /// path is recorded relative to the current directory.
+    pub debug_info: Option<PathBuf>,
+}
+
+impl Options {
+    /// Check that each setting is within its allowed range. Compiling or running a program checks its options
+    /// first, so this is only needed to report a problem before then.
+    pub fn validate(&self) -> Result<(), OptionsError> {
+        let valid_buffer_size = |size| 0 < size && size <= MAX_BUFFER_SIZE;
+
+        if self.tape_length == 0 {
+            Err(OptionsError::TapeLength)
+        } else if !valid_buffer_size(self.input_buffer_size) {
+            Err(OptionsError::InputBufferSize(self.input_buffer_size))
+        } else if !valid_buffer_size(self.output_buffer_size) {
+            Err(OptionsError::OutputBufferSize(self.output_buffer_size))
+        } else {
+            Ok(())
+        }
+    }
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Self {
+            tab_width: DEFAULT_TAB_WIDTH,
+            tape_length: DEFAULT_TAPE_LENGTH,
+            boundary: Boundary::Wrap,
+            cell_width: CellWidth::Bits8,
+            eof: Eof::Unchanged,
+            input_buffer_size: DEFAULT_BUFFER_SIZE,
+            output_buffer_size: DEFAULT_BUFFER_SIZE,
+            flush_on_newline: true,
+            debug_command: false,
+            passes: Passes::level(DEFAULT_OPTIMIZATION_LEVEL),
+            debug_info: None,
+        }
+    }
+}
+
+/// Settings in `Options` which are outside their allowed ranges.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum OptionsError {
+    /// The tape length is zero.
+    TapeLength,
+    /// The input buffer size, which is zero or larger than `MAX_BUFFER_SIZE`.
+    InputBufferSize(u64),
+    /// The output buffer size, which is zero or larger than `MAX_BUFFER_SIZE`.
+    OutputBufferSize(u64),
+}
+
+impl Display for OptionsError {
+    fn fmt(&self, formatter: &mut Formatter) -> fmt::Result {
+        let (name, size) = match self {
+            OptionsError::TapeLength => return write!(formatter, "the tape length must be nonzero"),
+            OptionsError::InputBufferSize(size) => ("input", size),
+            OptionsError::OutputBufferSize(size) => ("output", size),
+        };
+
+        write!(
+            formatter,
+            "invalid {} buffer size {}; it must be from 1 to {} bytes",
+            name, size, MAX_BUFFER_SIZE
+        )
+    }
+}
+
+/// Behaviors of the tape pointer at the ends of the tape.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Boundary {
+    /// Wrap around to the other end of the tape.
+    Wrap,
+    /// Stop the program, reporting the source position of the offending command on standard error and exiting
+    /// with status 3. Where a loop has been compiled to a multiplication, the position reported is that of the
+    /// loop's `[`, so it depends on which optimizations are enabled.
+    Abort,
+    /// Grow the tape on demand, so that it's unbounded to the right; moving past the left end stops the program
+    /// as for `Abort`. If the tape can't be grown, the program exits with status 4.
+    Grow,
+}
+
+/// Sizes of the cells on the tape. Arithmetic on cells wraps around modulo the cell size; `,` stores the byte read
+/// zero-extended to the cell size, and `.` writes the low 8 bits of the cell.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CellWidth {
+    Bits8,
+    Bits16,
+    Bits32,
+    Bits64,
+}
+
+impl CellWidth {
+    /// The number of bits in a cell.
+    pub fn bits(self) -> u32 {
+        match self {
+            CellWidth::Bits8 => 8,
+            CellWidth::Bits16 => 16,
+            CellWidth::Bits32 => 32,
+            CellWidth::Bits64 => 64,
+        }
+    }
+
+    /// The number of bytes in a cell.
+    pub fn bytes(self) -> u64 {
+        u64::from(self.bits() / 8)
+    }
+
+    /// The cell width with the given number of bits, if there is one.
+    pub fn from_bits(bits: u32) -> Option<Self> {
+        match bits {
+            8 => Some(CellWidth::Bits8),
+            16 => Some(CellWidth::Bits16),
+            32 => Some(CellWidth::Bits32),
+            64 => Some(CellWidth::Bits64),
+            _ => None,
+        }
+    }
+
+    /// Reduce a value modulo the cell size, e.g. -1 becomes 255 for 8-bit cells.
+    pub fn wrap(self, value: i64) -> u64 {
+        (value as u64) & (u64::MAX >> (64 - self.bits()))
+    }
+}
+
+/// Conventions for the `,` command at the end of input. Either way, a failure to read input stops the program
+/// with exit status 2.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Eof {
+    /// Leave the current cell unchanged.
+    Unchanged,
+    /// Set the current cell to zero.
+    Zero,
+    /// Set the current cell to -1, i.e. every bit set.
+    MinusOne,
+}
+
+/// Individual optimization passes, each of which can be toggled independently of the optimization level. With
+/// every pass disabled, the program is translated literally, one sequence of instructions per command.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Passes {
+    /// Compile loops like `[-]` to a single store.
+    pub clear_loops: bool,
+    /// Compile loops like `[->+>++<<]` to straight-line multiplications.
+    pub multiply_loops: bool,
+    /// Compile loops like `[>]` to a dedicated search for a zero cell.
+    pub scan_loops: bool,
+    /// Address cells relative to the tape pointer, moving it once per run of commands.
+    pub offset_addressing: bool,
+    /// Search for zero cells 16 at a time using SSE2.
+    pub vectorize_scans: bool,
+}
+
+impl Passes {
+    /// The name of each pass, as accepted by `set` and the `-f` command-line flags.
+    pub const NAMES: [&'static str; 5] = [
+        "clear-loops",
+        "multiply-loops",
+        "scan-loops",
+        "offset-addressing",
+        "vectorize-scans",
+    ];
+
+    /// The passes enabled at the given optimization level; levels above `MAX_OPTIMIZATION_LEVEL` are treated
+    /// as the maximum.
+    ///
+    /// - 0: no passes
+    /// - 1: clear, multiply and scan loops
+    /// - 2: as above, plus offset addressing
+    /// - 3: as above, plus vectorized scans
+    pub fn level(level: u8) -> Self {
+        Self {
+            clear_loops: level >= 1,
+            multiply_loops: level >= 1,
+            scan_loops: level >= 1,
+            offset_addressing: level >= 2,
+            vectorize_scans: level >= 3,
+        }
+    }
+
+    /// Enable or disable the pass with the given name, returning false if there's no such pass.
+    pub fn set(&mut self, name: &str, enabled: bool) -> bool {
+        let pass = match name {
+            "clear-loops" => &mut self.clear_loops,
+            "multiply-loops" => &mut self.multiply_loops,
+            "scan-loops" => &mut self.scan_loops,
+            "offset-addressing" => &mut self.offset_addressing,
+            "vectorize-scans" => &mut self.vectorize_scans,
+            _ => return false,
+        };
+
+        *pass = enabled;
+        true
+    }
+}
@@ -2,7 +2,7 @@ use std::fmt;
 use std::fmt::{Debug, Display, Formatter};
 use std::io;
 
-use crate::stream::Stream;
+use crate::stream::{Position, Span, Stream};
 
 pub enum Token {
     Move(i64),
@@ -11,33 +11,45 @@ pub enum Token {
     WriteChar,
     LoopStart,
     LoopEnd,
+    Debug,
     EndOfFile,
 }
 
 use Token::*;
 
-pub fn parse<R: io::Read>(stream: &mut Stream<R>) -> Result<(Token, usize, usize), ParseError> {
+// Returns the next token along with the span of source it was parsed from. Runs of moves or increments are
+// merged into a single token whose span covers the whole run, excluding any trailing comment characters. `#` is
+// a comment character unless debug is set, in which case it's a command of its own
+pub fn parse<R: io::Read>(stream: &mut Stream<R>, debug: bool) -> Result<(Token, Span), ParseError> {
     while let Some(byte) = stream.peek()? {
-        let (line, column) = (stream.line, stream.column);
+        let start = stream.position();
         stream.forward();
+        let mut end = stream.position();
 
         let token = match byte {
-            b'>' | b'<' => parse_move(stream, byte)?,
-            b'+' | b'-' => parse_increment(stream, byte)?,
+            b'>' | b'<' => parse_move(stream, byte, debug, &mut end)?,
+            b'+' | b'-' => parse_increment(stream, byte, debug, &mut end)?,
             b'[' => LoopStart,
             b']' => LoopEnd,
             b'.' => WriteChar,
             b',' => ReadChar,
+            b'#' if debug => Debug,
             _ => continue,
         };
 
-        return Ok((token, line, column));
+        return Ok((token, Span::new(start, end)));
     }
 
-    Ok((EndOfFile, stream.line, stream.column))
+    let position = stream.position();
+    Ok((EndOfFile, Span::new(position, position)))
 }
 
-fn parse_move<R: io::Read>(stream: &mut Stream<R>, byte: u8) -> Result<Token, ParseError> {
+fn parse_move<R: io::Read>(
+    stream: &mut Stream<R>,
+    byte: u8,
+    debug: bool,
+    end: &mut Position,
+) -> Result<Token, ParseError> {
     let mut shift = {
         if byte == b'>' {
             1
@@ -51,15 +63,26 @@ fn parse_move<R: io::Read>(stream: &mut Stream<R>, byte: u8) -> Result<Token, Pa
             b'>' => shift += 1,
             b'<' => shift -= 1,
             b'+' | b'-' | b'[' | b']' | b'.' | b',' => break,
-            _ => (),
+            b'#' if debug => break,
+            _ => {
+                // Comment characters are skipped, but don't extend the token's span
+                stream.forward();
+                continue;
+            }
         }
         stream.forward();
+        *end = stream.position();
     }
 
     Ok(Move(shift))
 }
 
-fn parse_increment<R: io::Read>(stream: &mut Stream<R>, byte: u8) -> Result<Token, ParseError> {
+fn parse_increment<R: io::Read>(
+    stream: &mut Stream<R>,
+    byte: u8,
+    debug: bool,
+    end: &mut Position,
+) -> Result<Token, ParseError> {
     let mut value = {
         if byte == b'+' {
             1
@@ -73,9 +96,15 @@ fn parse_increment<R: io::Read>(stream: &mut Stream<R>, byte: u8) -> Result<Toke
             b'+' => value += 1,
             b'-' => value -= 1,
             b'>' | b'<' | b'[' | b']' | b'.' | b',' => break,
-            _ => (),
+            b'#' if debug => break,
+            _ => {
+                // Comment characters are skipped, but don't extend the token's span
+                stream.forward();
+                continue;
+            }
         }
         stream.forward();
+        *end = stream.position();
     }
 
     Ok(Add(value))
@@ -117,20 +146,54 @@ impl From<SyntaxError> for ParseError {
 }
 
 pub struct SyntaxError {
-    line: usize,
-    column: usize,
+    position: Position,
     message: &'static str,
+    notes: Vec<Note>,
+}
+
+// A secondary position attached to a syntax error, e.g. one of several unclosed loops
+pub struct Note {
+    pub position: Position,
+    pub message: &'static str,
 }
 
 impl SyntaxError {
-    fn new(line: usize, column: usize, message: &'static str) -> Self {
-        Self { line, column, message }
+    pub fn new(position: Position, message: &'static str) -> Self {
+        Self {
+            position,
+            message,
+            notes: vec![],
+        }
+    }
+
+    pub fn add_note(&mut self, position: Position, message: &'static str) {
+        self.notes.push(Note { position, message });
+    }
+
+    pub fn position(&self) -> Position {
+        self.position
+    }
+
+    pub fn message(&self) -> &'static str {
+        self.message
+    }
+
+    pub fn notes(&self) -> &[Note] {
+        &self.notes
     }
 }
 
 impl Display for SyntaxError {
     fn fmt(&self, formatter: &mut Formatter) -> fmt::Result {
-        write!(formatter, "{}:{}: {}", self.line, self.column, self.message)
+        let Position { line, column, .. } = self.position;
+        write!(formatter, "{}:{}: {}", line, column, self.message)?;
+
+        for note in &self.notes {
+            let Position { line, column, .. } = note.position;
+            write!(formatter, "; {}:{}: {}", line, column, note.message)?;
+        }
+
+        Ok(())
     }
 }
 
@@ -139,3 +202,88 @@ impl Debug for SyntaxError {
         Display::fmt(self, formatter)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Each token with the columns its span covers, on the first line
+    fn tokens(source: &str, debug: bool) -> Vec<(String, usize, usize)> {
+        let mut stream = Stream::new(source.as_bytes());
+        let mut tokens = vec![];
+
+        loop {
+            let (token, span) = parse(&mut stream, debug).unwrap();
+
+            let name = match token {
+                Move(shift) => format!("move {}", shift),
+                Add(value) => format!("add {}", value),
+                ReadChar => "read".to_string(),
+                WriteChar => "write".to_string(),
+                LoopStart => "[".to_string(),
+                LoopEnd => "]".to_string(),
+                Debug => "debug".to_string(),
+                EndOfFile => return tokens,
+            };
+
+            tokens.push((name, span.start.column, span.end.column));
+        }
+    }
+
+    fn token(name: &str, start: usize, end: usize) -> (String, usize, usize) {
+        (name.to_string(), start, end)
+    }
+
+    #[test]
+    fn runs_are_merged() {
+        assert_eq!(
+            tokens(">>><+++--", false),
+            [token("move 2", 1, 5), token("add 1", 5, 10)]
+        );
+        assert_eq!(tokens("<<>>", false), [token("move 0", 1, 5)]);
+    }
+
+    #[test]
+    fn commands_end_runs() {
+        assert_eq!(
+            tokens("+[>.<,]-", false),
+            [
+                token("add 1", 1, 2),
+                token("[", 2, 3),
+                token("move 1", 3, 4),
+                token("write", 4, 5),
+                token("move -1", 5, 6),
+                token("read", 6, 7),
+                token("]", 7, 8),
+                token("add -1", 8, 9),
+            ]
+        );
+    }
+
+    #[test]
+    fn comments_inside_runs() {
+        // Comments within a run are skipped, but trailing ones aren't part of its span
+        assert_eq!(tokens("+ a +  .", false), [token("add 2", 1, 6), token("write", 8, 9)]);
+        assert_eq!(tokens("> x > y", false), [token("move 2", 1, 6)]);
+    }
+
+    #[test]
+    fn debug_command() {
+        assert_eq!(tokens("+#+", false), [token("add 2", 1, 4)]);
+        assert_eq!(
+            tokens("+#+", true),
+            [token("add 1", 1, 2), token("debug", 2, 3), token("add 1", 3, 4)]
+        );
+    }
+
+    #[test]
+    fn end_of_file() {
+        let mut stream = Stream::new(&b"+\nx"[..]);
+        parse(&mut stream, false).unwrap();
+
+        let (token, span) = parse(&mut stream, false).unwrap();
+        assert!(matches!(token, EndOfFile));
+        assert_eq!((span.start.line, span.start.column), (2, 2));
+        assert_eq!(span.start, span.end);
+    }
+}
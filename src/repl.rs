@@ -0,0 +1,215 @@
+use std::io::{BufRead, Write};
+
+use crate::console;
+use crate::console::Output;
+use crate::diagnostic::{Renderer, Style};
+use crate::interpreter::{Interpreter, RunError};
+use crate::ir;
+use crate::optimizer;
+use crate::options::Options;
+use crate::stream::Stream;
+
+/*
+    The REPL reads brainfuck a line at a time and runs each line on the interpreter,
+    against a tape which persists from one line to the next. A line which leaves a loop
+    open is continued on the lines that follow, until the loop is closed or a blank line
+    gives up on it. After each line, the cells around the tape pointer are shown.
+
+    Lines starting with a colon are meta-commands, which inspect or modify the tape
+    directly (see HELP). The program's input comes from the same stream as the lines
+    themselves, so `,` reads from the text following the current line.
+
+    A line that fails partway through, e.g. by moving off the end of the tape, keeps
+    whatever effects it had before the failure.
+*/
+
+const PROMPT: &str = "> ";
+const CONTINUATION_PROMPT: &str = ". ";
+
+// Name under which lines are quoted in diagnostics
+const FILE_NAME: &str = "<repl>";
+
+const HELP: &str = "\
+Meta-commands:
+  :reset               Clear the tape and move the pointer back to cell 0
+  :dump [START [END]]  Show the cells from START to END; with only START, show
+                       16 cells, and with neither, the whole tape up to the
+                       last nonzero cell
+  :set CELL VALUE...   Store each VALUE in consecutive cells, starting at CELL;
+                       a VALUE is a number, or a character in single quotes,
+                       which may be a space
+  :help                Print this message
+  :quit                Exit, as does the end of input
+";
+
+pub struct Repl<R, W> {
+    interpreter: Interpreter<R, Output<W>>,
+    options: Options,
+    style: Style,
+}
+
+impl<R: BufRead, W: Write> Repl<R, W> {
+    pub fn new(options: &Options, input: R, output: W, style: Style) -> Result<Self, RunError> {
+        Ok(Self {
+            interpreter: Interpreter::new(options, input, Output::new(output))?,
+            options: options.clone(),
+            style,
+        })
+    }
+
+    // Run lines until the end of input or :quit. Errors in the lines themselves are reported and the session carries
+    // on, so the only errors returned are failures to read or write
+    pub fn run(&mut self) -> Result<(), RunError> {
+        while let Some(entry) = self.read_entry()? {
+            let text = String::from_utf8_lossy(&entry);
+
+            match text.trim().strip_prefix(':') {
+                Some(command) => {
+                    if !self.meta_command(command)? {
+                        break;
+                    }
+                }
+                None if text.trim().is_empty() => continue,
+                None => self.execute(&entry)?,
+            }
+        }
+
+        Ok(())
+    }
+
+    // Read a line, along with as many more as it takes to close every loop it opens; None at the end of input
+    fn read_entry(&mut self) -> Result<Option<Vec<u8>>, RunError> {
+        let mut entry = vec![];
+        let mut prompt = PROMPT;
+
+        loop {
+            let length = entry.len();
+            let (input, output) = self.interpreter.streams_mut();
+
+            if output.prompt(prompt, input, &mut entry).map_err(RunError::Read)? == 0 {
+                // An unfinished entry is still run, so that its unclosed loops are reported
+                if entry.is_empty() {
+                    // Leave the terminal's cursor on a fresh line, rather than after the prompt
+                    self.print("")?;
+                    return Ok(None);
+                }
+
+                return Ok(Some(entry));
+            }
+
+            // A blank continuation line ends the entry regardless, so that its unclosed loops are reported
+            if length > 0 && entry[length..].iter().all(u8::is_ascii_whitespace) {
+                return Ok(Some(entry));
+            }
+
+            let is_meta_command = entry.iter().find(|byte| !byte.is_ascii_whitespace()) == Some(&b':');
+            let depth = entry.iter().fold(0i64, |depth, byte| match byte {
+                b'[' => depth + 1,
+                b']' => depth - 1,
+                _ => depth,
+            });
+
+            if is_meta_command || depth <= 0 {
+                return Ok(Some(entry));
+            }
+
+            prompt = CONTINUATION_PROMPT;
+        }
+    }
+
+    fn execute(&mut self, entry: &[u8]) -> Result<(), RunError> {
+        let mut stream = Stream::with_tab_width(entry, self.options.tab_width);
+        let result = ir::build(&mut stream, self.options.debug_command)
+            .map_err(RunError::from)
+            .and_then(|nodes| {
+                let nodes = optimizer::optimize(nodes, &self.options.passes);
+                self.interpreter.run(&nodes)
+            });
+
+        match result {
+            Ok(()) => (),
+            Err(RunError::Parse(error)) => {
+                let renderer = Renderer::new(FILE_NAME, entry, self.style).with_tab_width(self.options.tab_width);
+                return self.print(&renderer.render(&error));
+            }
+            Err(error @ RunError::Read(_)) | Err(error @ RunError::Write(_)) => return Err(error),
+            Err(error) => self.print(&format!("error: {}\n", error))?,
+        }
+
+        self.show_neighborhood()
+    }
+
+    // Carry out a meta-command, given without its colon; returns false if the session should end
+    fn meta_command(&mut self, command: &str) -> Result<bool, RunError> {
+        let mut words = console::split_arguments(command).into_iter();
+        let name = words.next().unwrap_or("");
+        let arguments: Vec<&str> = words.collect();
+
+        let result = match name {
+            "reset" if arguments.is_empty() => {
+                self.interpreter.reset();
+                self.show_neighborhood().map(Ok)
+            }
+            "dump" => {
+                let tape = self.interpreter.tape();
+                let dump = console::dump(tape, self.interpreter.pointer(), ":dump", &arguments);
+                dump.map_or_else(|message| Ok(Err(message)), |text| self.print(&text).map(Ok))
+            }
+            "set" => self.set(&arguments),
+            "help" => self.print(HELP).map(Ok),
+            "quit" | "q" => return Ok(false),
+            "reset" => Ok(Err("':reset' takes no arguments".to_string())),
+            _ => Ok(Err(format!("unknown meta-command ':{}' (try ':help')", name))),
+        };
+
+        if let Err(message) = result? {
+            self.print(&format!("error: {}\n", message))?;
+        }
+
+        Ok(true)
+    }
+
+    // Meta-commands return Ok(Err(message)) for mistakes in their arguments, which don't end the session
+    fn set(&mut self, arguments: &[&str]) -> Result<Result<(), String>, RunError> {
+        let (start, values) = match arguments {
+            [start, values @ ..] if !values.is_empty() => (start, values),
+            _ => return Ok(Err("':set' takes a cell and at least one value".to_string())),
+        };
+
+        let start = match console::parse_cell(start) {
+            Ok(start) => start,
+            Err(message) => return Ok(Err(message)),
+        };
+
+        // Check everything up front, so that a mistake leaves the tape untouched
+        let values = match values
+            .iter()
+            .map(|value| console::parse_value(value))
+            .collect::<Result<Vec<_>, _>>()
+        {
+            Ok(values) => values,
+            Err(message) => return Ok(Err(message)),
+        };
+
+        let end = start.saturating_add(values.len() - 1);
+
+        if end >= self.interpreter.tape().len() {
+            return Ok(Err(format!("cell {} is past the end of the tape", end)));
+        }
+
+        for (index, value) in (start..).zip(values) {
+            self.interpreter.set(index, value);
+        }
+
+        self.show_neighborhood().map(Ok)
+    }
+
+    fn show_neighborhood(&mut self) -> Result<(), RunError> {
+        let neighborhood = console::neighborhood(self.interpreter.tape(), self.interpreter.pointer());
+        self.print(&neighborhood)
+    }
+
+    fn print(&mut self, text: &str) -> Result<(), RunError> {
+        self.interpreter.output_mut().print(text).map_err(RunError::Write)
+    }
+}
@@ -1,22 +1,108 @@
 use std::io;
+use std::io::Read;
+use std::num::NonZeroUsize;
 
-pub struct Stream<R: io::Read> {
+pub const DEFAULT_TAB_WIDTH: NonZeroUsize = match NonZeroUsize::new(8) {
+    Some(width) => width,
+    None => unreachable!(),
+};
+
+// A location in the source; offset is a zero-based byte offset, while line and column are one-based. Columns
+// count characters rather than bytes, with tabs advancing to the next tab stop
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Position {
+    pub offset: usize,
     pub line: usize,
     pub column: usize,
+}
+
+impl Position {
+    pub fn start() -> Self {
+        Self {
+            offset: 0,
+            line: 1,
+            column: 1,
+        }
+    }
+}
+
+// A half-open range of source, from the first byte of a token up to (but excluding) the byte after its end
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Span {
+    pub start: Position,
+    pub end: Position,
+}
+
+impl Span {
+    pub fn new(start: Position, end: Position) -> Self {
+        Self { start, end }
+    }
+
+    // The span of the command at the start of this span, e.g. a loop's `[`; commands are a single character each
+    pub fn first_character(self) -> Self {
+        let end = Position {
+            offset: self.start.offset + 1,
+            line: self.start.line,
+            column: self.start.column + 1,
+        };
+
+        Self::new(self.start, end)
+    }
+
+    // The span of the command at the end of this span, e.g. a loop's `]`
+    pub fn last_character(self) -> Self {
+        let start = Position {
+            offset: self.end.offset - 1,
+            line: self.end.line,
+            column: self.end.column - 1,
+        };
+
+        Self::new(start, self.end)
+    }
+}
+
+// The column following a byte within a line. Each character takes a single column, except that tabs advance to the
+// next tab stop; a byte which isn't valid UTF-8 counts as a character, unless it's a continuation byte
+pub fn next_column(column: usize, byte: u8, tab_width: NonZeroUsize) -> usize {
+    match byte {
+        b'\t' => {
+            let tab_width = tab_width.get();
+            (column - 1) / tab_width * tab_width + tab_width + 1
+        }
+        // UTF-8 continuation bytes belong to the preceding character
+        0x80..=0xbf => column,
+        _ => column + 1,
+    }
+}
+
+pub struct Stream<R: io::Read> {
+    position: Position,
+    tab_width: NonZeroUsize,
+    after_carriage_return: bool,
     peeked: Option<u8>,
-    bytes: io::Bytes<R>,
+    bytes: io::Bytes<io::BufReader<R>>,
 }
 
 impl<R: io::Read> Stream<R> {
     pub fn new(read: R) -> Self {
+        Self::with_tab_width(read, DEFAULT_TAB_WIDTH)
+    }
+
+    pub fn with_tab_width(read: R, tab_width: NonZeroUsize) -> Self {
         Self {
-            line: 1,
-            column: 1,
+            position: Position::start(),
+            tab_width,
+            after_carriage_return: false,
             peeked: None,
-            bytes: read.bytes(),
+            bytes: io::BufReader::new(read).bytes(),
         }
     }
 
+    // The position of the next byte to be returned by peek
+    pub fn position(&self) -> Position {
+        self.position
+    }
+
     pub fn peek(&mut self) -> Result<Option<u8>, io::Error> {
         if self.peeked.is_none() {
             if let Some(result) = self.bytes.next() {
@@ -28,8 +114,133 @@ impl<R: io::Read> Stream<R> {
     }
 
     pub fn forward(&mut self) {
-        assert!(self.peeked.is_some());
-        self.column += 1;
-        self.peeked = None;
+        let byte = self.peeked.take().expect("forward called without a peeked byte");
+        let position = &mut self.position;
+
+        position.offset += 1;
+
+        match byte {
+            // A line feed directly after a carriage return completes a CRLF pair, which has already been
+            // counted as a line break
+            b'\n' if self.after_carriage_return => (),
+            b'\n' | b'\r' => {
+                position.line += 1;
+                position.column = 1;
+            }
+            _ => position.column = next_column(position.column, byte, self.tab_width),
+        }
+
+        self.after_carriage_return = byte == b'\r';
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // The position of each byte of the source, followed by the position at its end
+    fn positions(source: &[u8], tab_width: usize) -> Vec<(usize, usize)> {
+        let mut stream = Stream::with_tab_width(source, NonZeroUsize::new(tab_width).unwrap());
+        let mut positions = vec![];
+
+        loop {
+            let Position { line, column, .. } = stream.position();
+            positions.push((line, column));
+
+            if stream.peek().unwrap().is_none() {
+                return positions;
+            }
+
+            stream.forward();
+        }
+    }
+
+    #[test]
+    fn line_breaks() {
+        assert_eq!(positions(b"a\nb", 8), [(1, 1), (1, 2), (2, 1), (2, 2)]);
+        assert_eq!(positions(b"a\rb", 8), [(1, 1), (1, 2), (2, 1), (2, 2)]);
+        assert_eq!(positions(b"a\r\nb", 8), [(1, 1), (1, 2), (2, 1), (2, 1), (2, 2)]);
+        assert_eq!(positions(b"\n\r\n\r", 8), [(1, 1), (2, 1), (3, 1), (3, 1), (4, 1)]);
+        assert_eq!(positions(b"\n\n", 8), [(1, 1), (2, 1), (3, 1)]);
+    }
+
+    #[test]
+    fn tabs() {
+        assert_eq!(positions(b"\ta", 8), [(1, 1), (1, 9), (1, 10)]);
+        assert_eq!(
+            positions(b"abc\td", 4),
+            [(1, 1), (1, 2), (1, 3), (1, 4), (1, 5), (1, 6)]
+        );
+        assert_eq!(
+            positions(b"abcd\t", 4),
+            [(1, 1), (1, 2), (1, 3), (1, 4), (1, 5), (1, 9)]
+        );
+        assert_eq!(positions(b"a\n\tb", 2), [(1, 1), (1, 2), (2, 1), (2, 3), (2, 4)]);
+    }
+
+    #[test]
+    fn utf8() {
+        // é is two bytes and € three, but each is a single character
+        assert_eq!(
+            positions("é€+".as_bytes(), 8),
+            [(1, 1), (1, 2), (1, 2), (1, 3), (1, 3), (1, 3), (1, 4)]
+        );
+    }
+
+    #[test]
+    fn offsets() {
+        let mut stream = Stream::new(&b"a\r\n\tb"[..]);
+
+        while stream.peek().unwrap().is_some() {
+            stream.forward();
+        }
+
+        assert_eq!(
+            stream.position(),
+            Position {
+                offset: 5,
+                line: 2,
+                column: 10
+            }
+        );
+    }
+
+    #[test]
+    fn first_and_last_characters() {
+        let span = Span::new(
+            Position {
+                offset: 2,
+                line: 1,
+                column: 3,
+            },
+            Position {
+                offset: 9,
+                line: 2,
+                column: 5,
+            },
+        );
+
+        assert_eq!(
+            span.first_character(),
+            Span::new(
+                span.start,
+                Position {
+                    offset: 3,
+                    line: 1,
+                    column: 4
+                }
+            )
+        );
+        assert_eq!(
+            span.last_character(),
+            Span::new(
+                Position {
+                    offset: 8,
+                    line: 2,
+                    column: 4
+                },
+                span.end
+            )
+        );
     }
 }
@@ -0,0 +1,160 @@
+// Helpers shared by the integration tests, which compile programs into executables and check that they behave as
+// the interpreter says they should
+#![allow(dead_code)]
+
+use std::env;
+use std::fs;
+use std::fs::OpenOptions;
+use std::io;
+use std::io::Write;
+use std::os::unix::fs::OpenOptionsExt;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output, Stdio};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
+use std::time::Duration;
+
+use brainrust::compiler::{EXIT_OUT_OF_BOUNDS, EXIT_OUT_OF_MEMORY, EXIT_READ_ERROR, EXIT_WRITE_ERROR};
+use brainrust::{Options, Passes, RunError};
+
+// errno for an executable which some process still has open for writing
+const ETXTBSY: i32 = 26;
+
+// How a program behaved: the status it exited with and what it wrote to each stream
+#[derive(Debug, PartialEq, Eq)]
+pub struct Outcome {
+    pub status: i32,
+    pub stdout: Vec<u8>,
+    pub stderr: Vec<u8>,
+}
+
+impl Outcome {
+    pub fn stdout_text(&self) -> String {
+        String::from_utf8_lossy(&self.stdout).into_owned()
+    }
+
+    pub fn stderr_text(&self) -> String {
+        String::from_utf8_lossy(&self.stderr).into_owned()
+    }
+}
+
+// Compile a program into an executable, run it with the given input and collect what it did
+pub fn execute(source: &[u8], options: &Options, input: &[u8]) -> Outcome {
+    static COUNT: AtomicUsize = AtomicUsize::new(0);
+
+    let executable = brainrust::compile_to_vec(source, options).expect("program should compile");
+    let path = PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join(format!(
+        "program-{}-{}",
+        std::process::id(),
+        COUNT.fetch_add(1, Ordering::Relaxed)
+    ));
+
+    {
+        let mut file = OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .mode(0o755)
+            .open(&path)
+            .expect("executable should be writable");
+
+        file.write_all(&executable).expect("executable should be writable");
+    }
+
+    let output = spawn(&path, input);
+    fs::remove_file(&path).expect("executable should be removable");
+
+    Outcome {
+        status: output.status.code().expect("program should exit rather than be killed"),
+        stdout: output.stdout,
+        stderr: output.stderr,
+    }
+}
+
+fn spawn(path: &Path, input: &[u8]) -> Output {
+    // Another test's thread may fork while we still have the executable open for writing, in which case the child
+    // holds it open until it execs, and running the executable fails until then
+    let mut attempts = 0;
+
+    let mut child = loop {
+        let spawned = Command::new(path)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn();
+
+        match spawned {
+            Err(ref error) if error.raw_os_error() == Some(ETXTBSY) && attempts < 100 => {
+                attempts += 1;
+                thread::sleep(Duration::from_millis(10));
+            }
+            spawned => break spawned.expect("program should run"),
+        }
+    };
+
+    // The program may exit without reading all of its input
+    let mut stdin = child.stdin.take().unwrap();
+    match stdin.write_all(input) {
+        Err(ref error) if error.kind() == io::ErrorKind::BrokenPipe => (),
+        result => result.expect("input should be writable"),
+    }
+    drop(stdin);
+
+    child.wait_with_output().expect("program should run")
+}
+
+// Run a program in the interpreter, with every optimization disabled so that each command is executed literally,
+// reporting errors and exiting as the executable would
+pub fn interpret(source: &[u8], options: &Options, input: &[u8]) -> Outcome {
+    let options = Options {
+        passes: Passes::level(0),
+        ..options.clone()
+    };
+
+    let mut stdout = vec![];
+
+    let (status, stderr) = match brainrust::run(source, input, &mut stdout, &options) {
+        Ok(()) => (0, vec![]),
+        Err(RunError::Parse(error)) => panic!("program should parse: {}", error),
+        Err(RunError::Options(error)) => panic!("options should be valid: {}", error),
+        Err(error) => {
+            let status = match error {
+                RunError::Write(_) => EXIT_WRITE_ERROR,
+                RunError::Read(_) => EXIT_READ_ERROR,
+                RunError::OutOfBounds(..) => EXIT_OUT_OF_BOUNDS,
+                RunError::OutOfMemory => EXIT_OUT_OF_MEMORY,
+                RunError::Parse(_) | RunError::Options(_) => unreachable!(),
+            };
+
+            (status as i32, format!("error: {}\n", error).into_bytes())
+        }
+    };
+
+    Outcome { status, stdout, stderr }
+}
+
+// Check that at every optimization level, the executable a program compiles to exits with the same status and
+// writes the same output as the interpreter. What's written to standard error isn't compared, since the position
+// reported for an error depends on how commands were combined
+pub fn assert_matches_interpreter(source: &[u8], options: &Options, input: &[u8]) {
+    let expected = interpret(source, options, input);
+
+    for level in 0..=brainrust::options::MAX_OPTIMIZATION_LEVEL {
+        let options = Options {
+            passes: Passes::level(level),
+            ..options.clone()
+        };
+
+        let actual = execute(source, &options, input);
+
+        assert_eq!(
+            (actual.status, actual.stdout_text()),
+            (expected.status, expected.stdout_text()),
+            "-O{} differs from the interpreter for {:?} with {:?}; stderr: {}",
+            level,
+            String::from_utf8_lossy(source),
+            options,
+            actual.stderr_text(),
+        );
+    }
+}
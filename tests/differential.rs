@@ -0,0 +1,145 @@
+// Programs compiled at every optimization level, checked against the interpreter under every combination of tape
+// boundary behavior, cell width and end of input convention
+mod common;
+
+use brainrust::{Boundary, CellWidth, Eof, Options};
+
+use common::assert_matches_interpreter;
+
+const BOUNDARIES: [Boundary; 3] = [Boundary::Wrap, Boundary::Abort, Boundary::Grow];
+const CELL_WIDTHS: [CellWidth; 4] = [
+    CellWidth::Bits8,
+    CellWidth::Bits16,
+    CellWidth::Bits32,
+    CellWidth::Bits64,
+];
+const EOFS: [Eof; 3] = [Eof::Unchanged, Eof::Zero, Eof::MinusOne];
+
+// A short tape, which programs easily run off the end of, and one long enough for all of them
+const TAPE_LENGTHS: [u64; 2] = [8, 300];
+
+const HELLO_WORLD: &str = "++++++++[>++++[>++>+++>+++>+<<<<-]>+>+>->>+[<]<-]>>.>---.+++++++..+++.>>.<-.<.+++.------.\
+                           --------.>>+.>++.";
+
+// Run each program with no input under every combination of boundary behavior, cell width and tape length
+fn assert_all_match(programs: &[&str]) {
+    for &boundary in &BOUNDARIES {
+        for &cell_width in &CELL_WIDTHS {
+            for &tape_length in &TAPE_LENGTHS {
+                let options = Options {
+                    tape_length,
+                    boundary,
+                    cell_width,
+                    ..Options::default()
+                };
+
+                for program in programs {
+                    assert_matches_interpreter(program.as_bytes(), &options, b"");
+                }
+            }
+        }
+    }
+}
+
+#[test]
+fn straight_line() {
+    assert_all_match(&[
+        HELLO_WORLD,
+        "+++++++++++++++++++++++++++++++++++++++++++++++++.+.+.>++++++++++.",
+        "-.--.>---------.<+.",
+        // Wrapping arithmetic at each cell width
+        "-.>--.+++.",
+        "+>++>+++>++++<<<.>.>.>.<<<.",
+    ]);
+}
+
+#[test]
+fn moving_off_the_tape() {
+    assert_all_match(&[
+        "<+.",
+        "+++<<<+.>.>>>.",
+        "++++++++>>>>>>>>+.<<<<<<<<.",
+        ">>>>>>>>>>>>>>>>>>>>>>>>>>>>>>>>>>>>>>>>>>>>>>>>>>>>>>>>>>>>>>>>>>>>>>>>>>>>>>>>>>>>>>>>>>>>>>>>>+.",
+        // Blocks straddling either end of the tape
+        "++++[>>>>>>>>>+<<<<<<<<<-]>>>>>>>>>.",
+        ">>>>>>+>+>+>+>+<<<<<.>.>.>.>.",
+        "+[<<<+>>>-]<<<.",
+    ]);
+}
+
+#[test]
+fn multiply_loops() {
+    assert_all_match(&[
+        "++++[->+++<]>.",
+        ">>+++++[-<<++>+>]<<.>.",
+        "+++++[>++>>>>+++<<<<<-]>.>>>>.",
+        "+++[-<+>]<.",
+        "+++++[->>>>>>>>>>+<<<<<<<<<<]>>>>>>>>>>.",
+        "--[+>+<]>.",
+        "+++[>+++[>++<-]<-]>>.",
+        // Loops which visit cells off the tape without changing them
+        "+++[->>>>>>>>>+-<<<<<<<<<]+.",
+        "+++[-<+->]+.",
+        "+++[->+<<+->]>.",
+    ]);
+}
+
+#[test]
+fn loops_and_scans() {
+    assert_all_match(&[
+        HELLO_WORLD,
+        "+++++[>+++++[>++<-]<-]>>.[-]+.",
+        "+>+>+>+<<<[>]+.",
+        ">>>>+<+<+<+[<]+.",
+        ">>>+>+>+>+>+[>]+.",
+        "+>>+>>+<<<<[>>]+.",
+        "+[>>>>>>>]+.",
+        "+<+<+<+[<<]+.",
+        "+++[>+++++<-]>[>++<[-]]>.",
+    ]);
+}
+
+#[test]
+fn end_of_input() {
+    let programs: [(&str, &[u8]); 4] = [
+        (",.,.,.,.", b"xy"),
+        ("+++,.", b""),
+        (",[.,]", b"hello\0"),
+        (",>,>,<<[->+<]>[->+<]>.", b"\x01\x02\x03"),
+    ];
+
+    for &eof in &EOFS {
+        for &cell_width in &CELL_WIDTHS {
+            let options = Options {
+                cell_width,
+                eof,
+                ..Options::default()
+            };
+
+            for (program, input) in &programs {
+                assert_matches_interpreter(program.as_bytes(), &options, input);
+            }
+        }
+    }
+}
+
+#[test]
+fn buffering() {
+    let options = [
+        Options {
+            input_buffer_size: 1,
+            output_buffer_size: 1,
+            ..Options::default()
+        },
+        Options {
+            output_buffer_size: 3,
+            flush_on_newline: false,
+            ..Options::default()
+        },
+    ];
+
+    for options in &options {
+        assert_matches_interpreter(HELLO_WORLD.as_bytes(), options, b"");
+        assert_matches_interpreter(b",[.,]", options, b"line one\nline two\n\0");
+    }
+}
@@ -0,0 +1,52 @@
+// Programs which grow the tape in the middle of commands whose compiled code holds values in registers
+mod common;
+
+use brainrust::{Boundary, CellWidth, Options};
+
+use common::assert_matches_interpreter;
+
+const CELL_WIDTHS: [CellWidth; 4] = [
+    CellWidth::Bits8,
+    CellWidth::Bits16,
+    CellWidth::Bits32,
+    CellWidth::Bits64,
+];
+
+fn options(cell_width: CellWidth) -> Options {
+    Options {
+        tape_length: 4,
+        boundary: Boundary::Grow,
+        cell_width,
+        ..Options::default()
+    }
+}
+
+#[test]
+fn multiply_loop_growing_tape() {
+    // The second target is past the end of the tape, so the tape grows before it's multiplied into
+    let source = format!("++++++[->>>>>>>>++<<<<<<<<]>>>>>>>>{}.", "+".repeat(48));
+
+    for &cell_width in &CELL_WIDTHS {
+        assert_matches_interpreter(source.as_bytes(), &options(cell_width), b"");
+    }
+}
+
+#[test]
+fn multiply_loop_with_terms_after_growth() {
+    // The tape grows twice, with further targets after each growth
+    let source = "+++++[->+>>>>>++>>>>>>>>>>+++<<<<<<<<<<<<<<<<]>.>>>>>.>>>>>>>>>>.";
+
+    for &cell_width in &CELL_WIDTHS {
+        assert_matches_interpreter(source.as_bytes(), &options(cell_width), b"");
+    }
+}
+
+#[test]
+fn multiply_loop_in_block_growing_tape() {
+    // The multiply loop is part of a block, whose cells don't all fit on the tape until it grows
+    let source = ",[->>>>>>+++>>>>+<<<<<<<<<<]>+>>>>>.>>>>.";
+
+    for &cell_width in &CELL_WIDTHS {
+        assert_matches_interpreter(source.as_bytes(), &options(cell_width), b"\x0b");
+    }
+}
@@ -0,0 +1,32 @@
+// Programs compiled into memory release everything they map, including a tape they've grown
+use std::fs;
+
+use brainrust::{Boundary, CellWidth, Options};
+
+// The virtual memory size of this process, in kilobytes
+fn virtual_memory_size() -> u64 {
+    let status = fs::read_to_string("/proc/self/status").unwrap();
+    let line = status.lines().find(|line| line.starts_with("VmSize:")).unwrap();
+    line.split_whitespace().nth(1).unwrap().parse().unwrap()
+}
+
+#[test]
+fn grown_tape_is_unmapped() {
+    let options = Options {
+        tape_length: 4,
+        boundary: Boundary::Grow,
+        cell_width: CellWidth::Bits64,
+        ..Options::default()
+    };
+
+    // Each run grows the tape to about 800 KB
+    let source = ">".repeat(100_000) + "+";
+    let before = virtual_memory_size();
+
+    for _ in 0..50 {
+        assert_eq!(brainrust::jit(source.as_bytes(), &options).unwrap(), 0);
+    }
+
+    let growth = virtual_memory_size().saturating_sub(before);
+    assert!(growth < 10_000, "virtual memory grew by {} KB", growth);
+}
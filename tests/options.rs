@@ -0,0 +1,69 @@
+// Invalid options are reported as errors by every entry point, rather than causing a panic
+use brainrust::{CompileError, Options, OptionsError, RunError};
+
+use brainrust::options::MAX_BUFFER_SIZE;
+
+fn invalid_options() -> Vec<(Options, OptionsError)> {
+    vec![
+        (
+            Options {
+                tape_length: 0,
+                ..Options::default()
+            },
+            OptionsError::TapeLength,
+        ),
+        (
+            Options {
+                input_buffer_size: 0,
+                ..Options::default()
+            },
+            OptionsError::InputBufferSize(0),
+        ),
+        (
+            Options {
+                output_buffer_size: MAX_BUFFER_SIZE + 1,
+                ..Options::default()
+            },
+            OptionsError::OutputBufferSize(MAX_BUFFER_SIZE + 1),
+        ),
+    ]
+}
+
+#[test]
+fn validate() {
+    assert_eq!(Options::default().validate(), Ok(()));
+
+    for (options, expected) in invalid_options() {
+        assert_eq!(options.validate(), Err(expected));
+    }
+}
+
+#[test]
+fn compile_and_listing() {
+    for (options, expected) in invalid_options() {
+        match brainrust::compile_to_vec(b"+.", &options) {
+            Err(CompileError::Options(error)) => assert_eq!(error, expected),
+            result => panic!("expected an options error, got {:?}", result.map(|_| ())),
+        }
+
+        match brainrust::listing(b"+.", &mut vec![], &options) {
+            Err(CompileError::Options(error)) => assert_eq!(error, expected),
+            result => panic!("expected an options error, got {:?}", result),
+        }
+
+        match brainrust::jit(&b"+."[..], &options) {
+            Err(CompileError::Options(error)) => assert_eq!(error, expected),
+            result => panic!("expected an options error, got {:?}", result),
+        }
+    }
+}
+
+#[test]
+fn run() {
+    for (options, expected) in invalid_options() {
+        match brainrust::run(&b"+."[..], &b""[..], vec![], &options) {
+            Err(RunError::Options(error)) => assert_eq!(error, expected),
+            result => panic!("expected an options error, got {:?}", result),
+        }
+    }
+}
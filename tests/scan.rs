@@ -0,0 +1,72 @@
+// Scans which reach the ends of the tape, where the vectorized search has to hand over to the scalar one
+mod common;
+
+use brainrust::{Boundary, Options};
+
+use common::assert_matches_interpreter;
+
+const BOUNDARIES: [Boundary; 3] = [Boundary::Wrap, Boundary::Abort, Boundary::Grow];
+
+// Tape lengths which are, and aren't, multiples of the 16 cells searched at once; each leaves some zero cells, or a
+// scan around a wrapping tape would never stop
+const TAPE_LENGTHS: [u64; 4] = [17, 32, 33, 48];
+
+// Fill the last 16 cells of the tape with nonzero values and scan right from the first of them, then print the
+// whole tape from wherever the scan stopped
+fn scan_right(tape_length: u64) -> String {
+    let mut source = ">".repeat(tape_length as usize - 16);
+
+    for cell in 1..=16 {
+        source += &"+".repeat(cell);
+        source += if cell < 16 { ">" } else { "" };
+    }
+
+    source += &"<".repeat(15);
+    source += "[>]+";
+    source += &".>".repeat(tape_length as usize);
+    source
+}
+
+// As above, but with the first 16 cells, scanning left from the last of them
+fn scan_left(tape_length: u64) -> String {
+    let mut source = String::new();
+
+    for cell in 1..=16 {
+        source += &"+".repeat(cell);
+        source += if cell < 16 { ">" } else { "" };
+    }
+
+    source += "[<]+";
+    source += &".<".repeat(tape_length as usize);
+    source
+}
+
+#[test]
+fn scan_right_to_end_of_tape() {
+    for &boundary in &BOUNDARIES {
+        for &tape_length in &TAPE_LENGTHS {
+            let options = Options {
+                tape_length,
+                boundary,
+                ..Options::default()
+            };
+
+            assert_matches_interpreter(scan_right(tape_length).as_bytes(), &options, b"");
+        }
+    }
+}
+
+#[test]
+fn scan_left_to_start_of_tape() {
+    for &boundary in &BOUNDARIES {
+        for &tape_length in &TAPE_LENGTHS {
+            let options = Options {
+                tape_length,
+                boundary,
+                ..Options::default()
+            };
+
+            assert_matches_interpreter(scan_left(tape_length).as_bytes(), &options, b"");
+        }
+    }
+}
@@ -0,0 +1,32 @@
+// A failure to write the executable or listing is reported as such, rather than as a failure to read the program
+use std::io;
+
+use brainrust::{CompileError, Options};
+
+struct FailingWriter;
+
+impl io::Write for FailingWriter {
+    fn write(&mut self, _buffer: &[u8]) -> io::Result<usize> {
+        Err(io::Error::other("disk full"))
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[test]
+fn executable() {
+    match brainrust::compile(&b"+."[..], &mut FailingWriter, &Options::default()) {
+        Err(CompileError::Write(error)) => assert_eq!(error.to_string(), "disk full"),
+        result => panic!("expected a write error, got {:?}", result),
+    }
+}
+
+#[test]
+fn listing() {
+    match brainrust::listing(b"+.", &mut FailingWriter, &Options::default()) {
+        Err(CompileError::Write(error)) => assert_eq!(error.to_string(), "disk full"),
+        result => panic!("expected a write error, got {:?}", result),
+    }
+}